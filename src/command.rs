@@ -1,22 +1,137 @@
 pub mod args;
 pub mod auto_encode;
+pub mod batch_images;
+pub mod config;
 pub mod crf_search;
 pub mod encode;
+pub mod encoders;
+pub mod inspect;
 pub mod print_completions;
 pub mod sample_encode;
 pub mod vmaf;
 pub mod xpsnr;
 
 pub use auto_encode::auto_encode;
+pub use batch_images::batch_images;
+pub use config::config;
 pub use crf_search::crf_search;
 pub use encode::encode;
+pub use encoders::encoders;
+pub use inspect::inspect;
 pub use print_completions::print_completions;
 pub use sample_encode::sample_encode;
 pub use vmaf::vmaf;
 pub use xpsnr::xpsnr;
 
+use crate::{
+    command::args::PixelFormat,
+    console_ext::style,
+    ffprobe::Ffprobe,
+    metric::{self, MetricOut, ScoreKind},
+    process::FfmpegOut,
+};
+use indicatif::ProgressBar;
+use std::{path::Path, pin::pin};
+use tokio_stream::StreamExt;
+
 const PROGRESS_CHARS: &str = "##-";
 
+/// Run automatic input analysis, e.g. `--crop auto` & `--film-grain auto`, applying the
+/// results to the args.
+async fn analyse_input(
+    enc: &mut args::Encode,
+    mut score: Option<&mut args::ScoreArgs>,
+    probe: &Ffprobe,
+    bar: &ProgressBar,
+) -> anyhow::Result<()> {
+    // before other filters are prepended so fps conversion runs after cropping & deinterlacing
+    if let Some(filter) = enc.apply_force_cfr(probe)? {
+        if let Some(score) = score.as_deref_mut() {
+            score.prepend_reference_vfilter(&filter);
+        }
+        bar.println(style!("Constant frame rate {filter}").dim().to_string());
+    } else if probe.vfr {
+        bar.println(
+            style!("Variable frame rate input, consider --force-cfr")
+                .dim()
+                .to_string(),
+        );
+    }
+    if enc.crop == args::CropMode::Auto {
+        bar.set_message("cropdetect, ");
+        match enc.detect_crop(probe).await? {
+            Some(crop) => {
+                if let Some(score) = score.as_deref_mut() {
+                    score.prepend_reference_vfilter(&crop.vfilter());
+                }
+                bar.println(style!("Cropping {crop}").dim().to_string());
+            }
+            None => bar.println(style!("Cropping none").dim().to_string()),
+        }
+    }
+    if enc.deinterlace == args::DeinterlaceMode::Auto {
+        bar.set_message("idet, ");
+        if let Some(scan) = enc.detect_deinterlace(probe).await? {
+            if let (Some(score), Some(filter)) = (score.as_deref_mut(), scan.vfilter()) {
+                score.prepend_reference_vfilter(filter);
+            }
+            bar.println(style!("Scan {scan}").dim().to_string());
+        }
+    }
+    if enc.film_grain == args::FilmGrainMode::Auto {
+        bar.set_message("noise estimation, ");
+        match enc.detect_film_grain(probe).await? {
+            Some(level) if level > 0 => {
+                if let Some(score) = score {
+                    score.grain_synthesis = true;
+                }
+                bar.println(style!("Film grain {level}").dim().to_string());
+            }
+            _ => bar.println(style!("Film grain none").dim().to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Calculate additional `--metric` scores of `distorted` vs `reference`, printing each to stderr.
+async fn print_metrics(
+    kinds: &[ScoreKind],
+    reference: &Path,
+    distorted: &Path,
+    score: &args::ScoreArgs,
+    pix_fmt: Option<PixelFormat>,
+    bar: &ProgressBar,
+) -> anyhow::Result<()> {
+    for kind in kinds.iter().copied() {
+        bar.set_message(format!("{} running, ", kind.fps_label()));
+        let mut out = pin!(metric::run(
+            kind,
+            reference,
+            None,
+            distorted,
+            (0, 0),
+            score.ref_vfilter(None).as_deref(),
+            pix_fmt,
+            score.grain_synthesis,
+            None,
+            None,
+        )?);
+        while let Some(next) = out.next().await {
+            match next {
+                MetricOut::Done(s) => {
+                    bar.println(format!("{kind} {s:.*}", kind.decimals()));
+                }
+                MetricOut::Progress(FfmpegOut::Progress { fps, .. }) if fps > 0.0 => {
+                    bar.set_message(format!("{} {fps} fps, ", kind.fps_label()));
+                }
+                MetricOut::Progress(_) => {}
+                MetricOut::Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Helper trait for durations under 584942 years or so.
 trait SmallDuration {
     /// Returns the total number of whole microseconds.
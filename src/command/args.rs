@@ -5,9 +5,13 @@ mod vmaf;
 pub use encode::*;
 pub use vmaf::*;
 
-use crate::{command::encode::default_output_ext, ffprobe::Ffprobe};
+use crate::{
+    command::encode::default_output_ext, ffprobe::Ffprobe, float::TerseF32, noise::DENOISE_VFILTER,
+};
 use clap::{Parser, ValueHint};
 use std::{
+    borrow::Cow,
+    fmt::Write,
     path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
@@ -36,6 +40,48 @@ pub struct EncodeToOutput {
     #[arg(long)]
     pub downmix_to_stereo: bool,
 
+    /// Transcode audio streams using this codec to Opus, other audio streams use
+    /// --acodec as normal. E.g. `--transcode-audio truehd --transcode-audio dts-hd`.
+    ///
+    /// Values are ffprobe codec names, additionally "dts-hd" matches DTS-HD MA & HRA.
+    /// The Opus bitrate is scaled by the channel count, see --opus-channel-bitrate.
+    #[arg(long)]
+    pub transcode_audio: Vec<String>,
+
+    /// Opus bitrate per channel for --transcode-audio & --stereo-compat streams,
+    /// e.g. 5.1 audio at "64k" is encoded at 384k, or 128k with --downmix-to-stereo.
+    #[arg(long, default_value = "64k", value_parser = parse_opus_channel_kbps)]
+    pub opus_channel_bitrate: u32,
+
+    /// Add a stereo Opus audio stream downmixed from the first audio stream
+    /// with more than 2 channels.
+    #[arg(long)]
+    pub stereo_compat: bool,
+
+    /// EBU R128 normalise the loudness of --transcode-audio & --stereo-compat streams
+    /// using a two-pass ffmpeg `loudnorm`. The first pass decodes each source stream.
+    #[arg(long)]
+    pub loudnorm: bool,
+
+    /// Only keep audio & subtitle streams in these languages, e.g. `--languages eng,jpn`.
+    /// Streams without a language are kept, as are forced subtitles.
+    ///
+    /// The first kept audio stream is set as default. If no audio stream matches
+    /// the first non-commentary audio stream is kept.
+    #[arg(long, value_delimiter = ',')]
+    pub languages: Vec<String>,
+
+    /// Drop audio & subtitle commentary streams, identified by disposition or title.
+    #[arg(long)]
+    pub drop_commentary: bool,
+
+    /// Encode secondary video streams, e.g. multi-angle, with the same encoder at this crf.
+    /// By default secondary video streams are copied.
+    ///
+    /// Attached pictures, e.g. cover art, are always copied.
+    #[arg(long)]
+    pub secondary_video_crf: Option<f32>,
+
     /// Only process the main video stream, drop all other streams.
     ///
     /// The output will be a single video stream.
@@ -67,6 +113,11 @@ pub struct EncodeToOutput {
     #[arg(long)]
     pub verify_duration: bool,
 
+    /// Verify the encoded result has the expected number of streams & the same
+    /// number of chapters as the input.
+    #[arg(long)]
+    pub verify_streams: bool,
+
     /// Stop the encode as soon as ffmpeg reports an error, instead of finishing with
     /// a damaged result. Maps to ffmpeg `-xerror`.
     ///
@@ -77,6 +128,78 @@ pub struct EncodeToOutput {
     pub fail_fast: bool,
 }
 
+impl EncodeToOutput {
+    /// Returns the args, to follow an [`Encode::encode_hint`], that affect the encoded result.
+    ///
+    /// Excludes the output path & checks like --verify.
+    pub fn encode_hint_args(&self) -> String {
+        let Self {
+            output: _,
+            audio_codec,
+            downmix_to_stereo,
+            transcode_audio,
+            opus_channel_bitrate,
+            stereo_compat,
+            loudnorm,
+            languages,
+            drop_commentary,
+            secondary_video_crf,
+            video_only,
+            overwrite_input: _,
+            verify: _,
+            verify_decode: _,
+            verify_duration: _,
+            verify_streams: _,
+            fail_fast: _,
+        } = self;
+
+        let mut hint = String::new();
+        if let Some(acodec) = audio_codec {
+            write!(hint, " --acodec {acodec}").unwrap();
+        }
+        if *downmix_to_stereo {
+            hint.push_str(" --downmix-to-stereo");
+        }
+        for codec in transcode_audio {
+            write!(hint, " --transcode-audio {codec}").unwrap();
+        }
+        if *opus_channel_bitrate != DEFAULT_OPUS_CHANNEL_KBPS {
+            write!(hint, " --opus-channel-bitrate {opus_channel_bitrate}k").unwrap();
+        }
+        if *stereo_compat {
+            hint.push_str(" --stereo-compat");
+        }
+        if *loudnorm {
+            hint.push_str(" --loudnorm");
+        }
+        if !languages.is_empty() {
+            write!(hint, " --languages {}", languages.join(",")).unwrap();
+        }
+        if *drop_commentary {
+            hint.push_str(" --drop-commentary");
+        }
+        if let Some(crf) = secondary_video_crf {
+            write!(hint, " --secondary-video-crf {}", TerseF32(*crf)).unwrap();
+        }
+        if *video_only {
+            hint.push_str(" --video-only");
+        }
+        hint
+    }
+}
+
+const DEFAULT_OPUS_CHANNEL_KBPS: u32 = 64;
+
+fn parse_opus_channel_kbps(arg: &str) -> anyhow::Result<u32> {
+    let kbps: u32 = arg
+        .strip_suffix('k')
+        .unwrap_or(arg)
+        .parse()
+        .map_err(|_| anyhow::anyhow!("expected kbps, e.g. 64k"))?;
+    anyhow::ensure!((6..=256).contains(&kbps), "must be between 6k & 256k");
+    Ok(kbps)
+}
+
 /// Sampling arguments.
 #[derive(Parser, Clone)]
 pub struct Sample {
@@ -101,6 +224,15 @@ pub struct Sample {
     #[arg(long, default_value = "20s", value_parser = humantime::parse_duration)]
     pub sample_duration: Duration,
 
+    /// How samples are extracted from the input.
+    ///
+    /// `copy` stream copies, which is fast but starts at the keyframe before the sample start.
+    /// `lossless` re-encodes with ffv1, cutting exactly at the sample start.
+    /// `auto` copies unless the keyframe before the sample start is further away than
+    /// --sample-duration, or the copy fails.
+    #[arg(long, value_enum, default_value_t = SampleExtract::Auto)]
+    pub sample_extract: SampleExtract,
+
     /// Keep temporary files after exiting.
     #[arg(long)]
     pub keep: bool,
@@ -115,6 +247,15 @@ pub struct Sample {
     pub extension: Option<Arc<str>>,
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[clap(rename_all = "lower")]
+pub enum SampleExtract {
+    #[default]
+    Auto,
+    Copy,
+    Lossless,
+}
+
 impl Sample {
     /// Calculate the desired sample count using `samples` or `sample_every` & `min_samples`.
     pub fn sample_count(&self, input_duration: Duration) -> u64 {
@@ -130,7 +271,7 @@ impl Sample {
     }
 
     pub fn set_extension_from_input(&mut self, input: &Path, encoder: &Encoder, probe: &Ffprobe) {
-        self.extension = Some(default_output_ext(input, encoder, probe.is_image).into());
+        self.extension = Some(default_output_ext(input, encoder, probe).into());
     }
 
     pub fn set_extension_from_output(&mut self, output: &Path) {
@@ -147,6 +288,33 @@ pub struct ScoreArgs {
     /// Overrides --vfilter which would otherwise be used.
     #[arg(long)]
     pub reference_vfilter: Option<Arc<str>>,
+
+    /// Score an encode using grain synthesis, i.e. ignore film grain when decoding the
+    /// distorted & denoise the reference. Set by `--film-grain auto`.
+    #[arg(skip)]
+    pub grain_synthesis: bool,
+}
+
+impl ScoreArgs {
+    /// Add a detected filter, e.g. crop, to the start of --reference-vfilter, if set.
+    ///
+    /// Otherwise the reference uses --vfilter which should already include it.
+    pub fn prepend_reference_vfilter(&mut self, filter: &str) {
+        if let Some(vf) = &self.reference_vfilter {
+            self.reference_vfilter = Some(prepend_vfilter(filter, Some(vf)).into());
+        }
+    }
+
+    /// Returns the reference vfilter, --reference-vfilter or otherwise `vfilter`,
+    /// followed by the denoiser if scoring grain synthesis.
+    pub fn ref_vfilter<'a>(&'a self, vfilter: Option<&'a str>) -> Option<Cow<'a, str>> {
+        let vf = self.reference_vfilter.as_deref().or(vfilter);
+        match (vf, self.grain_synthesis) {
+            (vf, false) => vf.map(Cow::Borrowed),
+            (Some(vf), true) => Some(format!("{vf},{DENOISE_VFILTER}").into()),
+            (None, true) => Some(DENOISE_VFILTER.into()),
+        }
+    }
 }
 
 /// Common xpsnr options.
@@ -175,3 +343,30 @@ impl std::hash::Hash for Xpsnr {
         self.xpsnr_fps.to_ne_bytes().hash(state);
     }
 }
+
+#[test]
+fn encode_to_output_hint_args() {
+    let args = EncodeToOutput::try_parse_from([
+        "encode",
+        "-o",
+        "out.mkv",
+        "--transcode-audio",
+        "truehd",
+        "--opus-channel-bitrate",
+        "80k",
+        "--languages",
+        "eng,jpn",
+        "--secondary-video-crf",
+        "40.5",
+        "--verify",
+    ])
+    .unwrap();
+    assert_eq!(
+        args.encode_hint_args(),
+        " --transcode-audio truehd --opus-channel-bitrate 80k --languages eng,jpn \
+         --secondary-video-crf 40.5"
+    );
+
+    let defaults = EncodeToOutput::try_parse_from(["encode"]).unwrap();
+    assert_eq!(defaults.encode_hint_args(), "");
+}
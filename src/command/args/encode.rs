@@ -1,9 +1,14 @@
 use crate::{
+    cropdetect::{self, Crop},
+    encoder::{self, Backend, EncoderProfile},
     ffmpeg::FfmpegEncodeArgs,
     ffprobe::{Ffprobe, ProbeError},
     float::TerseF32,
+    idet::{self, Scan},
+    native::{self, Native, NativeEncoder},
+    noise, sequence,
 };
-use anyhow::ensure;
+use anyhow::{Context, ensure};
 use clap::{Parser, ValueHint};
 use std::{
     collections::HashMap,
@@ -23,9 +28,17 @@ pub struct Encode {
     pub encoder: Encoder,
 
     /// Input video file.
-    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    ///
+    /// May also be an image sequence, either a numbered pattern like `frame_%05d.png`
+    /// or a directory of numbered frames, e.g. EXR/TIFF. These require --fps.
+    #[arg(short, long, value_hint = ValueHint::AnyPath)]
     pub input: PathBuf,
 
+    /// Frame rate of image sequence inputs, e.g. `-i frames/ --fps 24`.
+    /// Not supported by other inputs, which use their own frame rate.
+    #[arg(long, value_parser = parse_fps)]
+    pub fps: Option<f64>,
+
     /// Ffmpeg video filter applied to the input before encoding.
     /// E.g. --vfilter "scale=1280:-1,fps=24".
     ///
@@ -44,6 +57,29 @@ pub struct Encode {
     #[arg(long)]
     pub vfilter: Option<String>,
 
+    /// Crop black bars. `auto` runs a fast ffmpeg cropdetect pass across several
+    /// points of the input & adds the detected crop to the start of --vfilter.
+    ///
+    /// So as with --vfilter the crop also applies to the VMAF/XPSNR reference.
+    #[arg(long, value_enum, default_value_t = CropMode::None)]
+    pub crop: CropMode,
+
+    /// Deinterlace. `auto` runs a fast ffmpeg idet pass across several points of the
+    /// input & if interlaced adds `bwdif` to the start of --vfilter, or if telecined
+    /// adds `fieldmatch,decimate`.
+    ///
+    /// So as with --vfilter this also applies to the VMAF/XPSNR reference.
+    #[arg(long, value_enum, default_value_t = DeinterlaceMode::None)]
+    pub deinterlace: DeinterlaceMode,
+
+    /// Convert to constant frame rate at the input average fps, adding `fps` to the
+    /// start of --vfilter. Useful for variable frame rate inputs, e.g. phone recordings
+    /// & screen captures.
+    ///
+    /// So as with --vfilter this also applies to the VMAF/XPSNR reference.
+    #[arg(long)]
+    pub force_cfr: bool,
+
     /// Pixel format. libsvtav1, libaom-av1 & librav1e default to yuv420p10le.
     #[arg(value_enum, long)]
     pub pix_format: Option<PixelFormat>,
@@ -79,6 +115,14 @@ pub struct Encode {
     #[arg(long = "svt", value_parser = parse_svt_arg)]
     pub svt_args: Vec<Arc<str>>,
 
+    /// svt-av1 film grain synthesis. `auto` estimates the source noise across several
+    /// points of the input & chooses a film-grain level, adding it as `--svt film-grain=N`.
+    ///
+    /// When grain is added VMAF/XPSNR scoring compares the encode, decoded without
+    /// synthesized grain, to a denoised reference so the grain is not penalised.
+    #[arg(long, value_enum, default_value_t = FilmGrainMode::None)]
+    pub film_grain: FilmGrainMode,
+
     /// Additional ffmpeg encoder arg(s). E.g. `--enc x265-params=lossless=1`
     /// These are added as ffmpeg output file options.
     ///
@@ -101,6 +145,26 @@ pub struct Encode {
     /// e.g. `-enc-input hwaccel=none --enc-input hwaccel_output_format=none`
     #[arg(long = "enc-input", allow_hyphen_values = true, value_parser = parse_enc_arg)]
     pub enc_input_args: Vec<String>,
+
+    /// Encode backend. `native` pipes ffmpeg decoded y4m into the standalone encoder
+    /// binary, i.e. SvtAv1EncApp, aomenc or rav1e, then muxes the result with ffmpeg.
+    /// This allows using encoder features not yet exposed by the ffmpeg wrappers.
+    ///
+    /// Supported by libsvtav1, libaom-av1 & librav1e. Defaults to the config
+    /// `[encoder.NAME] backend`, otherwise ffmpeg.
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// Additional native encoder binary arg(s), requires `--backend native`.
+    /// E.g. `--native-arg=--tune --native-arg=0`.
+    ///
+    /// With svt-av1 `--svt` args are also passed, e.g. `--svt tune=0` -> `--tune 0`.
+    #[arg(long = "native-arg", allow_hyphen_values = true)]
+    pub native_args: Vec<String>,
+
+    /// Native encoder binary version, see [`Self::read_native_version`].
+    #[arg(skip)]
+    pub native_version: Option<Arc<str>>,
 }
 
 fn parse_svt_arg(arg: &str) -> anyhow::Result<Arc<str>> {
@@ -113,6 +177,15 @@ fn parse_svt_arg(arg: &str) -> anyhow::Result<Arc<str>> {
     Ok(arg.into())
 }
 
+fn parse_fps(arg: &str) -> anyhow::Result<f64> {
+    let fps = match arg.split_once('/') {
+        Some((n, d)) => n.trim().parse::<f64>()? / d.trim().parse::<f64>()?,
+        None => arg.parse()?,
+    };
+    ensure!(fps.is_finite() && fps > 0.0, "fps must be positive");
+    Ok(fps)
+}
+
 fn parse_enc_arg(arg: &str) -> anyhow::Result<String> {
     let mut arg = arg.to_owned();
     if !arg.starts_with('-') {
@@ -132,14 +205,22 @@ impl Encode {
         let Self {
             encoder,
             input,
+            fps,
             vfilter,
+            crop,
+            deinterlace,
+            force_cfr,
             preset,
             pix_format,
             keyint,
             scd,
             svt_args,
+            film_grain,
             enc_args,
             enc_input_args,
+            backend,
+            native_args,
+            native_version: _,
         } = self;
 
         let input = shell_escape::escape(input.display().to_string().into());
@@ -152,6 +233,9 @@ impl Encode {
         }
         write!(hint, " -i {input} --crf {}", TerseF32(crf)).unwrap();
 
+        if let Some(fps) = fps {
+            write!(hint, " --fps {fps}").unwrap();
+        }
         if let Some(preset) = preset {
             write!(hint, " --preset {preset}").unwrap();
         }
@@ -167,9 +251,21 @@ impl Encode {
         if let Some(filter) = vfilter {
             write!(hint, " --vfilter {filter:?}").unwrap();
         }
+        if *crop == CropMode::Auto {
+            write!(hint, " --crop auto").unwrap();
+        }
+        if *deinterlace == DeinterlaceMode::Auto {
+            write!(hint, " --deinterlace auto").unwrap();
+        }
+        if *force_cfr {
+            write!(hint, " --force-cfr").unwrap();
+        }
         for arg in svt_args {
             write!(hint, " --svt {arg}").unwrap();
         }
+        if *film_grain == FilmGrainMode::Auto {
+            write!(hint, " --film-grain auto").unwrap();
+        }
         for arg in enc_input_args {
             let arg = arg.trim_start_matches('-');
             write!(hint, " --enc-input {arg}").unwrap();
@@ -178,10 +274,91 @@ impl Encode {
             let arg = arg.trim_start_matches('-');
             write!(hint, " --enc {arg}").unwrap();
         }
+        if let Some(Backend::Native) = backend {
+            write!(hint, " --backend native").unwrap();
+        }
+        for arg in native_args {
+            write!(hint, " --native-arg={arg}").unwrap();
+        }
 
         hint
     }
 
+    /// Run `--crop auto` detection, if enabled, adding the detected crop to `vfilter`.
+    ///
+    /// Afterwards `crop` is unset so the detection only happens once.
+    pub async fn detect_crop(&mut self, probe: &Ffprobe) -> anyhow::Result<Option<Crop>> {
+        if self.crop != CropMode::Auto {
+            return Ok(None);
+        }
+        self.crop = CropMode::None;
+
+        let crop = cropdetect::detect(&self.input, self.fps, probe).await?;
+        if let Some(crop) = crop {
+            self.vfilter = Some(prepend_vfilter(&crop.vfilter(), self.vfilter.as_deref()));
+        }
+        Ok(crop)
+    }
+
+    /// Run `--deinterlace auto` detection, if enabled, adding any required filter
+    /// to the start of `vfilter`.
+    ///
+    /// Afterwards `deinterlace` is unset so the detection only happens once.
+    pub async fn detect_deinterlace(&mut self, probe: &Ffprobe) -> anyhow::Result<Option<Scan>> {
+        if self.deinterlace != DeinterlaceMode::Auto {
+            return Ok(None);
+        }
+        self.deinterlace = DeinterlaceMode::None;
+
+        let scan = idet::detect(&self.input, self.fps, probe).await?;
+        if let Some(filter) = scan.vfilter() {
+            self.vfilter = Some(prepend_vfilter(filter, self.vfilter.as_deref()));
+        }
+        Ok(Some(scan))
+    }
+
+    /// Apply `--force-cfr`, if enabled, adding an `fps` filter at the input average
+    /// frame rate to the start of `vfilter`. Returns the added filter.
+    ///
+    /// Afterwards `force_cfr` is unset so the filter is only added once.
+    pub fn apply_force_cfr(&mut self, probe: &Ffprobe) -> anyhow::Result<Option<String>> {
+        if !self.force_cfr {
+            return Ok(None);
+        }
+        self.force_cfr = false;
+
+        let fps = probe.fps.clone()?;
+        let filter = format!("fps={}", (fps * 1000.0).round() / 1000.0);
+        self.vfilter = Some(prepend_vfilter(&filter, self.vfilter.as_deref()));
+        Ok(Some(filter))
+    }
+
+    /// Run `--film-grain auto` estimation, if enabled, adding the chosen level to `svt_args`.
+    /// Returns the chosen level, 0 meaning no grain is added.
+    ///
+    /// Afterwards `film_grain` is unset so the estimation only happens once.
+    pub async fn detect_film_grain(&mut self, probe: &Ffprobe) -> anyhow::Result<Option<u8>> {
+        if self.film_grain != FilmGrainMode::Auto {
+            return Ok(None);
+        }
+        ensure!(
+            self.encoder.as_str() == "libsvtav1",
+            "--film-grain may only be used with svt-av1"
+        );
+        ensure!(
+            !self.svt_args.iter().any(|a| a.starts_with("film-grain=")),
+            "--film-grain auto cannot be used with --svt film-grain"
+        );
+        self.film_grain = FilmGrainMode::None;
+
+        let noise = noise::estimate(&self.input, self.fps, probe, self.vfilter.as_deref()).await?;
+        let level = noise::film_grain_level(noise);
+        if level > 0 {
+            self.svt_args.push(format!("film-grain={level}").into());
+        }
+        Ok(Some(level))
+    }
+
     pub fn to_ffmpeg_args(
         &self,
         crf: f32,
@@ -195,21 +372,24 @@ impl Encode {
             "--svt may only be used with svt-av1"
         );
 
-        let preset = match &self.preset {
-            Some(n) => Some(n.clone()),
-            None if svtav1 => Some("8".into()),
-            None => None,
-        };
+        let preset = self.preset();
 
         let keyint = self.keyint(probe)?;
 
+        let native = match self.backend() {
+            Backend::Native => Some(self.native(probe, keyint)?),
+            Backend::Ffmpeg => {
+                ensure!(
+                    self.native_args.is_empty(),
+                    "--native-arg may only be used with --backend native"
+                );
+                None
+            }
+        };
+
         let mut svtav1_params = vec![];
         if svtav1 {
-            let scd = match (self.scd, self.keyint, keyint) {
-                (Some(true), ..) | (_, None, Some(_)) => 1,
-                _ => 0,
-            };
-            svtav1_params.push(format!("scd={scd}"));
+            svtav1_params.push(format!("scd={}", self.scd(keyint)));
             // include crf in svtav1-params to support quarter-steps
             svtav1_params.push(format!("crf={crf}"));
             // add all --svt args
@@ -253,10 +433,16 @@ impl Encode {
             }
         }
 
-        let pix_fmt = self.pix_format.or_else(|| match &**vcodec {
-            "libsvtav1" | "libaom-av1" | "librav1e" => Some(PixelFormat::Yuv420p10le),
-            _ => None,
-        });
+        if native.is_some() {
+            ensure!(
+                self.enc_args.is_empty(),
+                "--enc may only be used with --backend ffmpeg, use --native-arg"
+            );
+            // video encoder args are passed to the native encoder instead
+            args.clear();
+        }
+
+        let pix_fmt = self.pix_fmt();
 
         let mut input_args: Vec<Arc<String>> = self
             .enc_input_args
@@ -325,6 +511,7 @@ impl Encode {
 
         Ok(FfmpegEncodeArgs {
             input: &self.input,
+            input_fps: self.fps,
             vcodec: Arc::clone(vcodec),
             pix_fmt,
             vfilter: self.vfilter.as_deref(),
@@ -333,14 +520,123 @@ impl Encode {
             output_args: args,
             input_args,
             video_only: false,
+            video_stream: probe.video_stream,
+            native,
         })
     }
 
+    /// Returns `--backend` or the encoder default.
+    pub fn backend(&self) -> Backend {
+        self.backend.unwrap_or(self.encoder.profile().backend)
+    }
+
+    /// Svt-av1 scene change detection value.
+    fn scd(&self, keyint: Option<i32>) -> u8 {
+        match (self.scd, self.keyint, keyint) {
+            (Some(true), ..) | (_, None, Some(_)) => 1,
+            _ => 0,
+        }
+    }
+
+    /// Native encoder binary & args for `--backend native`.
+    fn native(&self, probe: &Ffprobe, keyint: Option<i32>) -> anyhow::Result<Native> {
+        let profile = self.encoder.profile();
+        let encoder = profile.native.with_context(|| {
+            format!(
+                "--backend native is not supported by {}",
+                self.encoder.as_str()
+            )
+        })?;
+
+        let mut args = vec![];
+        if let Some(keyint) = keyint {
+            args.extend(encoder.keyint_args(keyint));
+        }
+        if encoder == NativeEncoder::SvtAv1 {
+            args.extend(["--scd".into(), self.scd(keyint).to_string()]);
+            for arg in &self.svt_args {
+                let (name, val) = arg.split_once('=').unwrap_or((arg, "1"));
+                args.extend([format!("--{name}"), val.to_owned()]);
+            }
+        }
+        args.extend(self.native_args.iter().cloned());
+
+        let input_fps = probe.fps.clone().ok();
+        let fps = self
+            .vfilter
+            .as_deref()
+            .and_then(|vf| try_parse_fps_vfilter(vf, input_fps))
+            .or(input_fps)
+            .context("--backend native requires a known input frame rate")?;
+
+        Ok(Native {
+            encoder,
+            bin: profile.native_bin.unwrap_or(encoder.bin()).into(),
+            args: args.into_iter().map(Arc::new).collect(),
+            fps,
+            version: self.native_version.clone().unwrap_or_default(),
+        })
+    }
+
+    /// Read the `--backend native` encoder binary version, if not already read.
+    pub async fn read_native_version(&mut self) {
+        let profile = self.encoder.profile();
+        if self.backend() == Backend::Native
+            && self.native_version.is_none()
+            && let Some(encoder) = profile.native
+        {
+            let bin = profile.native_bin.unwrap_or(encoder.bin());
+            self.native_version = Some(native::read_version(encoder, bin).await.into());
+        }
+    }
+
+    /// Returns `--preset` or the encoder default.
+    pub fn preset(&self) -> Option<Arc<str>> {
+        self.preset
+            .clone()
+            .or_else(|| self.encoder.profile().preset.map(Into::into))
+    }
+
+    /// Returns `--pix-format` or the encoder default.
+    pub fn pix_fmt(&self) -> Option<PixelFormat> {
+        self.pix_format.or(self.encoder.profile().pix_fmt)
+    }
+
+    /// Check `--fps` is only used with image sequence inputs, as it would be ignored.
+    pub fn validate_fps(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.fps.is_none() || sequence::is_sequence(&self.input),
+            "--fps is only supported for image sequence inputs"
+        );
+        Ok(())
+    }
+
+    /// Check ffmpeg supports the encoder, pixel format & preset, and `crfs`.
+    ///
+    /// Skipped with `--backend native` as ffmpeg doesn't do the encoding,
+    /// reading the encoder binary version instead.
+    pub async fn validate_encoder(&mut self, crfs: &[f32]) -> anyhow::Result<()> {
+        if self.backend() == Backend::Native {
+            self.read_native_version().await;
+            return Ok(());
+        }
+        encoder::validate(
+            self.encoder.as_str(),
+            self.pix_fmt(),
+            self.preset().as_deref(),
+            crfs,
+        )
+        .await
+    }
+
     fn keyint(&self, probe: &Ffprobe) -> anyhow::Result<Option<i32>> {
         const KEYINT_DEFAULT_INPUT_MIN: Duration = Duration::from_secs(60 * 3);
         const KEYINT_DEFAULT: Duration = Duration::from_secs(10);
 
-        let filter_fps = self.vfilter.as_deref().and_then(try_parse_fps_vfilter);
+        let filter_fps = self
+            .vfilter
+            .as_deref()
+            .and_then(|vf| try_parse_fps_vfilter(vf, probe.fps.clone().ok()));
         Ok(
             match (self.keyint, &probe.duration, &probe.fps, filter_fps) {
                 // use the filter-fps if used, otherwise the input fps
@@ -358,6 +654,14 @@ impl Encode {
     }
 }
 
+/// Returns `filter` followed by the `vfilter` chain, if any.
+pub fn prepend_vfilter(filter: &str, vfilter: Option<&str>) -> String {
+    match vfilter {
+        Some(vf) if !vf.trim().is_empty() => format!("{filter},{vf}"),
+        _ => filter.to_owned(),
+    }
+}
+
 /// Video codec for encoding.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Encoder(Arc<str>);
@@ -368,84 +672,49 @@ impl Encoder {
         &self.0
     }
 
+    /// Returns the encoder profile, defining encoder specific behaviour & defaults.
+    pub fn profile(&self) -> &'static EncoderProfile {
+        EncoderProfile::get(self.as_str())
+    }
+
     /// Returns:
     /// * `true`: Higher crf values mean higher quality.
     /// * `false`: Higher crf values mean lower quality.
     pub fn high_crf_means_hq(&self) -> bool {
-        self.as_str() == "hevc_videotoolbox"
+        self.profile().high_crf_means_hq
     }
 
     /// Returns default crf-increment.
     ///
     /// Generally 0.1 if codec supports decimal crf.
     pub fn default_crf_increment(&self) -> f32 {
-        match self.as_str() {
-            "libx264" | "libx265" => 0.1,
-            "libsvtav1" => 0.25,
-            _ => 1.0,
-        }
+        self.profile().crf_increment
     }
 
     pub fn default_min_crf(&self) -> f32 {
-        match self.as_str() {
-            "mpeg2video" => 2.0,
-            "libsvtav1" => 5.0,
-            _ => 10.0,
-        }
+        self.profile().min_crf
     }
 
     pub fn default_max_crf(&self) -> f32 {
-        match self.as_str() {
-            "librav1e" | "av1_vaapi" => 255.0,
-            "libx264" | "libx265" => 46.0,
-            "mpeg2video" => 30.0,
-            "hevc_videotoolbox" => 100.0,
-            "libsvtav1" => 70.0,
-            _ => 55.0,
-        }
+        self.profile().max_crf
     }
 
     pub fn default_image_ext(&self) -> &'static str {
-        match self.as_str() {
-            // ffmpeg doesn't currently have good heif support,
-            // these raw formats allow crf-search to work
-            "libx264" => "264",
-            "libx265" => "265",
-            // otherwise assume av1
-            _ => "avif",
-        }
+        self.profile().image_ext
     }
 
     /// Additional encoder specific ffmpeg output arg defaults.
-    fn default_ffmpeg_args(&self, ext: &str) -> &[(&'static str, &'static str)] {
-        match self.as_str() {
-            // add `-b:v 0` for aom & vp9 to use "constant quality" mode
-            "libaom-av1" | "libvpx-vp9" => &[("-b:v", "0")],
-            // apple compat for hevc
-            "libx265" if ext.eq_ignore_ascii_case("mp4") || ext.eq_ignore_ascii_case("mov") => {
-                &[("-tag:v", "hvc1")]
-            }
-            // enable lookahead mode for qsv encoders
-            "av1_qsv" | "hevc_qsv" | "h264_qsv" => &[
-                ("-look_ahead", "1"),
-                ("-extbrc", "1"),
-                ("-look_ahead_depth", "40"),
-            ],
-            _ => &[],
+    fn default_ffmpeg_args(&self, ext: &str) -> &'static [(&'static str, &'static str)] {
+        let profile = self.profile();
+        match ext.eq_ignore_ascii_case("mp4") || ext.eq_ignore_ascii_case("mov") {
+            true if !profile.mp4_output_args.is_empty() => profile.mp4_output_args,
+            _ => profile.output_args,
         }
     }
 
     /// Additional encoder specific ffmpeg input arg defaults.
-    fn default_ffmpeg_input_args(&self) -> &[(&'static str, &'static str)] {
-        match self.as_str() {
-            e if e.ends_with("_vaapi") => {
-                &[("-hwaccel", "vaapi"), ("-hwaccel_output_format", "vaapi")]
-            }
-            e if e.ends_with("_vulkan") => {
-                &[("-hwaccel", "vulkan"), ("-hwaccel_output_format", "vulkan")]
-            }
-            _ => <_>::default(),
-        }
+    fn default_ffmpeg_input_args(&self) -> &'static [(&'static str, &'static str)] {
+        self.profile().input_args
     }
 }
 
@@ -501,6 +770,30 @@ impl std::str::FromStr for KeyInterval {
     }
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[clap(rename_all = "lower")]
+pub enum CropMode {
+    #[default]
+    None,
+    Auto,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[clap(rename_all = "lower")]
+pub enum DeinterlaceMode {
+    #[default]
+    None,
+    Auto,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[clap(rename_all = "lower")]
+pub enum FilmGrainMode {
+    #[default]
+    None,
+    Auto,
+}
+
 /// Ordered by ascending quality.
 #[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[clap(rename_all = "lower")]
@@ -560,28 +853,95 @@ impl TryFrom<&str> for PixelFormat {
     }
 }
 
-fn try_parse_fps_vfilter(vfilter: &str) -> Option<f64> {
-    let fps_filter = vfilter
-        .split(',')
-        .find_map(|vf| vf.trim().strip_prefix("fps="))?
-        .trim();
-
-    match fps_filter {
-        "ntsc" => Some(30000.0 / 1001.0),
-        "pal" => Some(25.0),
-        "film" => Some(24.0),
-        "ntsc_film" => Some(24000.0 / 1001.0),
-        _ => crate::ffprobe::parse_frame_rate(fps_filter),
+/// Returns the output frame rate of `vfilter` if it contains filters that change it.
+///
+/// Handles `fps`, `decimate` & field rate `bwdif`/`yadif`. The latter are relative
+/// so require the `input_fps`.
+fn try_parse_fps_vfilter(vfilter: &str, input_fps: Option<f64>) -> Option<f64> {
+    let mut fps = input_fps;
+    let mut changed = false;
+    for vf in vfilter.split(',').map(str::trim) {
+        let (name, opts) = vf.split_once('=').unwrap_or((vf, ""));
+        // first option, which may be named or positional
+        let first_opt = |opt: &str| {
+            let first = opts.split(':').next().unwrap_or_default().trim();
+            first
+                .strip_prefix(opt)
+                .and_then(|f| f.strip_prefix('='))
+                .unwrap_or(first)
+        };
+        match name.trim() {
+            "fps" => {
+                changed = true;
+                fps = match first_opt("fps") {
+                    "ntsc" => Some(30000.0 / 1001.0),
+                    "pal" => Some(25.0),
+                    "film" => Some(24.0),
+                    "ntsc_film" => Some(24000.0 / 1001.0),
+                    f => crate::ffprobe::parse_frame_rate(f),
+                };
+            }
+            "decimate" => {
+                changed = true;
+                let cycle = match opts
+                    .split(':')
+                    .find_map(|o| o.trim().strip_prefix("cycle="))
+                {
+                    Some(c) => c.parse::<u32>().ok().filter(|c| *c >= 2)?,
+                    None => 5,
+                };
+                fps = fps.map(|f| f * (cycle - 1) as f64 / cycle as f64);
+            }
+            "bwdif" | "yadif" => {
+                // default mode "send_field" outputs one frame per field
+                let field_rate = matches!(
+                    first_opt("mode"),
+                    "" | "send_field" | "send_field_nospatial" | "1" | "3"
+                );
+                if field_rate {
+                    changed = true;
+                    fps = fps.map(|f| f * 2.0);
+                }
+            }
+            _ => {}
+        }
     }
+    fps.filter(|_| changed)
 }
 
 #[test]
 fn test_try_parse_fps_vfilter() {
-    let fps = try_parse_fps_vfilter("scale=1280:-1, fps=24, transpose=1").unwrap();
+    let fps = try_parse_fps_vfilter("scale=1280:-1, fps=24, transpose=1", None).unwrap();
     assert!((fps - 24.0).abs() < f64::EPSILON, "{fps:?}");
 
-    let fps = try_parse_fps_vfilter("scale=1280:-1, fps=ntsc, transpose=1").unwrap();
+    let fps = try_parse_fps_vfilter("scale=1280:-1, fps=ntsc, transpose=1", None).unwrap();
     assert!((fps - 30000.0 / 1001.0).abs() < f64::EPSILON, "{fps:?}");
+
+    assert_eq!(try_parse_fps_vfilter("scale=1280:-1", Some(25.0)), None);
+}
+
+#[test]
+fn test_try_parse_fps_vfilter_deinterlace() {
+    let ntsc = 30000.0 / 1001.0;
+    let fps = try_parse_fps_vfilter("fieldmatch,decimate,crop=720:480:0:0", Some(ntsc)).unwrap();
+    assert!((fps - 24000.0 / 1001.0).abs() < 1e-9, "{fps:?}");
+
+    let fps = try_parse_fps_vfilter("bwdif", Some(25.0)).unwrap();
+    assert!((fps - 50.0).abs() < f64::EPSILON, "{fps:?}");
+
+    let fps = try_parse_fps_vfilter("yadif=mode=send_field:parity=auto", Some(25.0)).unwrap();
+    assert!((fps - 50.0).abs() < f64::EPSILON, "{fps:?}");
+
+    assert_eq!(
+        try_parse_fps_vfilter("bwdif=mode=send_frame", Some(25.0)),
+        None
+    );
+    assert_eq!(try_parse_fps_vfilter("decimate", None), None);
+    assert_eq!(try_parse_fps_vfilter("decimate=cycle=0", Some(25.0)), None);
+    assert_eq!(try_parse_fps_vfilter("decimate=cycle=1", Some(25.0)), None);
+
+    let fps = try_parse_fps_vfilter("bwdif,fps=30", Some(25.0)).unwrap();
+    assert!((fps - 30.0).abs() < f64::EPSILON, "{fps:?}");
 }
 
 #[test]
@@ -604,28 +964,35 @@ fn svtav1_to_ffmpeg_args_default_over_3m() {
     let enc = Encode {
         encoder: Encoder("libsvtav1".into()),
         input: "vid.mp4".into(),
+        fps: None,
         vfilter: Some("scale=320:-1,fps=film".into()),
+        crop: CropMode::None,
+        deinterlace: DeinterlaceMode::None,
+        force_cfr: false,
         preset: None,
         pix_format: None,
         keyint: None,
         scd: None,
         svt_args: vec!["film-grain=30".into()],
+        film_grain: FilmGrainMode::None,
         enc_args: <_>::default(),
         enc_input_args: <_>::default(),
+        backend: None,
+        native_args: vec![],
+        native_version: None,
     };
 
     let probe = Ffprobe {
         duration: Ok(Duration::from_secs(300)),
         has_audio: true,
-        max_audio_channels: None,
         fps: Ok(30.0),
         resolution: Some((1280, 720)),
-        is_image: false,
-        pix_fmt: None,
+        ..<_>::default()
     };
 
     let FfmpegEncodeArgs {
         input,
+        input_fps: _,
         vcodec,
         vfilter,
         pix_fmt,
@@ -634,6 +1001,8 @@ fn svtav1_to_ffmpeg_args_default_over_3m() {
         output_args,
         input_args,
         video_only,
+        video_stream: _,
+        native,
     } = enc
         .to_ffmpeg_args(32.0, &probe, "mkv")
         .expect("to_ffmpeg_args");
@@ -645,6 +1014,7 @@ fn svtav1_to_ffmpeg_args_default_over_3m() {
     assert_eq!(preset, Some("8".into()));
     assert_eq!(pix_fmt, Some(PixelFormat::Yuv420p10le));
     assert!(!video_only);
+    assert!(native.is_none());
 
     assert!(
         output_args
@@ -669,28 +1039,34 @@ fn svtav1_to_ffmpeg_args_default_under_3m() {
     let enc = Encode {
         encoder: Encoder("libsvtav1".into()),
         input: "vid.mp4".into(),
+        fps: None,
         vfilter: None,
+        crop: CropMode::None,
+        deinterlace: DeinterlaceMode::None,
+        force_cfr: false,
         preset: Some("7".into()),
         pix_format: Some(PixelFormat::Yuv420p),
         keyint: None,
         scd: None,
         svt_args: vec![],
+        film_grain: FilmGrainMode::None,
         enc_args: <_>::default(),
         enc_input_args: <_>::default(),
+        backend: None,
+        native_args: vec![],
+        native_version: None,
     };
 
     let probe = Ffprobe {
         duration: Ok(Duration::from_secs(179)),
         has_audio: true,
-        max_audio_channels: None,
-        fps: Ok(24.0),
         resolution: Some((1280, 720)),
-        is_image: false,
-        pix_fmt: None,
+        ..<_>::default()
     };
 
     let FfmpegEncodeArgs {
         input,
+        input_fps: _,
         vcodec,
         vfilter,
         pix_fmt,
@@ -699,6 +1075,8 @@ fn svtav1_to_ffmpeg_args_default_under_3m() {
         output_args,
         input_args,
         video_only,
+        video_stream: _,
+        native,
     } = enc
         .to_ffmpeg_args(32.0, &probe, "mkv")
         .expect("to_ffmpeg_args");
@@ -710,6 +1088,7 @@ fn svtav1_to_ffmpeg_args_default_under_3m() {
     assert_eq!(preset, Some("7".into()));
     assert_eq!(pix_fmt, Some(PixelFormat::Yuv420p));
     assert!(!video_only);
+    assert!(native.is_none());
 
     assert!(
         !output_args.iter().any(|a| a.as_str() == "-g"),
@@ -727,29 +1106,107 @@ fn svtav1_to_ffmpeg_args_default_under_3m() {
     assert!(input_args.is_empty());
 }
 
+#[test]
+fn svtav1_native_args() {
+    let mut enc = Encode {
+        encoder: Encoder("libsvtav1".into()),
+        input: "vid.mp4".into(),
+        fps: None,
+        vfilter: Some("fps=24".into()),
+        crop: CropMode::None,
+        deinterlace: DeinterlaceMode::None,
+        force_cfr: false,
+        preset: None,
+        pix_format: None,
+        keyint: None,
+        scd: None,
+        svt_args: vec!["tune=0".into()],
+        film_grain: FilmGrainMode::None,
+        enc_args: <_>::default(),
+        enc_input_args: <_>::default(),
+        backend: Some(Backend::Native),
+        native_args: vec!["--progress".into(), "2".into()],
+        native_version: Some("SVT-AV1 v3.0.2 (release)".into()),
+    };
+
+    let probe = Ffprobe {
+        duration: Ok(Duration::from_secs(300)),
+        has_audio: true,
+        fps: Ok(30.0),
+        resolution: Some((1280, 720)),
+        ..<_>::default()
+    };
+
+    let FfmpegEncodeArgs {
+        output_args,
+        native,
+        ..
+    } = enc
+        .to_ffmpeg_args(32.0, &probe, "mkv")
+        .expect("to_ffmpeg_args");
+    assert!(output_args.is_empty(), "unexpected {output_args:?}");
+    let native = native.expect("native");
+    assert_eq!(&*native.bin, "SvtAv1EncApp");
+    assert_eq!(native.fps, 24.0);
+    assert_eq!(&*native.version, "SVT-AV1 v3.0.2 (release)");
+    assert_eq!(
+        native.encode_args(32.0, Some("8"), true),
+        [
+            "-i",
+            "stdin",
+            "--crf",
+            "32",
+            "--preset",
+            "8",
+            "--keyint",
+            "240",
+            "--scd",
+            "1",
+            "--tune",
+            "0",
+            "--progress",
+            "2"
+        ]
+    );
+
+    enc.enc_args.push("-g=100".into());
+    assert!(enc.to_ffmpeg_args(32.0, &probe, "mkv").is_err());
+
+    enc.enc_args.clear();
+    enc.encoder = Encoder("libx264".into());
+    enc.svt_args.clear();
+    assert!(enc.to_ffmpeg_args(32.0, &probe, "mkv").is_err());
+}
+
 #[test]
 fn libx265_default_hvc1_mp4_mov() {
     let mut enc = Encode {
         encoder: Encoder("libx265".into()),
         input: "vid.mp4".into(),
+        fps: None,
         vfilter: None,
+        crop: CropMode::None,
+        deinterlace: DeinterlaceMode::None,
+        force_cfr: false,
         preset: None,
         pix_format: None,
         keyint: None,
         scd: None,
         svt_args: <_>::default(),
+        film_grain: FilmGrainMode::None,
         enc_args: <_>::default(),
         enc_input_args: <_>::default(),
+        backend: None,
+        native_args: vec![],
+        native_version: None,
     };
 
     let probe = Ffprobe {
         duration: Ok(Duration::from_secs(300)),
         has_audio: true,
-        max_audio_channels: None,
         fps: Ok(30.0),
         resolution: Some((1280, 720)),
-        is_image: false,
-        pix_fmt: None,
+        ..<_>::default()
     };
 
     let FfmpegEncodeArgs { output_args, .. } = enc
@@ -799,3 +1256,44 @@ fn libx265_default_hvc1_mp4_mov() {
         "mp4: unexpected -tag:v hcv1 in {output_args:?}"
     );
 }
+
+#[test]
+fn test_prepend_vfilter() {
+    assert_eq!(
+        prepend_vfilter("crop=1920:800:0:140", Some("scale=1280:-1")),
+        "crop=1920:800:0:140,scale=1280:-1"
+    );
+    assert_eq!(
+        prepend_vfilter("crop=1920:800:0:140", None),
+        "crop=1920:800:0:140"
+    );
+}
+
+#[test]
+fn test_apply_force_cfr() {
+    let mut enc = Encode::parse_from(["ab-av1", "-i", "vid.mp4", "--vfilter", "scale=1280:-1"]);
+    let probe = Ffprobe {
+        fps: Ok(29.970_029),
+        vfr: true,
+        ..<_>::default()
+    };
+    assert_eq!(enc.apply_force_cfr(&probe).unwrap(), None);
+
+    enc.force_cfr = true;
+    assert_eq!(
+        enc.apply_force_cfr(&probe).unwrap().as_deref(),
+        Some("fps=29.97")
+    );
+    assert_eq!(enc.vfilter.as_deref(), Some("fps=29.97,scale=1280:-1"));
+    assert!(!enc.force_cfr, "should only apply once");
+}
+
+#[test]
+fn fps_requires_sequence() {
+    let enc = Encode::try_parse_from(["encode", "-i", "vid.mkv", "--fps", "24"]).unwrap();
+    assert!(enc.validate_fps().is_err());
+    let enc = Encode::try_parse_from(["encode", "-i", "vid.mkv"]).unwrap();
+    assert!(enc.validate_fps().is_ok());
+    let enc = Encode::try_parse_from(["encode", "-i", "f_%05d.png", "--fps", "24"]).unwrap();
+    assert!(enc.validate_fps().is_ok());
+}
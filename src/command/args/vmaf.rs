@@ -1,9 +1,16 @@
+mod model;
+
+pub use model::VmafModel;
+
 use crate::command::args::PixelFormat;
 use anyhow::Context;
 use clap::Parser;
+use model::ModelResolution;
 use std::{borrow::Cow, fmt::Display, sync::Arc, thread};
 
 const DEFAULT_VMAF_FPS: f32 = 25.0;
+/// libvmaf `n_subsample` used by [`Vmaf::fast`], scoring every Nth frame.
+const FAST_N_SUBSAMPLE: u32 = 4;
 
 /// Common vmaf options.
 #[derive(Debug, Parser, Clone)]
@@ -22,16 +29,29 @@ pub struct Vmaf {
     #[arg(long = "vmaf", value_parser = parse_vmaf_arg)]
     pub vmaf_args: Vec<Arc<str>>,
 
+    /// VMAF model to use.
+    /// * `auto` default 1k model, or the 4k model for resolutions > 2560x1440.
+    /// * `neg` no enhancement gain 1k model, or the 4k NEG model for resolutions > 2560x1440.
+    /// * `phone` default 1k model with the phone viewing score transform.
+    /// * A libvmaf built-in model version, e.g. `vmaf_v1.0.16_3d0h`.
+    /// * A model `.json` file path.
+    ///
+    /// Ignored if a `--vmaf model=...` arg is set.
+    #[arg(long, default_value_t, value_parser = VmafModel::parse)]
+    pub vmaf_model: VmafModel,
+
     /// Video resolution scale to use in VMAF analysis. If set, video streams will be bicubic
     /// scaled to this during VMAF analysis. `auto` (default) automatically sets
     /// based on the model and input video resolution. `none` disables any scaling.
     /// `WxH` format may be used to specify custom scaling, e.g. `1920x1080`.
     ///
     /// auto behaviour:
-    /// * 1k model (default for resolutions <= 2560x1440) if width and height
+    /// * 1k models (default for resolutions <= 2560x1440) if width and height
     ///   are less than 1728 & 972 respectively upscale to 1080p. Otherwise no scaling.
-    /// * 4k model (default for resolutions > 2560x1440) if width and height
+    /// * 4k models (default for resolutions > 2560x1440) if width and height
     ///   are less than 3456 & 1944 respectively upscale to 4k. Otherwise no scaling.
+    /// * Model files are 4k if the file name contains "4k" or "2160", otherwise 1k.
+    /// * Unknown models, no scaling.
     ///
     /// The auto behaviour is based on the distorted video dimensions, equivalent
     /// to post input/reference vfilter dimensions.
@@ -53,6 +73,7 @@ impl Default for Vmaf {
         Self {
             and_vmaf: None,
             vmaf_args: <_>::default(),
+            vmaf_model: <_>::default(),
             vmaf_scale: <_>::default(),
             vmaf_fps: DEFAULT_VMAF_FPS,
         }
@@ -63,6 +84,7 @@ impl std::hash::Hash for Vmaf {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.and_vmaf.hash(state);
         self.vmaf_args.hash(state);
+        self.vmaf_model.hash(state);
         self.vmaf_scale.hash(state);
         self.vmaf_fps.to_ne_bytes().hash(state);
     }
@@ -77,6 +99,21 @@ impl Vmaf {
         Some(self.vmaf_fps).filter(|r| *r > 0.0)
     }
 
+    /// Returns faster, approximate, vmaf options. Scores every 4th frame, unless
+    /// `n_subsample` is already set, & `auto` scaling is disabled so low resolution
+    /// videos are scored at their own resolution.
+    pub fn fast(&self) -> Self {
+        let mut fast = self.clone();
+        if !fast.vmaf_args.iter().any(|a| a.contains("n_subsample")) {
+            fast.vmaf_args
+                .push(format!("n_subsample={FAST_N_SUBSAMPLE}").into());
+        }
+        if fast.vmaf_scale == VmafScale::Auto {
+            fast.vmaf_scale = VmafScale::None;
+        }
+        fast
+    }
+
     /// Returns ffmpeg `filter_complex`/`lavfi` value for calculating vmaf.
     pub fn ffmpeg_lavfi(
         &self,
@@ -98,15 +135,19 @@ impl Vmaf {
         let mut lavfi = args.join(":");
         lavfi.insert_str(0, "libvmaf=shortest=true:ts_sync_mode=nearest:");
 
-        let mut model = VmafModel::from_args(&args);
-        if let (None, Some((w, h))) = (model, distorted_res)
-            && w > 2560
-            && h > 1440
-        {
-            // for >2k resolutions use 4k model
-            lavfi.push_str(":model=version=vmaf_4k_v0.6.1");
-            model = Some(VmafModel::V0_6_1_4k);
-        }
+        let model_args: Vec<_> = args.iter().filter(|v| v.contains("model")).collect();
+        let model_res = match model_args[..] {
+            [] => {
+                let (model, res) = self.vmaf_model.select(distorted_res);
+                if let Some(model) = model {
+                    lavfi.push_str(":model=");
+                    lavfi.push_str(&model);
+                }
+                res
+            }
+            [arg] => VmafModel::arg_resolution(arg),
+            _ => None,
+        };
 
         let ref_vf: Cow<_> = match ref_vfilter {
             None => "".into(),
@@ -115,7 +156,7 @@ impl Vmaf {
         };
         let format = pix_fmt.map(|v| format!("format={v},")).unwrap_or_default();
         let scale = self
-            .vf_scale(model.unwrap_or_default(), distorted_res)
+            .vf_scale(model_res, distorted_res)
             .map(|(w, h)| format!("scale={w}:{h}:flags=bicubic,"))
             .unwrap_or_default();
 
@@ -134,13 +175,17 @@ impl Vmaf {
         lavfi
     }
 
-    fn vf_scale(&self, model: VmafModel, distorted_res: Option<(u32, u32)>) -> Option<(i32, i32)> {
+    fn vf_scale(
+        &self,
+        model_res: Option<ModelResolution>,
+        distorted_res: Option<(u32, u32)>,
+    ) -> Option<(i32, i32)> {
         match (self.vmaf_scale, distorted_res) {
-            (VmafScale::Auto, Some((w, h))) => match model {
-                m if m.wants_1080_scale() && w < 1728 && h < 972 => {
+            (VmafScale::Auto, Some((w, h))) => match model_res {
+                Some(ModelResolution::Hd) if w < 1728 && h < 972 => {
                     Some(minimally_scale((w, h), (1920, 1080)))
                 }
-                m if m.wants_2160_scale() && w < 3456 && h < 1944 => {
+                Some(ModelResolution::Uhd) if w < 3456 && h < 1944 => {
                     Some(minimally_scale((w, h), (3840, 2160)))
                 }
                 _ => None,
@@ -200,56 +245,6 @@ impl Display for VmafScale {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
-enum VmafModel {
-    /// Default 1080p model.
-    #[default]
-    V0_6_1,
-    /// 4k model.
-    V0_6_1_4k,
-    V1_0_16_3d0h,
-    V1_0_16_5d0h,
-    V1_0_16_1d5h4k,
-    V1_0_16_3d0h4k,
-    /// Some other user specified model.
-    Custom,
-}
-
-impl VmafModel {
-    fn from_args(args: &[Arc<str>]) -> Option<Self> {
-        let mut using_custom_model: Vec<_> = args.iter().filter(|v| v.contains("model")).collect();
-
-        match using_custom_model.len() {
-            0 => None,
-            1 => Some(match using_custom_model.remove(0) {
-                v if v.ends_with("version=vmaf_v0.6.1") => Self::V0_6_1,
-                v if v.ends_with("version=vmaf_4k_v0.6.1") => Self::V0_6_1_4k,
-                v if v.ends_with("version=vmaf_v1.0.16_3d0h") => Self::V1_0_16_3d0h,
-                v if v.ends_with("version=vmaf_v1.0.16_hfr_3d0h") => Self::V1_0_16_3d0h,
-                v if v.ends_with("version=vmaf_v1.0.16_5d0h") => Self::V1_0_16_5d0h,
-                v if v.ends_with("version=vmaf_v1.0.16_hfr_5d0h") => Self::V1_0_16_5d0h,
-                v if v.ends_with("version=vmaf_v1.0.16_1d5h_2160") => Self::V1_0_16_1d5h4k,
-                v if v.ends_with("version=vmaf_v1.0.16_hfr_1d5h_2160") => Self::V1_0_16_1d5h4k,
-                v if v.ends_with("version=vmaf_v1.0.16_3d0h_2160") => Self::V1_0_16_3d0h4k,
-                v if v.ends_with("version=vmaf_v1.0.16_hfr_3d0h_2160") => Self::V1_0_16_3d0h4k,
-                _ => Self::Custom,
-            }),
-            _ => Some(Self::Custom),
-        }
-    }
-
-    fn wants_1080_scale(self) -> bool {
-        matches!(self, Self::V0_6_1 | Self::V1_0_16_3d0h | Self::V1_0_16_5d0h)
-    }
-
-    fn wants_2160_scale(self) -> bool {
-        matches!(
-            self,
-            Self::V0_6_1_4k | Self::V1_0_16_1d5h4k | Self::V1_0_16_3d0h4k
-        )
-    }
-}
-
 #[test]
 fn vmaf_lavfi() {
     let vmaf = Vmaf {
@@ -313,6 +308,28 @@ fn vmaf_lavfi_include_n_threads() {
     );
 }
 
+#[test]
+fn vmaf_lavfi_fast() {
+    let vmaf = Vmaf {
+        vmaf_args: vec!["n_threads=5".into()],
+        ..<_>::default()
+    }
+    .fast();
+    assert_eq!(
+        vmaf.ffmpeg_lavfi(Some((1280, 720)), Some(PixelFormat::Yuv420p), None),
+        "[0:v]format=yuv420p,setpts=PTS-STARTPTS,settb=AVTB[dis];\
+         [1:v]format=yuv420p,setpts=PTS-STARTPTS,settb=AVTB[ref];\
+         [dis][ref]libvmaf=shortest=true:ts_sync_mode=nearest:n_threads=5:n_subsample=4"
+    );
+
+    // user n_subsample is kept
+    let vmaf = Vmaf {
+        vmaf_args: vec!["n_subsample=8".into()],
+        ..<_>::default()
+    };
+    assert_eq!(vmaf.fast().vmaf_args, vmaf.vmaf_args);
+}
+
 /// Low resolution videos should be upscaled to 1080p
 #[test]
 fn vmaf_lavfi_small_width_upscale_to_1k() {
@@ -461,3 +478,31 @@ fn vmaf_lavfi_1080p() {
          [dis][ref]libvmaf=shortest=true:ts_sync_mode=nearest:n_threads=5:n_subsample=4"
     );
 }
+
+#[test]
+fn vmaf_lavfi_neg_4k() {
+    let vmaf = Vmaf {
+        vmaf_args: vec!["n_threads=5".into()],
+        vmaf_model: VmafModel::Neg,
+        ..<_>::default()
+    };
+    assert_eq!(
+        vmaf.ffmpeg_lavfi(Some((3008, 1692)), Some(PixelFormat::Yuv420p), None),
+        "[0:v]format=yuv420p,scale=3840:-1:flags=bicubic,setpts=PTS-STARTPTS,settb=AVTB[dis];\
+         [1:v]format=yuv420p,scale=3840:-1:flags=bicubic,setpts=PTS-STARTPTS,settb=AVTB[ref];\
+         [dis][ref]libvmaf=shortest=true:ts_sync_mode=nearest:n_threads=5:model=version=vmaf_4k_v0.6.1neg"
+    );
+}
+
+#[test]
+fn vmaf_lavfi_phone() {
+    let vmaf = Vmaf {
+        vmaf_args: vec!["n_threads=5".into()],
+        vmaf_model: VmafModel::Phone,
+        ..<_>::default()
+    };
+    assert_eq!(
+        vmaf.ffmpeg_lavfi(Some((1280, 720)), Some(PixelFormat::Yuv420p), None),
+        r"[0:v]format=yuv420p,scale=1920:-1:flags=bicubic,setpts=PTS-STARTPTS,settb=AVTB[dis];[1:v]format=yuv420p,scale=1920:-1:flags=bicubic,setpts=PTS-STARTPTS,settb=AVTB[ref];[dis][ref]libvmaf=shortest=true:ts_sync_mode=nearest:n_threads=5:model=version=vmaf_v0.6.1\\:enable_transform=true"
+    );
+}
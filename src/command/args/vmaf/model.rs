@@ -0,0 +1,179 @@
+//! VMAF model selection & registry of known libvmaf built-in models.
+use crate::frame_report;
+use anyhow::Context;
+use std::{fmt, path::PathBuf, sync::Arc};
+
+/// Viewing resolution a model is intended for, used by auto `--vmaf-scale`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelResolution {
+    /// 1080p, e.g. the default model.
+    Hd,
+    /// 4k, e.g. the 4k model.
+    Uhd,
+}
+
+/// Known libvmaf built-in model versions & their intended resolution.
+const KNOWN_MODELS: &[(&str, ModelResolution)] = &[
+    ("vmaf_v0.6.1", ModelResolution::Hd),
+    ("vmaf_v0.6.1neg", ModelResolution::Hd),
+    ("vmaf_4k_v0.6.1", ModelResolution::Uhd),
+    ("vmaf_4k_v0.6.1neg", ModelResolution::Uhd),
+    ("vmaf_v1.0.16_3d0h", ModelResolution::Hd),
+    ("vmaf_v1.0.16_hfr_3d0h", ModelResolution::Hd),
+    ("vmaf_v1.0.16_5d0h", ModelResolution::Hd),
+    ("vmaf_v1.0.16_hfr_5d0h", ModelResolution::Hd),
+    ("vmaf_v1.0.16_1d5h_2160", ModelResolution::Uhd),
+    ("vmaf_v1.0.16_hfr_1d5h_2160", ModelResolution::Uhd),
+    ("vmaf_v1.0.16_3d0h_2160", ModelResolution::Uhd),
+    ("vmaf_v1.0.16_hfr_3d0h_2160", ModelResolution::Uhd),
+];
+
+/// Returns the intended resolution of a built-in model `version`, if known.
+fn version_resolution(version: &str) -> Option<ModelResolution> {
+    KNOWN_MODELS
+        .iter()
+        .find(|(v, _)| *v == version)
+        .map(|(_, res)| *res)
+}
+
+/// Returns the intended resolution of a model file, 4k if the name indicates so.
+fn path_resolution(path: &str) -> ModelResolution {
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    match name.contains("4k") || name.contains("2160") {
+        true => ModelResolution::Uhd,
+        false => ModelResolution::Hd,
+    }
+}
+
+/// `--vmaf-model` VMAF model.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub enum VmafModel {
+    /// Default model, or the 4k model for resolutions > 2560x1440.
+    #[default]
+    Auto,
+    /// No enhancement gain model, or the 4k NEG model for resolutions > 2560x1440.
+    Neg,
+    /// Default model with the phone viewing score transform.
+    Phone,
+    /// A libvmaf built-in model version.
+    Version(Arc<str>),
+    /// A model json file.
+    Path {
+        path: PathBuf,
+        /// File content hash, so cached scores are invalidated by model changes.
+        content: blake3::Hash,
+    },
+}
+
+impl VmafModel {
+    /// Parse `--vmaf-model` values.
+    pub fn parse(arg: &str) -> anyhow::Result<Self> {
+        Ok(match arg {
+            "auto" => Self::Auto,
+            "neg" => Self::Neg,
+            "phone" => Self::Phone,
+            _ if arg.ends_with(".json") => {
+                let data = std::fs::read(arg).with_context(|| format!("failed to read {arg}"))?;
+                Self::Path {
+                    path: arg.into(),
+                    content: blake3::hash(&data),
+                }
+            }
+            _ => Self::Version(arg.into()),
+        })
+    }
+
+    /// Returns the libvmaf `model` filter value, `None` meaning the libvmaf default, and the
+    /// intended resolution, `None` if unknown.
+    pub fn select(
+        &self,
+        distorted_res: Option<(u32, u32)>,
+    ) -> (Option<String>, Option<ModelResolution>) {
+        let over_2k = distorted_res.is_some_and(|(w, h)| w > 2560 && h > 1440);
+        match self {
+            Self::Auto if over_2k => (
+                Some("version=vmaf_4k_v0.6.1".into()),
+                Some(ModelResolution::Uhd),
+            ),
+            Self::Auto => (None, Some(ModelResolution::Hd)),
+            Self::Neg if over_2k => (
+                Some("version=vmaf_4k_v0.6.1neg".into()),
+                Some(ModelResolution::Uhd),
+            ),
+            Self::Neg => (
+                Some("version=vmaf_v0.6.1neg".into()),
+                Some(ModelResolution::Hd),
+            ),
+            Self::Phone => (
+                Some(r"version=vmaf_v0.6.1\\:enable_transform=true".into()),
+                Some(ModelResolution::Hd),
+            ),
+            Self::Version(v) => (Some(format!("version={v}")), version_resolution(v)),
+            Self::Path { path, .. } => {
+                let path = path.to_string_lossy();
+                (
+                    Some(frame_report::filter_value(&format!("path={path}"))),
+                    Some(path_resolution(&path)),
+                )
+            }
+        }
+    }
+
+    /// Returns the intended resolution of a `--vmaf model=...` arg model,
+    /// `None` if unknown.
+    pub fn arg_resolution(arg: &str) -> Option<ModelResolution> {
+        if let Some((_, version)) = arg.rsplit_once("version=") {
+            return version_resolution(version);
+        }
+        let (_, path) = arg.rsplit_once("path=")?;
+        Some(path_resolution(path.trim_matches('\'')))
+    }
+}
+
+impl fmt::Display for VmafModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => "auto".fmt(f),
+            Self::Neg => "neg".fmt(f),
+            Self::Phone => "phone".fmt(f),
+            Self::Version(v) => v.fmt(f),
+            Self::Path { path, .. } => path.display().fmt(f),
+        }
+    }
+}
+
+#[test]
+fn parse_vmaf_model() {
+    assert_eq!(VmafModel::parse("auto").unwrap(), VmafModel::Auto);
+    assert_eq!(VmafModel::parse("neg").unwrap(), VmafModel::Neg);
+    assert_eq!(
+        VmafModel::parse("vmaf_v1.0.16_3d0h").unwrap(),
+        VmafModel::Version("vmaf_v1.0.16_3d0h".into())
+    );
+    assert!(VmafModel::parse("/does/not/exist.json").is_err());
+}
+
+#[test]
+fn model_resolution() {
+    assert_eq!(
+        VmafModel::Version("vmaf_4k_v0.6.1neg".into())
+            .select(None)
+            .1,
+        Some(ModelResolution::Uhd)
+    );
+    assert_eq!(VmafModel::Version("foo".into()).select(None).1, None);
+    assert_eq!(
+        path_resolution("/models/my_4k_model.json"),
+        ModelResolution::Uhd
+    );
+    assert_eq!(path_resolution("/4k/model.json"), ModelResolution::Hd);
+    assert_eq!(
+        VmafModel::arg_resolution("model=version=vmaf_v1.0.16_hfr_3d0h_2160"),
+        Some(ModelResolution::Uhd)
+    );
+    assert_eq!(
+        VmafModel::arg_resolution("model=path=model.json"),
+        Some(ModelResolution::Hd)
+    );
+    assert_eq!(VmafModel::arg_resolution("model=version=foo"), None);
+}
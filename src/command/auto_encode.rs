@@ -1,23 +1,27 @@
 use crate::{
     command::{
-        PROGRESS_CHARS, args, crf_search,
-        encode::{self, default_output_name},
-        sample_encode::{self, Work},
+        PROGRESS_CHARS, analyse_input, args, crf_search,
+        encode::{self, VerifyScore, VerifyScoreMode, default_output_name},
+        sample_encode::{self, StdoutFormat, Work},
     },
     console_ext::style,
-    ffprobe,
+    ffprobe::{self, Ffprobe},
     float::TerseF32,
-    temporary,
+    metric::ScoreKind,
+    provenance, temporary,
 };
 use anyhow::Context;
-use clap::Parser;
-use console::style;
+use clap::{Parser, ValueEnum};
 use futures_util::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
 use same_file::is_same_file;
-use std::{pin::pin, sync::Arc, time::Duration};
+use std::{path::Path, pin::pin, sync::Arc, time::Duration};
 
 const BAR_LEN: u64 = 1024 * 1024 * 1024;
+const SPINNER_RUNNING: &str =
+    "{spinner:.cyan.bold} {elapsed_precise:.bold} {prefix} {wide_bar:.cyan/blue} ({msg}eta {eta})";
+const SPINNER_FINISHED: &str =
+    "{spinner:.cyan.bold} {elapsed_precise:.bold} {prefix} {wide_bar:.cyan/blue} ({msg})";
 
 /// Automatically determine the best crf to deliver the min-vmaf and use it to encode a video or image.
 ///
@@ -36,22 +40,100 @@ pub struct Args {
 
     #[clap(flatten)]
     pub encode: args::EncodeToOutput,
+
+    /// Copy inputs skipped by --skip-efficient to the output, instead of writing no output.
+    ///
+    /// The input & output must have the same extension.
+    #[arg(long, requires = "skip_efficient")]
+    pub copy_skipped: bool,
+
+    /// Fallbacks, tried in order, when crf-search fails to find a crf meeting the
+    /// min score & --max-encoded-percent. E.g. `--no-good-crf relax,slower-preset,copy`.
+    ///
+    /// Fallbacks accumulate, e.g. slower-preset after relax searches using the relaxed
+    /// min score. The fallbacks used are recorded in the output metadata, see `inspect`.
+    ///
+    /// [default: fail]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub no_good_crf: Vec<NoGoodCrf>,
+
+    /// `--no-good-crf relax` amount to lower the min score by for each search.
+    ///
+    /// [default: 0.5 for VMAF, XPSNR & PSNR, 0.002 for SSIM & MS-SSIM, 1 for SSIMULACRA2]
+    #[arg(long)]
+    pub relax_step: Option<f32>,
+
+    /// `--no-good-crf relax` lowest min score to search with.
+    ///
+    /// [default: 4 steps below the min score]
+    #[arg(long)]
+    pub relax_floor: Option<f32>,
+
+    /// Verify the final encode's scores meet each min score, within
+    /// --verify-score-tolerance, before moving it into place. Reports actual vs predicted
+    /// scores & video size.
+    ///
+    /// `samples` scores as many samples as the crf-search, placed between the search samples.
+    /// `full` scores the whole encode.
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "samples")]
+    pub verify_score: Option<VerifyScoreMode>,
+
+    /// --verify-score allowed shortfall below the min score.
+    #[arg(long, default_value_t = 1.0)]
+    pub verify_score_tolerance: f32,
+
+    /// --verify-score prints a warning, rather than failing, if the score is too low.
+    #[arg(long)]
+    pub verify_score_warn: bool,
+
+    /// `--no-good-crf slower-preset` encoder preset to search with.
+    #[arg(long, allow_hyphen_values = true)]
+    pub fallback_preset: Option<Arc<str>>,
+
+    /// Stdout message format `human` or `json`. Json reports each --no-good-crf
+    /// fallback used.
+    ///
+    /// See <https://github.com/alexheretic/ab-av1/blob/main/stdout-format-json.md>
+    #[arg(long, value_enum, default_value_t = StdoutFormat::Human)]
+    pub stdout_format: StdoutFormat,
 }
 
-pub async fn auto_encode(Args { mut search, encode }: Args) -> anyhow::Result<()> {
-    const SPINNER_RUNNING: &str = "{spinner:.cyan.bold} {elapsed_precise:.bold} {prefix} {wide_bar:.cyan/blue} ({msg}eta {eta})";
-    const SPINNER_FINISHED: &str =
-        "{spinner:.cyan.bold} {elapsed_precise:.bold} {prefix} {wide_bar:.cyan/blue} ({msg})";
+/// Fallback when crf-search fails to find a crf.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoGoodCrf {
+    /// Fail writing no output.
+    Fail,
+    /// Search again lowering the min score by --relax-step, down to --relax-floor.
+    Relax,
+    /// Encode using the attempted crf closest to meeting the constraints, i.e. with the
+    /// smallest min score shortfall & size excess relative to each constraint.
+    Closest,
+    /// Search again using --fallback-preset.
+    SlowerPreset,
+    /// Copy the input to the output. The input & output must have the same extension.
+    Copy,
+}
 
+pub async fn auto_encode(
+    Args {
+        mut search,
+        encode,
+        copy_skipped,
+        no_good_crf,
+        relax_step,
+        relax_floor,
+        fallback_preset,
+        verify_score,
+        verify_score_tolerance,
+        verify_score_warn,
+        stdout_format,
+    }: Args,
+) -> anyhow::Result<()> {
     let defaulting_output = encode.output.is_none();
-    let input_probe = Arc::new(ffprobe::probe(&search.args.input));
+    let input_probe = Arc::new(ffprobe::probe(&search.args.input, search.args.fps));
 
     let output = encode.output.unwrap_or_else(|| {
-        default_output_name(
-            &search.args.input,
-            &search.args.encoder,
-            input_probe.is_image,
-        )
+        default_output_name(&search.args.input, &search.args.encoder, &input_probe)
     });
 
     anyhow::ensure!(
@@ -59,9 +141,19 @@ pub async fn auto_encode(Args { mut search, encode }: Args) -> anyhow::Result<()
         "Input and Output are specified as the same file. Not proceeding. \
          Pass in `--overwrite-input` to allow this."
     );
+    anyhow::ensure!(
+        fallback_preset.is_some() || !no_good_crf.contains(&NoGoodCrf::SlowerPreset),
+        "--no-good-crf slower-preset requires --fallback-preset"
+    );
+    anyhow::ensure!(
+        relax_step.is_none_or(|s| s > 0.0),
+        "--relax-step must be positive"
+    );
 
     search.sample.set_extension_from_output(&output);
     search.validate()?;
+    // read once for the search & final encode
+    search.args.read_native_version().await;
 
     let bar = ProgressBar::new(BAR_LEN).with_style(
         ProgressStyle::default_bar()
@@ -74,41 +166,238 @@ pub async fn auto_encode(Args { mut search, encode }: Args) -> anyhow::Result<()
         let out = shell_escape::escape(output.display().to_string().into());
         bar.println(style!("Encoding {out}").dim().to_string());
     }
+    analyse_input(
+        &mut search.args,
+        Some(&mut search.score),
+        &input_probe,
+        &bar,
+    )
+    .await?;
 
-    let min_score = search.min_score();
     let max_encoded_percent = search.max_encoded_percent;
-    let enc_args = search.args.clone();
-    let thorough = search.thorough;
-    let verbose = search.verbose;
     let keep = search.sample.keep;
+    let input = search.args.input.clone();
+    let main_kind = search.main_min_score().kind;
+    let relax_step = relax_step.unwrap_or_else(|| default_relax_step(main_kind));
+    let relax_floor = relax_floor.unwrap_or(search.min_score() - 4.0 * relax_step);
 
-    let mut crf_search = pin!(crf_search::run(search, input_probe.clone()));
-    let mut best = None;
-    while let Some(update) = crf_search.next().await {
-        match update {
-            Err(err) => {
-                if let crf_search::Error::NoGoodCrf { last } = &err {
-                    // show last sample attempt in progress bar
-                    bar.set_style(
-                        ProgressStyle::default_bar()
-                            .template(SPINNER_FINISHED)?
-                            .progress_chars(PROGRESS_CHARS),
-                    );
-                    let mut vmaf = style(last.enc.single_score());
-                    if last.enc.single_score() < min_score {
-                        vmaf = vmaf.red();
-                    }
-                    let mut percent = style!("{:.0}%", last.enc.encode_percent);
-                    if last.enc.encode_percent > max_encoded_percent as _ {
-                        percent = percent.red();
-                    }
-                    let score_kind = last.enc.single_score_kind();
-                    bar.finish_with_message(format!("{score_kind} {vmaf:.2}, size {percent}"));
+    let mut fallbacks = no_good_crf.into_iter().peekable();
+    let mut fallbacks_used = vec![];
+    let best = loop {
+        let (last, closest) = match run_search(search.clone(), input_probe.clone(), &bar).await {
+            Ok(Searched::Best(best)) => break best,
+            Ok(Searched::Skipped(skip)) => {
+                finish_bar(&bar, format!("skipped, {skip}"))?;
+                if copy_skipped {
+                    copy_input(&input, &output).await?;
+                    let out = shell_escape::escape(output.display().to_string().into());
+                    eprintln!("{}", style!("Copied input to {out}").dim());
                 }
+                return Ok(());
+            }
+            Ok(Searched::NoGoodCrf { last, closest }) => (last, closest),
+            Err(err) => {
                 bar.finish();
                 return Err(err.into());
             }
-            Ok(crf_search::Update::Status {
+        };
+
+        let min_scores = search.min_scores();
+        let min_score = search.min_score();
+        // relax stays the next fallback until reaching the floor
+        let relaxed = round_score(min_score - relax_step, main_kind);
+        if relaxed < relax_floor - relax_step / 100.0 {
+            fallbacks.next_if_eq(&NoGoodCrf::Relax);
+        }
+
+        let score_kind = search.main_min_score();
+        let failed = || {
+            let mut percent = style!("{:.0}%", last.enc.encode_percent);
+            if last.enc.encode_percent > max_encoded_percent as _ {
+                percent = percent.red();
+            }
+            format!("{}, size {percent}", last.scores_summary(&min_scores))
+        };
+        match fallbacks.peek().copied() {
+            None | Some(NoGoodCrf::Fail) => {
+                // show last sample attempt in progress bar
+                finish_bar(&bar, failed())?;
+                return Err(crf_search::Error::NoGoodCrf { last }.into());
+            }
+            Some(NoGoodCrf::Relax) => {
+                let relaxed = TerseF32(relaxed);
+                bar.println(
+                    style!(
+                        "No good crf ({}), relaxing min {score_kind} to {relaxed}",
+                        failed()
+                    )
+                    .dim()
+                    .to_string(),
+                );
+                search.set_min_score(relaxed.0);
+                fallbacks_used.push(format!("relax min {score_kind} {relaxed}"));
+                stdout_format.print_fallback(NoGoodCrf::Relax, &fallbacks_used);
+            }
+            Some(NoGoodCrf::Closest) => {
+                bar.println(
+                    style!(
+                        "No good crf ({}), using closest crf {}",
+                        failed(),
+                        TerseF32(closest.crf)
+                    )
+                    .dim()
+                    .to_string(),
+                );
+                fallbacks_used.push(format!("closest crf {}", TerseF32(closest.crf)));
+                stdout_format.print_fallback(NoGoodCrf::Closest, &fallbacks_used);
+                break closest;
+            }
+            Some(NoGoodCrf::SlowerPreset) => {
+                fallbacks.next();
+                let preset = fallback_preset.clone();
+                let p = preset.as_deref().unwrap_or_default();
+                bar.println(
+                    style!("No good crf ({}), searching with preset {p}", failed())
+                        .dim()
+                        .to_string(),
+                );
+                fallbacks_used.push(format!("preset {p}"));
+                stdout_format.print_fallback(NoGoodCrf::SlowerPreset, &fallbacks_used);
+                search.args.preset = preset;
+            }
+            Some(NoGoodCrf::Copy) => {
+                finish_bar(&bar, failed())?;
+                copy_input(&input, &output).await?;
+                fallbacks_used.push("copy input".into());
+                stdout_format.print_fallback(NoGoodCrf::Copy, &fallbacks_used);
+                let out = shell_escape::escape(output.display().to_string().into());
+                eprintln!("{}", style!("No good crf, copied input to {out}").dim());
+                return Ok(());
+            }
+        }
+    };
+
+    let min_scores = search.min_scores();
+    let search_result = provenance::SearchResult {
+        scores: min_scores
+            .iter()
+            .map(|m| m.search_score(&best.enc))
+            .collect(),
+        max_encoded_percent,
+        predicted_size: best.enc.predicted_encode_size,
+        predicted_percent: best.enc.encode_percent,
+        fallbacks: fallbacks_used,
+    };
+
+    let verify_score = verify_score.map(|mode| VerifyScore {
+        mode,
+        tolerance: verify_score_tolerance,
+        warn: verify_score_warn,
+        samples: match &input_probe.duration {
+            Ok(d) => search.sample.sample_count(*d),
+            Err(_) => 1,
+        },
+        sample_duration: search.sample.sample_duration,
+        vmaf: search.vmaf.clone(),
+        score: search.score.clone(),
+        min_scores: min_scores.clone(),
+        xpsnr: search.xpsnr,
+    });
+
+    let mut percent = style!("{:.0}%", best.enc.encode_percent).green();
+    if best.enc.encode_percent > max_encoded_percent as _ {
+        percent = percent.red();
+    }
+    finish_bar(
+        &bar,
+        format!("{}, size {percent}", best.scores_summary(&min_scores)),
+    )?;
+    temporary::clean(keep).await;
+
+    let bar = ProgressBar::new(12).with_style(
+        ProgressStyle::default_bar()
+            .template(SPINNER_RUNNING)?
+            .progress_chars(PROGRESS_CHARS),
+    );
+    bar.set_prefix("Encoding");
+    bar.enable_steady_tick(Duration::from_millis(100));
+
+    encode::run(
+        encode::Args {
+            args: search.args,
+            crf: best.crf,
+            encode: args::EncodeToOutput {
+                output: Some(output),
+                ..encode
+            },
+        },
+        input_probe,
+        Some(search_result),
+        verify_score,
+        &bar,
+    )
+    .await
+}
+
+enum Searched {
+    Best(crf_search::Sample),
+    Skipped(crf_search::Skip),
+    /// No crf met the constraints, with the last & closest attempts.
+    NoGoodCrf {
+        last: crf_search::Sample,
+        closest: crf_search::Sample,
+    },
+}
+
+impl StdoutFormat {
+    /// Print an `auto-encode-fallback` json message for the latest `fallbacks_used`.
+    fn print_fallback(self, fallback: NoGoodCrf, fallbacks_used: &[String]) {
+        if let Self::Json = self {
+            println!("{}", fallback_json(fallback, fallbacks_used));
+        }
+    }
+}
+
+/// `auto-encode-fallback` json message, see _stdout-format-json.md_.
+fn fallback_json(fallback: NoGoodCrf, fallbacks_used: &[String]) -> serde_json::Value {
+    serde_json::json!({
+        "type": "auto-encode-fallback",
+        "fallback": fallback.to_possible_value().map(|v| v.get_name().to_owned()),
+        "description": fallbacks_used.last(),
+        "fallbacks": fallbacks_used,
+    })
+}
+
+/// Run crf-search showing progress on `bar`.
+async fn run_search(
+    search: crf_search::SearchArgs,
+    input_probe: Arc<Ffprobe>,
+    bar: &ProgressBar,
+) -> Result<Searched, crf_search::Error> {
+    let min_scores = search.min_scores();
+    let max_encoded_percent = search.max_encoded_percent;
+    let thorough = search.thorough;
+    let verbose = search.verbose;
+
+    bar.set_position(0);
+    let mut attempts = vec![];
+    let mut crf_search = pin!(crf_search::run(search, input_probe));
+    while let Some(update) = crf_search.next().await {
+        let update = match update {
+            Err(crf_search::Error::NoGoodCrf { last }) => {
+                let closest = attempts
+                    .into_iter()
+                    .min_by(|a: &crf_search::Sample, b| {
+                        a.deficit(&min_scores, max_encoded_percent)
+                            .total_cmp(&b.deficit(&min_scores, max_encoded_percent))
+                    })
+                    .unwrap_or_else(|| last.clone());
+                return Ok(Searched::NoGoodCrf { last, closest });
+            }
+            update => update?,
+        };
+        match update {
+            crf_search::Update::Status {
                 crf_run,
                 crf,
                 sample:
@@ -120,7 +409,7 @@ pub async fn auto_encode(Args { mut search, encode }: Args) -> anyhow::Result<()
                         samples,
                         full_pass,
                     },
-            }) => {
+            } => {
                 bar.set_position(crf_search::guess_progress(crf_run, progress, thorough) as _);
                 let crf = TerseF32(crf);
                 match full_pass {
@@ -134,73 +423,129 @@ pub async fn auto_encode(Args { mut search, encode }: Args) -> anyhow::Result<()
                     _ => bar.set_message(format!("{label} {fps} fps, ")),
                 }
             }
-            Ok(crf_search::Update::SampleResult {
+            crf_search::Update::SampleResult {
                 crf,
                 sample,
                 result,
-            }) => {
+            } => {
                 if verbose
                     .log_level()
                     .is_some_and(|lvl| lvl > log::Level::Warn)
                 {
-                    result.print_attempt(&bar, sample, Some(crf))
+                    result.print_attempt(bar, sample, Some(crf))
                 }
             }
-            Ok(crf_search::Update::SampleEncodeDone(_)) => {}
-            Ok(crf_search::Update::RunResult(result)) => {
+            crf_search::Update::SampleEncodeDone(sample) => attempts.push(sample),
+            crf_search::Update::RunResult(result) => {
                 if verbose
                     .log_level()
                     .is_some_and(|lvl| lvl > log::Level::Error)
                 {
-                    result.print_attempt(&bar, min_score, max_encoded_percent)
+                    result.print_attempt(bar, &min_scores, max_encoded_percent)
                 }
             }
-            Ok(crf_search::Update::Done(result)) => best = Some(result),
+            crf_search::Update::Done(best) => return Ok(Searched::Best(best)),
+            crf_search::Update::Skipped(skip) => return Ok(Searched::Skipped(skip)),
         }
     }
-    let best = best.context("no crf-search best?")?;
+    Err(anyhow::anyhow!("no crf-search best?").into())
+}
+
+/// Default `--relax-step` on the scale of the main min score `kind`.
+fn default_relax_step(kind: ScoreKind) -> f32 {
+    match kind {
+        ScoreKind::Vmaf | ScoreKind::Xpsnr | ScoreKind::Psnr => 0.5,
+        ScoreKind::Ssim | ScoreKind::MsSsim => 0.002,
+        // butteraugli is never a min score
+        ScoreKind::Ssimulacra2 | ScoreKind::Butteraugli => 1.0,
+    }
+}
 
+/// Round a relaxed score to the displayed decimals, avoiding f32 step drift.
+fn round_score(score: f32, kind: ScoreKind) -> f32 {
+    let scale = 10_f32.powi(kind.decimals() as _);
+    (score * scale).round() / scale
+}
+
+fn finish_bar(bar: &ProgressBar, msg: String) -> anyhow::Result<()> {
     bar.set_style(
         ProgressStyle::default_bar()
             .template(SPINNER_FINISHED)?
             .progress_chars(PROGRESS_CHARS),
     );
-    bar.finish_with_message(format!(
-        "{} {:.2}, size {}",
-        best.enc.single_score_kind(),
-        style(best.enc.single_score()).green(),
-        style(format!("{:.0}%", best.enc.encode_percent)).green(),
-    ));
-    temporary::clean(keep).await;
+    bar.finish_with_message(msg);
+    Ok(())
+}
 
-    let bar = ProgressBar::new(12).with_style(
-        ProgressStyle::default_bar()
-            .template(SPINNER_RUNNING)?
-            .progress_chars(PROGRESS_CHARS),
+/// Copy a skipped `input` to `output`, which must have the same container.
+async fn copy_input(input: &Path, output: &Path) -> anyhow::Result<()> {
+    if is_same_file(input, output).unwrap_or(false) {
+        return Ok(());
+    }
+    anyhow::ensure!(
+        input.extension() == output.extension(),
+        "cannot copy input, output extension differs, set -o with the input extension"
     );
-    bar.set_prefix("Encoding");
-    bar.enable_steady_tick(Duration::from_millis(100));
+    tokio::fs::copy(input, output)
+        .await
+        .context("copy input to output")?;
+    Ok(())
+}
 
-    encode::run(
-        encode::Args {
-            args: enc_args,
-            crf: best.crf,
-            encode: args::EncodeToOutput {
-                output: Some(output),
-                ..encode
-            },
-        },
-        input_probe,
-        &bar,
-    )
-    .await
+/// auto-encode json output only reports fallbacks, crf-search messages are not available.
+#[test]
+fn stdout_format_fallbacks() {
+    let args =
+        Args::try_parse_from(["auto-encode", "-i", "vid.mkv", "--stdout-format", "json"]).unwrap();
+    assert!(matches!(args.stdout_format, StdoutFormat::Json));
+    let args = Args::try_parse_from(["auto-encode", "-i", "vid.mkv"]).unwrap();
+    assert!(matches!(args.stdout_format, StdoutFormat::Human));
+
+    let used = [
+        "relax min VMAF 94.5".to_owned(),
+        "closest crf 34".to_owned(),
+    ];
+    assert_eq!(
+        fallback_json(NoGoodCrf::Closest, &used),
+        serde_json::json!({
+            "type": "auto-encode-fallback",
+            "fallback": "closest",
+            "description": "closest crf 34",
+            "fallbacks": ["relax min VMAF 94.5", "closest crf 34"],
+        })
+    );
 }
 
-/// crf-search json output is not currently available in auto-encode.
 #[test]
-fn stdout_format_unsupported() {
-    assert!(
-        Args::try_parse_from(["auto-encode", "-i", "vid.mkv", "--stdout-format", "json"]).is_err()
+fn parse_no_good_crf() {
+    let args = Args::try_parse_from([
+        "auto-encode",
+        "-i",
+        "vid.mkv",
+        "--no-good-crf",
+        "relax,slower-preset,copy",
+    ])
+    .unwrap();
+    assert_eq!(
+        args.no_good_crf,
+        [NoGoodCrf::Relax, NoGoodCrf::SlowerPreset, NoGoodCrf::Copy]
     );
-    assert!(Args::try_parse_from(["auto-encode", "-i", "vid.mkv"]).is_ok());
+}
+
+#[test]
+fn relax_step_default_by_score_kind() {
+    let args =
+        Args::try_parse_from(["auto-encode", "-i", "vid.mkv", "--min-ssim", "0.95"]).unwrap();
+    assert_eq!(args.relax_step, None);
+    let kind = args.search.main_min_score().kind;
+    assert_eq!(kind, ScoreKind::Ssim);
+
+    let step = default_relax_step(kind);
+    let relaxed = round_score(0.95 - step, kind);
+    assert_eq!(relaxed, 0.948);
+    // default floor stays on the ssim scale
+    assert!((0.95 - 4.0 * step - 0.942).abs() < 1e-6);
+
+    assert_eq!(default_relax_step(ScoreKind::Vmaf), 0.5);
+    assert_eq!(round_score(95.0 - 0.5, ScoreKind::Vmaf), 94.5);
 }
@@ -0,0 +1,447 @@
+use crate::{
+    command::{PROGRESS_CHARS, analyse_input, crf_search, encode::tmp_output_name},
+    console_ext::style,
+    ffmpeg::{self, OutputStreams},
+    ffprobe::{self, Ffprobe},
+    float::TerseF32,
+    process::{CommandExt, ensure_success},
+    provenance::{self, Provenance},
+    temporary::{self, TempKind},
+};
+use anyhow::{Context, ensure};
+use clap::Parser;
+use futures_util::{StreamExt, stream};
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use log::info;
+use std::{
+    path::{Path, PathBuf},
+    pin::pin,
+    process::Stdio,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::process::Command;
+
+/// Image extensions encoded from input directories.
+const IMAGE_EXTENSIONS: &[&str] = &["bmp", "jpeg", "jpg", "png", "tif", "tiff", "webp"];
+
+/// Encode a batch of images, e.g. a photo library, searching for the best crf of each
+/// image to deliver the min score. Images are searched & encoded in parallel.
+///
+/// Outputs are written next to each input, or under --output-dir, with the encoder
+/// image extension, e.g. `-e libjxl` for JPEG XL. Images with an existing output
+/// are skipped, so an interrupted batch may be resumed.
+///
+/// EXIF & ICC metadata is copied from each input using exiftool.
+///
+/// Outputs:
+/// * Per image crf, score & size
+/// * Total size & space saved
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    /// Search args, where --input is an image or a directory searched recursively for images.
+    #[clap(flatten)]
+    pub search: crf_search::SearchArgs,
+
+    /// Output directory, keeping the directory structure of the input.
+    /// By default outputs are written next to each input.
+    #[arg(long, value_hint = clap::ValueHint::DirPath)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Number of images to process in parallel.
+    ///
+    /// [default: half the available cpus]
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+
+    /// Don't copy EXIF & ICC metadata to the outputs, which otherwise requires exiftool.
+    #[arg(long)]
+    pub strip_metadata: bool,
+}
+
+pub async fn batch_images(
+    Args {
+        mut search,
+        output_dir,
+        jobs,
+        strip_metadata,
+    }: Args,
+) -> anyhow::Result<()> {
+    search.validate()?;
+    // read once for all images
+    search.args.read_native_version().await;
+    let root = search.args.input.clone();
+    let images = find_images(&root)?;
+    ensure!(!images.is_empty(), "no images found in {}", root.display());
+
+    if !strip_metadata {
+        let exiftool = Command::new("exiftool")
+            .arg("-ver")
+            .stdin(Stdio::null())
+            .output()
+            .await;
+        ensure!(
+            exiftool.is_ok_and(|o| o.status.success()),
+            "exiftool is required to copy metadata, alternatively use --strip-metadata"
+        );
+    }
+
+    let ext = search.args.encoder.default_image_ext();
+    let jobs = jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get() / 2))
+        .max(1);
+    // runs share the temp files registry, so must not clean up each other's files
+    temporary::defer_early_clean();
+
+    let bar = ProgressBar::new(images.len() as _).with_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({pos}/{len}, eta {eta})")?
+            .progress_chars(PROGRESS_CHARS)
+    );
+    bar.enable_steady_tick(Duration::from_millis(100));
+
+    let temp_parent = temporary::process_dir(search.sample.temp_dir.clone())?;
+    let mut results = pin!(
+        stream::iter(images.into_iter().enumerate())
+            .map(|(idx, input)| {
+                let output = output_path(&root, &input, output_dir.as_deref(), ext);
+                let mut search = search.clone();
+                search.args.input = input.clone();
+                // distinct per image, as samples are named after the input file name
+                search.sample.temp_dir = Some(temp_parent.join(idx.to_string()));
+                async move {
+                    let result = encode_image(search, &output, strip_metadata).await;
+                    (input, output, result)
+                }
+            })
+            .buffer_unordered(jobs)
+    );
+
+    let mut summary = Summary::default();
+    while let Some((input, output, result)) = results.next().await {
+        bar.inc(1);
+        let name = input.strip_prefix(&root).unwrap_or(&input).display();
+        match result {
+            Ok(Encoded::Done(image)) => {
+                let percent = 100.0 * image.output_size as f64 / image.input_size.max(1) as f64;
+                let mut line = format!(
+                    "{name} crf {} {} {:.*} {} ({percent:.0}%)",
+                    TerseF32(image.crf),
+                    image.score_kind,
+                    image.score_decimals,
+                    image.score,
+                    HumanBytes(image.output_size),
+                );
+                if let Some(err) = &image.metadata_err {
+                    line += &style!(", metadata not copied: {err}").yellow().to_string();
+                }
+                bar.println(line);
+                summary.add(&image);
+            }
+            Ok(Encoded::Skipped(reason)) => {
+                bar.println(style!("{name} skipped, {reason}").dim().to_string());
+                summary.skipped += 1;
+            }
+            Err(err) => {
+                info!("{} failed: {err:#}", output.display());
+                bar.println(style!("{name} failed, {err}").red().to_string());
+                summary.failed += 1;
+            }
+        }
+    }
+    bar.finish_and_clear();
+
+    println!("{summary}");
+    ensure!(summary.failed == 0, "{} images failed", summary.failed);
+    Ok(())
+}
+
+/// Input images, sorted. A directory is searched recursively, skipping hidden entries.
+fn find_images(input: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !input.is_dir() {
+        return Ok(vec![input.into()]);
+    }
+    let mut images = vec![];
+    let mut dirs = vec![input.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("{}", dir.display()))? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_image_ext(&path) {
+                images.push(path);
+            }
+        }
+    }
+    images.sort();
+    Ok(images)
+}
+
+fn is_image_ext(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Output path of `input` found under `root`, e.g. `photos/a/b.jpg` -> `out/a/b.avif`.
+///
+/// Without an `output_dir` outputs are next to the input, e.g. `photos/a/b.avif`.
+fn output_path(root: &Path, input: &Path, output_dir: Option<&Path>, ext: &str) -> PathBuf {
+    let output = match output_dir {
+        Some(dir) if input == root => dir.join(input.file_name().unwrap_or_default()),
+        Some(dir) => dir.join(input.strip_prefix(root).unwrap_or(input)),
+        None => input.into(),
+    };
+    match output.with_extension(ext) {
+        // e.g. avif -> avif
+        same if same == input => output.with_extension(format!("new.{ext}")),
+        output => output,
+    }
+}
+
+enum Encoded {
+    Done(Image),
+    Skipped(String),
+}
+
+/// Result of a single image encode.
+struct Image {
+    crf: f32,
+    score_kind: String,
+    score: f32,
+    score_decimals: usize,
+    input_size: u64,
+    output_size: u64,
+    /// Why EXIF/ICC metadata could not be copied, if it couldn't.
+    metadata_err: Option<String>,
+}
+
+/// Search & encode a single image.
+async fn encode_image(
+    mut search: crf_search::SearchArgs,
+    output: &Path,
+    strip_metadata: bool,
+) -> anyhow::Result<Encoded> {
+    if output.exists() {
+        return Ok(Encoded::Skipped("output exists".into()));
+    }
+    let input = search.args.input.clone();
+    let probe = Arc::new(ffprobe::probe(&input, search.args.fps));
+    if !probe.is_image {
+        return Ok(Encoded::Skipped("not a still image".into()));
+    }
+    if let Some(dir) = &search.sample.temp_dir {
+        temporary::add(dir, TempKind::Keepable);
+    }
+    search.sample.set_extension_from_output(output);
+    analyse_input(
+        &mut search.args,
+        Some(&mut search.score),
+        &probe,
+        &ProgressBar::hidden(),
+    )
+    .await?;
+
+    let mut best = None;
+    let mut run = pin!(crf_search::run(search.clone(), probe.clone()));
+    while let Some(update) = run.next().await {
+        match update? {
+            crf_search::Update::Done(sample) => best = Some(sample),
+            crf_search::Update::Skipped(skip) => return Ok(Encoded::Skipped(skip.to_string())),
+            _ => {}
+        }
+    }
+    let best = best.context("no crf-search result?")?;
+
+    let main_min_score = search.main_min_score();
+    let score = main_min_score.score(&best.enc);
+    let search_result = provenance::SearchResult {
+        scores: search
+            .min_scores()
+            .iter()
+            .map(|m| m.search_score(&best.enc))
+            .collect(),
+        max_encoded_percent: search.max_encoded_percent,
+        predicted_size: best.enc.predicted_encode_size,
+        predicted_percent: best.enc.encode_percent,
+        fallbacks: vec![],
+    };
+    encode(&search, best.crf, &probe, output, search_result).await?;
+
+    let metadata_err = match strip_metadata {
+        true => None,
+        false => copy_metadata(&input, output)
+            .await
+            .err()
+            .map(|e| e.to_string()),
+    };
+
+    Ok(Encoded::Done(Image {
+        crf: best.crf,
+        score_kind: main_min_score.to_string(),
+        score,
+        score_decimals: main_min_score.kind.decimals(),
+        input_size: tokio::fs::metadata(&input).await?.len(),
+        output_size: tokio::fs::metadata(output).await?.len(),
+        metadata_err,
+    }))
+}
+
+/// Encode the image video stream only at `crf` to `output`.
+async fn encode(
+    search: &crf_search::SearchArgs,
+    crf: f32,
+    probe: &Ffprobe,
+    output: &Path,
+    search_result: provenance::SearchResult,
+) -> anyhow::Result<()> {
+    let out_ext = output
+        .extension()
+        .and_then(|e| e.to_str())
+        .context("no output extension?")?;
+    let mut enc_args = search.args.to_ffmpeg_args(crf, probe, out_ext)?;
+    enc_args.video_only = true;
+    let provenance = Provenance {
+        command: Some(format!(
+            "{} -o {}",
+            search.args.encode_hint(crf),
+            shell_escape::escape(output.display().to_string().into())
+        )),
+        search: Some(search_result),
+        ..<_>::default()
+    };
+
+    if let Some(dir) = output.parent().filter(|d| !d.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let tmp_output = tmp_output_name(output)?;
+    temporary::add(&tmp_output, TempKind::NotKeepable);
+
+    let mut enc = ffmpeg::encode(
+        enc_args,
+        &tmp_output,
+        false,
+        None,
+        &OutputStreams::default(),
+        false,
+        false,
+        provenance,
+    )?;
+    while let Some(progress) = enc.next().await {
+        progress?;
+    }
+
+    tokio::fs::rename(&tmp_output, output).await?;
+    temporary::unadd(&tmp_output);
+    Ok(())
+}
+
+/// Copy EXIF, XMP & ICC metadata from `input` to `output` using exiftool.
+async fn copy_metadata(input: &Path, output: &Path) -> anyhow::Result<()> {
+    let out = Command::new("exiftool")
+        .arg("-overwrite_original")
+        .arg("-quiet")
+        .arg2("-tagsFromFile", input)
+        .arg("-all:all")
+        .arg("-icc_profile")
+        .arg(output)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("exiftool")?;
+    ensure_success("exiftool", &out)
+}
+
+/// Batch totals.
+#[derive(Debug, Default)]
+struct Summary {
+    encoded: usize,
+    skipped: usize,
+    failed: usize,
+    input_size: u64,
+    output_size: u64,
+}
+
+impl Summary {
+    fn add(&mut self, image: &Image) {
+        self.encoded += 1;
+        self.input_size += image.input_size;
+        self.output_size += image.output_size;
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            encoded,
+            skipped,
+            failed,
+            input_size,
+            output_size,
+        } = *self;
+        write!(f, "Encoded {encoded} images")?;
+        if skipped > 0 {
+            write!(f, ", skipped {skipped}")?;
+        }
+        if failed > 0 {
+            write!(f, ", failed {failed}")?;
+        }
+        if encoded > 0 {
+            let saved = input_size.saturating_sub(output_size);
+            let percent = 100.0 * saved as f64 / input_size.max(1) as f64;
+            write!(
+                f,
+                ": {} -> {}, saved {} ({percent:.0}%)",
+                HumanBytes(input_size),
+                HumanBytes(output_size),
+                HumanBytes(saved),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn batch_output_paths() {
+    let root = Path::new("photos");
+    let input = Path::new("photos/2024/a.jpg");
+    assert_eq!(
+        output_path(root, input, None, "avif"),
+        Path::new("photos/2024/a.avif")
+    );
+    assert_eq!(
+        output_path(root, input, Some(Path::new("out")), "jxl"),
+        Path::new("out/2024/a.jxl")
+    );
+    assert_eq!(
+        output_path(input, input, Some(Path::new("out")), "jxl"),
+        Path::new("out/a.jxl")
+    );
+    assert_eq!(
+        output_path(root, Path::new("photos/b.avif"), None, "avif"),
+        Path::new("photos/b.new.avif")
+    );
+}
+
+#[test]
+fn batch_summary() {
+    let summary = Summary {
+        encoded: 2,
+        skipped: 1,
+        failed: 0,
+        input_size: 4 * 1024 * 1024,
+        output_size: 1024 * 1024,
+    };
+    assert_eq!(
+        summary.to_string(),
+        "Encoded 2 images, skipped 1: 4.00 MiB -> 1.00 MiB, saved 3.00 MiB (75%)"
+    );
+}
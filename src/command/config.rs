@@ -0,0 +1,59 @@
+use crate::config::{self, Profile};
+use anyhow::Context;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use std::borrow::Cow;
+
+/// Configuration file & profile commands.
+///
+/// Profiles are read from `~/.config/ab-av1/config.toml`, or the `--config` file.
+/// They set argument defaults, e.g. `--profile anime`, which command line args override.
+#[derive(Parser)]
+#[group(skip)]
+pub struct Args {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Print the effective arguments of a command, merging built-in defaults with `--profile`.
+    Show {
+        /// Command to show arguments of.
+        #[arg(long, default_value = "auto-encode")]
+        command: String,
+    },
+}
+
+pub fn config(Args { action }: Args, profile: Option<Profile>) -> anyhow::Result<()> {
+    match action {
+        Action::Show { command } => show(&command, profile.as_ref()),
+    }
+}
+
+fn show(command: &str, profile: Option<&Profile>) -> anyhow::Result<()> {
+    let mut cmd = config::with_config_args(crate::Command::command());
+    match profile {
+        Some(profile) => {
+            println!("# profile {} ({})", profile.name, profile.path.display());
+            cmd = config::apply_profile(cmd, profile)?;
+        }
+        None => println!("# no profile"),
+    }
+    let sub = cmd
+        .find_subcommand(command)
+        .with_context(|| format!("unknown command `{command}`"))?;
+    for arg in sub.get_arguments() {
+        let Some(long) = arg.get_long() else { continue };
+        if let ArgAction::SetTrue = arg.get_action() {
+            if arg.get_default_values().iter().any(|v| v == "true") {
+                println!("--{long}");
+            }
+            continue;
+        }
+        for value in arg.get_default_values() {
+            let value = shell_escape::escape(Cow::Owned(value.to_string_lossy().into_owned()));
+            println!("--{long} {value}");
+        }
+    }
+    Ok(())
+}
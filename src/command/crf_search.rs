@@ -1,23 +1,30 @@
+mod calibration;
 mod err;
+mod min_score;
+mod preflight;
 
 pub use err::Error;
+pub use min_score::MinScore;
+pub use preflight::Skip;
 
 use crate::{
     command::{
-        PROGRESS_CHARS, args,
-        sample_encode::{self, StdoutFormat, Work},
+        PROGRESS_CHARS, analyse_input, args,
+        sample_encode::{self, ScoreKind, StdoutFormat, Work},
     },
     console_ext::style,
     ffprobe::{self, Ffprobe},
     float::TerseF32,
+    script,
 };
 use anyhow::Context;
+use calibration::Calibration;
 use clap::{ArgAction, Parser};
 use console::style;
 use futures_util::{Stream, StreamExt};
 use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
 use log::info;
-use std::{io::IsTerminal, pin::pin, sync::Arc, time::Duration};
+use std::{fmt::Write, io::IsTerminal, pin::pin, sync::Arc, time::Duration};
 
 const BAR_LEN: u64 = 1024 * 1024 * 1024;
 const DEFAULT_MIN_VMAF: f32 = 95.0;
@@ -47,7 +54,7 @@ pub struct Args {
 }
 
 /// Search args shared with auto-encode.
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[group(skip)]
 pub struct SearchArgs {
     #[clap(flatten)]
@@ -55,29 +62,55 @@ pub struct SearchArgs {
 
     /// Desired min VMAF score to deliver.
     ///
-    /// [default: 95]
-    #[arg(long, group = "min_score")]
+    /// Min scores may be combined, e.g. `--min-vmaf 95 --min-xpsnr 42`, requiring all
+    /// to be delivered.
+    ///
+    /// [default: 95, if no other min score is set]
+    #[arg(long)]
     pub min_vmaf: Option<f32>,
 
     /// Desired min XPSNR score to deliver.
     ///
-    /// Enables use of XPSNR for score analysis instead of VMAF.
-    #[arg(long, group = "min_score")]
+    /// Enables use of XPSNR for score analysis, instead of VMAF unless --min-vmaf is also set.
+    #[arg(long)]
     pub min_xpsnr: Option<f32>,
 
+    /// Desired min SSIM score to deliver, using the ffmpeg ssim filter.
+    #[arg(long)]
+    pub min_ssim: Option<f32>,
+
+    /// Desired min MS-SSIM score to deliver, using the libvmaf float_ms_ssim feature.
+    #[arg(long)]
+    pub min_ms_ssim: Option<f32>,
+
+    /// Desired min PSNR score to deliver, using the ffmpeg psnr filter.
+    #[arg(long)]
+    pub min_psnr: Option<f32>,
+
+    /// Desired min SSIMULACRA2 score to deliver.
+    ///
+    /// Requires the libjxl `ssimulacra2` tool.
+    #[arg(long)]
+    pub min_ssimulacra2: Option<f32>,
+
+    /// Desired min VMAF score percentile of all sample frames, e.g. `5=88` requires the
+    /// 5th percentile frame VMAF to be at least 88.
+    #[arg(long, value_parser = MinScore::parse_vmaf_percentile)]
+    pub min_vmaf_percentile: Option<MinScore>,
+
     /// Maximum desired encoded size percentage of the input size.
     #[arg(long, default_value_t = 80.0)]
     pub max_encoded_percent: f32,
 
     /// Minimum (highest quality) crf value to try.
     ///
-    /// [default: 10, 5 for svt-av1, 2 for mpeg2video]
+    /// [default: 10, 5 for svt-av1, 2 for mpeg2video, 0.5 for libjxl]
     #[arg(long)]
     pub min_crf: Option<f32>,
 
     /// Maximum (lowest quality) crf value to try.
     ///
-    /// [default: 55, 46 for x264,x265, 255 for rav1e,av1_vaapi, 30 for mpeg2video]
+    /// [default: 55, 46 for x264,x265, 255 for rav1e,av1_vaapi, 30 for mpeg2video, 10 for libjxl]
     #[arg(long)]
     pub max_crf: Option<f32>,
 
@@ -90,7 +123,7 @@ pub struct SearchArgs {
 
     /// Constant rate factor search increment precision.
     ///
-    /// [default: 1.0, 0.1 for x264,x265,vp9]
+    /// [default: 1.0, 0.1 for x264,x265,vp9,libjxl]
     #[arg(long)]
     pub crf_increment: Option<f32>,
 
@@ -110,6 +143,29 @@ pub struct SearchArgs {
     )]
     pub cache: bool,
 
+    /// Skip the search, succeeding without a crf, if the input is not expected
+    /// to re-encode within --max-encoded-percent.
+    ///
+    /// Skips inputs already using av1, hevc, vp9 or vvc at a low bits per pixel, under 0.05
+    /// for the default --max-encoded-percent, scaled inversely with it, e.g. 0.1 for 40%.
+    #[arg(long)]
+    pub skip_efficient: bool,
+
+    /// With --skip-efficient first encode a single sample at the lowest quality crf,
+    /// skipping if it is still larger than --max-encoded-percent.
+    #[arg(long, requires = "skip_efficient")]
+    pub skip_efficient_sample: bool,
+
+    /// Score search iterations with faster, approximate, VMAF. Every 4th frame is scored
+    /// (libvmaf `n_subsample`) without `auto` --vmaf-scale upscaling.
+    ///
+    /// The first crf samples are scored both fast & at full fidelity to calibrate the offset
+    /// between fast & full scores for the input, which corrects later fast scores. The final
+    /// candidate crf is always re-scored at full fidelity before it is accepted, also adding
+    /// a calibration point.
+    #[arg(long)]
+    pub fast_score: bool,
+
     #[clap(flatten)]
     pub sample: args::Sample,
 
@@ -127,8 +183,53 @@ pub struct SearchArgs {
 }
 
 impl SearchArgs {
+    /// Returns all min scores to deliver, by default VMAF 95. The first is the main score.
+    pub fn min_scores(&self) -> Vec<MinScore> {
+        let mut min_scores: Vec<_> = [
+            (ScoreKind::Vmaf, self.min_vmaf),
+            (ScoreKind::Xpsnr, self.min_xpsnr),
+            (ScoreKind::Ssim, self.min_ssim),
+            (ScoreKind::MsSsim, self.min_ms_ssim),
+            (ScoreKind::Psnr, self.min_psnr),
+            (ScoreKind::Ssimulacra2, self.min_ssimulacra2),
+        ]
+        .into_iter()
+        .filter_map(|(kind, min)| Some(MinScore::mean(kind, min?)))
+        .chain(self.min_vmaf_percentile)
+        .collect();
+        if min_scores.is_empty() {
+            min_scores.push(MinScore::mean(ScoreKind::Vmaf, DEFAULT_MIN_VMAF));
+        }
+        min_scores
+    }
+
+    /// Returns the main min score.
+    pub fn main_min_score(&self) -> MinScore {
+        self.min_scores()[0]
+    }
+
     pub fn min_score(&self) -> f32 {
-        self.min_vmaf.or(self.min_xpsnr).unwrap_or(DEFAULT_MIN_VMAF)
+        self.main_min_score().min
+    }
+
+    /// Set the main min score.
+    pub fn set_min_score(&mut self, score: f32) {
+        let main = self.main_min_score();
+        if main.percentile.is_some() {
+            if let Some(p) = &mut self.min_vmaf_percentile {
+                p.min = score;
+            }
+            return;
+        }
+        let min = match main.kind {
+            ScoreKind::Xpsnr => &mut self.min_xpsnr,
+            ScoreKind::Ssim => &mut self.min_ssim,
+            ScoreKind::MsSsim => &mut self.min_ms_ssim,
+            ScoreKind::Psnr => &mut self.min_psnr,
+            ScoreKind::Ssimulacra2 => &mut self.min_ssimulacra2,
+            _ => &mut self.min_vmaf,
+        };
+        *min = Some(score);
     }
 
     pub fn validate(&self) -> anyhow::Result<()> {
@@ -141,7 +242,11 @@ impl SearchArgs {
         {
             anyhow::bail!("Invalid use of --vmaf NUMBER, did you mean: --min-vmaf {num}");
         }
-        Ok(())
+        anyhow::ensure!(
+            !self.fast_score || self.min_scores().iter().any(|m| m.kind == ScoreKind::Vmaf),
+            "--fast-score requires a VMAF min score"
+        );
+        self.args.validate_fps()
     }
 }
 
@@ -160,13 +265,14 @@ pub async fn crf_search(
     );
     bar.enable_steady_tick(Duration::from_millis(100));
 
-    let probe = ffprobe::probe(&search.args.input);
+    let probe = ffprobe::probe(&search.args.input, search.args.fps);
+    analyse_input(&mut search.args, Some(&mut search.score), &probe, &bar).await?;
     let input_is_image = probe.is_image;
     search
         .sample
         .set_extension_from_input(&search.args.input, &search.args.encoder, &probe);
 
-    let min_score = search.min_score();
+    let min_scores = search.min_scores();
     let max_encoded_percent = search.max_encoded_percent;
     let thorough = search.thorough;
     let enc_args = search.args.clone();
@@ -176,7 +282,7 @@ pub async fn crf_search(
     while let Some(update) = run.next().await {
         let update = update.inspect_err(|e| {
             if let Error::NoGoodCrf { last } = e {
-                last.print_attempt(&bar, min_score, max_encoded_percent);
+                last.print_attempt(&bar, &min_scores, max_encoded_percent);
                 if let StdoutFormat::Json = stdout_format {
                     println!("{}", error_json(e));
                 }
@@ -226,7 +332,18 @@ pub async fn crf_search(
                     println!("{}", sample.enc.sample_encode_done_json(sample.crf));
                 }
             }
-            Update::RunResult(result) => result.print_attempt(&bar, min_score, max_encoded_percent),
+            Update::RunResult(result) => {
+                result.print_attempt(&bar, &min_scores, max_encoded_percent)
+            }
+            Update::Skipped(skip) => {
+                info!("skipped: {skip}");
+                bar.finish_with_message("");
+                match stdout_format {
+                    StdoutFormat::Human => println!("Skipped: {skip}"),
+                    StdoutFormat::Json => println!("{}", skipped_json(&skip)),
+                }
+                return Ok(());
+            }
             Update::Done(best) => {
                 info!("crf {} successful", best.crf);
                 bar.finish_with_message("");
@@ -238,7 +355,7 @@ pub async fn crf_search(
                     );
                 }
                 match stdout_format {
-                    StdoutFormat::Human => best.print_result_human(input_is_image),
+                    StdoutFormat::Human => best.print_result_human(&min_scores, input_is_image),
                     StdoutFormat::Json => println!("{}", best.done_json()),
                 }
                 return Ok(());
@@ -249,10 +366,27 @@ pub async fn crf_search(
 }
 
 pub fn run(
-    SearchArgs {
-        args,
-        min_vmaf,
-        min_xpsnr,
+    search: SearchArgs,
+    input_probe: Arc<Ffprobe>,
+) -> impl Stream<Item = Result<Update, Error>> {
+    // by default use vmaf 95, otherwise use whatever is specified
+    let min_scores = search.min_scores();
+    let mut score_kinds = vec![];
+    for MinScore { kind, .. } in &min_scores {
+        if !score_kinds.contains(kind) {
+            score_kinds.push(*kind);
+        }
+    }
+    let vmaf_frames = min_scores.iter().any(|m| m.percentile.is_some());
+    let SearchArgs {
+        mut args,
+        min_vmaf: _,
+        min_xpsnr: _,
+        min_ssim: _,
+        min_ms_ssim: _,
+        min_psnr: _,
+        min_ssimulacra2: _,
+        min_vmaf_percentile: _,
         max_encoded_percent,
         min_crf,
         max_crf,
@@ -261,22 +395,22 @@ pub fn run(
         thorough,
         sample,
         cache,
+        skip_efficient,
+        skip_efficient_sample,
+        fast_score,
         vmaf,
         score,
         xpsnr,
         verbose: _,
-    }: SearchArgs,
-    input_probe: Arc<Ffprobe>,
-) -> impl Stream<Item = Result<Update, Error>> {
+    } = search;
+
     async_stream::try_stream! {
         let default_max_crf = args.encoder.default_max_crf();
         let max_crf = max_crf.unwrap_or(default_max_crf);
         let default_min_crf = args.encoder.default_min_crf();
         let min_crf = min_crf.unwrap_or(default_min_crf);
         Error::ensure_other(min_crf < max_crf, "Invalid --min-crf & --max-crf")?;
-        // by default use vmaf 95, otherwise use whatever is specified
-        let min_score = min_vmaf.or(min_xpsnr).unwrap_or(DEFAULT_MIN_VMAF);
-
+        args.validate_encoder(&[min_crf, max_crf]).await?;
         // Whether to make the 2nd iteration on the ~20%/~80% crf point instead of the min/max to
         // improve interpolation by narrowing the crf range a 20% (or 30%) subrange.
         //
@@ -308,11 +442,56 @@ pub fn run(
             stdout_format: sample_encode::StdoutFormat::Json,
             vmaf: vmaf.clone(),
             score: score.clone(),
-            xpsnr: min_xpsnr.is_some(),
+            xpsnr: false,
             xpsnr_opts: xpsnr,
+            metric: score_kinds,
+            vmaf_frames,
+            fast_vmaf: None,
         };
 
-        let mut crf_attempts = Vec::new();
+        if skip_efficient {
+            let input_size = script::input_size(&args.args.input).await?;
+            if let Some(skip) = preflight::check_probe(&input_probe, input_size, max_encoded_percent) {
+                yield Update::Skipped(skip);
+                return;
+            }
+        }
+        if skip_efficient_sample {
+            let mut test_args = args.clone();
+            test_args.crf = q_conv.crf(max_q);
+            test_args.sample.samples = Some(1);
+            test_args.sample.min_samples = None;
+
+            let mut sample_enc = pin!(sample_encode::run(test_args.clone(), input_probe.clone()));
+            let mut sample_enc_output = None;
+            while let Some(update) = sample_enc.next().await {
+                match update? {
+                    sample_encode::Update::Status(status) => {
+                        yield Update::Status { crf_run: 1, crf: test_args.crf, sample: status };
+                    }
+                    sample_encode::Update::SampleResult { sample, result } => {
+                        yield Update::SampleResult { crf: test_args.crf, sample, result };
+                    }
+                    sample_encode::Update::Done(output) => sample_enc_output = Some(output),
+                }
+            }
+            let output = sample_enc_output.context("no sample output?")?;
+            if output.encode_percent > max_encoded_percent as _ {
+                yield Update::Skipped(Skip::SampleTooLarge {
+                    crf: test_args.crf,
+                    encode_percent: output.encode_percent,
+                });
+                return;
+            }
+        }
+
+        let mut crf_attempts: Vec<Sample> = Vec::new();
+        let fast_vmaf = fast_score.then(|| vmaf.fast());
+        let mut calibration = Calibration::default();
+        // uncorrected fast outputs by q, re-corrected as the calibration improves
+        let mut fast_outputs: Vec<(i64, sample_encode::Output)> = Vec::new();
+        // q of a fast scored candidate to re-score at full fidelity
+        let mut rescore = None;
 
         for run in 1.. {
             // how much we're prepared to go higher than the min-vmaf
@@ -324,6 +503,15 @@ pub fn run(
             };
             args.crf = q_conv.crf(q);
 
+            // fast score, unless re-scoring. Full score when not fast, when re-scoring & on the
+            // first run, which is also fast scored to calibrate
+            let fast_only = run > 1 && rescore != Some(q) && fast_vmaf.is_some();
+            args.vmaf = match &fast_vmaf {
+                Some(fast) if fast_only => fast.clone(),
+                _ => vmaf.clone(),
+            };
+            args.fast_vmaf = fast_vmaf.clone().filter(|_| run == 1);
+
             let mut sample_enc = pin!(sample_encode::run(args.clone(), input_probe.clone()));
             let mut sample_enc_output = None;
             while let Some(update) = sample_enc.next().await {
@@ -337,41 +525,60 @@ pub fn run(
                     sample_encode::Update::Done(output) => sample_enc_output = Some(output),
                 }
             }
+            let output = sample_enc_output.context("no sample output?")?;
 
-            let sample = Sample {
-                crf: args.crf,
-                q,
-                enc: sample_enc_output.context("no sample output?")?,
+            let sample = match fast_only {
+                true => {
+                    fast_outputs.push((q, output.clone()));
+                    Sample { crf: args.crf, q, enc: calibration.apply(q, output), fast: true }
+                }
+                false => {
+                    // calibrate using the first run fast pass, or the fast attempt re-scored
+                    let fast = output.fast_vmaf.or_else(|| {
+                        let (_, fast) = fast_outputs.iter().find(|(fast_q, _)| *fast_q == q)?;
+                        fast.scores.get(&ScoreKind::Vmaf).copied()
+                    });
+                    if let (Some(fast), Some(full)) = (fast, output.scores.get(&ScoreKind::Vmaf)) {
+                        calibration.add(q, fast, *full);
+                        for attempt in crf_attempts.iter_mut().filter(|s| s.fast) {
+                            if let Some((_, fast)) = fast_outputs.iter().find(|(q, _)| *q == attempt.q) {
+                                attempt.enc = calibration.apply(attempt.q, fast.clone());
+                            }
+                        }
+                    }
+                    Sample { crf: args.crf, q, enc: output, fast: false }
+                }
             };
-            let score = sample.enc.single_score();
+            // a re-scored sample replaces the fast attempt
+            crf_attempts.retain(|s| s.q != q);
             crf_attempts.push(sample.clone());
             yield Update::SampleEncodeDone(sample.clone());
             let sample_small_enough = sample.enc.encode_percent <= max_encoded_percent as _;
 
-            if score > min_score {
+            // the final result, if found
+            let mut best = None;
+            if min_scores.iter().all(|m| m.passes(&sample.enc)) {
                 // good
-                if sample_small_enough && score < min_score + higher_tolerance {
-                    yield Update::Done(sample);
-                    return;
-                }
+                let within_tolerance = min_scores
+                    .iter()
+                    .any(|m| m.score(&sample.enc) < m.min + higher_tolerance);
                 let u_bound = crf_attempts
                     .iter()
                     .filter(|s| s.q > sample.q)
                     .min_by_key(|s| s.q);
 
                 match u_bound {
+                    _ if sample_small_enough && within_tolerance => best = Some(sample.clone()),
                     Some(upper) if upper.q == sample.q + 1 => {
                         Error::ensure_or_no_good_crf(sample_small_enough, &sample)?;
-                        yield Update::Done(sample);
-                        return;
+                        best = Some(sample.clone());
                     }
                     Some(upper) => {
-                        q = vmaf_lerp_q(min_score, upper, &sample);
+                        q = lerp_q(&min_scores, upper, &sample);
                     }
                     None if sample.q == max_q => {
                         Error::ensure_or_no_good_crf(sample_small_enough, &sample)?;
-                        yield Update::Done(sample);
-                        return;
+                        best = Some(sample.clone());
                     }
                     None if cut_on_iter2 && run == 1 && sample.q + 1 < max_q => {
                         q = (sample.q as f32 * 0.4 + max_q as f32 * 0.6).round() as _;
@@ -392,12 +599,10 @@ pub fn run(
                 match l_bound {
                     Some(lower) if lower.q + 1 == sample.q => {
                         Error::ensure_or_no_good_crf(lower.enc.encode_percent <= max_encoded_percent as _, &sample)?;
-                        yield Update::RunResult(sample.clone());
-                        yield Update::Done(lower.clone());
-                        return;
+                        best = Some(lower.clone());
                     }
                     Some(lower) => {
-                        q = vmaf_lerp_q(min_score, &sample, lower);
+                        q = lerp_q(&min_scores, &sample, lower);
                     }
                     None if cut_on_iter2 && run == 1 && sample.q > min_q + 1 => {
                         q = (sample.q as f32 * 0.4 + min_q as f32 * 0.6).round() as _;
@@ -405,6 +610,22 @@ pub fn run(
                     None => q = min_q,
                 };
             }
+
+            match best {
+                // re-score a fast scored candidate at full fidelity before accepting it
+                Some(best) if best.fast => {
+                    q = best.q;
+                    rescore = Some(q);
+                }
+                Some(best) => {
+                    if best.q != sample.q {
+                        yield Update::RunResult(sample);
+                    }
+                    yield Update::Done(best);
+                    return;
+                }
+                None => {}
+            }
             yield Update::RunResult(sample.clone());
         }
         unreachable!();
@@ -416,17 +637,27 @@ pub struct Sample {
     pub enc: sample_encode::Output,
     pub crf: f32,
     q: i64,
+    /// Scored with calibrated `--fast-score` VMAF.
+    fast: bool,
 }
 
 impl Sample {
-    pub fn print_attempt(&self, bar: &ProgressBar, min_score: f32, max_encoded_percent: f32) {
+    pub fn print_attempt(
+        &self,
+        bar: &ProgressBar,
+        min_scores: &[MinScore],
+        max_encoded_percent: f32,
+    ) {
         if bar.is_hidden() {
+            let mut scores = String::new();
+            for m in min_scores {
+                _ = write!(scores, " {m} {:.*}", m.kind.decimals(), m.score(&self.enc));
+            }
             info!(
-                "crf {} {} {:.2} ({:.0}%){}",
+                "crf {}{scores} ({:.0}%){}{}",
                 TerseF32(self.crf),
-                self.enc.single_score_kind(),
-                self.enc.single_score(),
                 self.enc.encode_percent,
+                if self.fast { " (fast)" } else { "" },
                 if self.enc.from_cache { " (cache)" } else { "" }
             );
             return;
@@ -435,36 +666,81 @@ impl Sample {
         let crf_label = style("- crf").dim();
         let mut crf = style(TerseF32(self.crf));
 
-        let score_v = self.enc.single_score();
-        let mut score = style(score_v);
-        let score_label = style(self.enc.single_score_kind()).dim();
+        let mut scores = String::new();
+        for m in min_scores {
+            let score_v = m.score(&self.enc);
+            let mut score = style(score_v);
+            if score_v < m.min {
+                crf = crf.red().bright();
+                score = score.red().bright();
+            }
+            let decimals = m.kind.decimals();
+            _ = write!(scores, " {} {score:.decimals$}", style(m).dim());
+        }
         let mut percent = style!("{:.0}%", self.enc.encode_percent);
         let open = style("(").dim();
         let close = style(")").dim();
+        let fast_msg = match self.fast {
+            true => style(" (fast)").dim(),
+            false => style(""),
+        };
         let cache_msg = match self.enc.from_cache {
             true => style(" (cache)").dim(),
             false => style(""),
         };
 
-        if score_v < min_score {
-            crf = crf.red().bright();
-            score = score.red().bright();
-        }
         if self.enc.encode_percent > max_encoded_percent as _ {
             crf = crf.red().bright();
             percent = percent.red().bright();
         }
 
         bar.println(format!(
-            "{crf_label} {crf} {score_label} {score:.2} {open}{percent}{close}{cache_msg}"
+            "{crf_label} {crf}{scores} {open}{percent}{close}{fast_msg}{cache_msg}"
         ));
     }
 
-    pub fn print_result_human(&self, image: bool) {
+    /// Returns how far this attempt is from meeting the constraints, 0 if it does.
+    ///
+    /// The sum of each min score shortfall & the encoded percent excess, each relative to
+    /// its constraint.
+    pub fn deficit(&self, min_scores: &[MinScore], max_encoded_percent: f32) -> f32 {
+        let scores: f32 = min_scores
+            .iter()
+            .map(|m| ((m.min - m.score(&self.enc)) / m.min.abs().max(f32::EPSILON)).max(0.0))
+            .sum();
+        let size = ((self.enc.encode_percent as f32 - max_encoded_percent)
+            / max_encoded_percent.max(f32::EPSILON))
+        .max(0.0);
+        scores + size
+    }
+
+    /// Returns each min score, e.g. "VMAF 95.12, XPSNR 42.10", with failures in red.
+    pub fn scores_summary(&self, min_scores: &[MinScore]) -> String {
+        let mut summary = String::new();
+        for m in min_scores {
+            if !summary.is_empty() {
+                summary.push_str(", ");
+            }
+            let score_v = m.score(&self.enc);
+            let mut score = style(score_v);
+            if score_v < m.min {
+                score = score.red();
+            }
+            let decimals = m.kind.decimals();
+            _ = write!(summary, "{m} {score:.decimals$}");
+        }
+        summary
+    }
+
+    pub fn print_result_human(&self, min_scores: &[MinScore], image: bool) {
         let crf = style(TerseF32(self.crf)).bold().green();
         let enc = &self.enc;
-        let score = style(enc.single_score()).bold().green();
-        let score_kind = enc.single_score_kind();
+        let mut scores = String::new();
+        for m in min_scores {
+            let decimals = m.kind.decimals();
+            let score = style(m.score(enc)).bold().green();
+            _ = write!(scores, " {m} {score:.decimals$}");
+        }
         let size = style(HumanBytes(enc.predicted_encode_size)).bold().green();
         let percent = style!("{}%", enc.encode_percent.round()).bold().green();
         let time = style(HumanDuration(enc.predicted_encode_time)).bold();
@@ -473,7 +749,7 @@ impl Sample {
             false => "video stream",
         };
         println!(
-            "crf {crf} {score_kind} {score:.2} predicted {enc_description} size {size} ({percent}) taking {time}"
+            "crf {crf}{scores} predicted {enc_description} size {size} ({percent}) taking {time}"
         );
     }
 
@@ -485,6 +761,14 @@ impl Sample {
     }
 }
 
+/// `crf-search-skipped` json message, see _stdout-format-json.md_.
+fn skipped_json(skip: &Skip) -> serde_json::Value {
+    serde_json::json!({
+        "type": "crf-search-skipped",
+        "reason": skip.to_string(),
+    })
+}
+
 /// `crf-search-error` json message, see _stdout-format-json.md_.
 fn error_json(err: &Error) -> serde_json::Value {
     serde_json::json!({
@@ -497,8 +781,9 @@ fn error_json(err: &Error) -> serde_json::Value {
 fn test_sample() -> Sample {
     Sample {
         enc: sample_encode::Output {
-            vmaf_score: Some(95.5),
-            xpsnr_score: None,
+            scores: [(ScoreKind::Vmaf, 95.5)].into(),
+            vmaf_frames: vec![],
+            fast_vmaf: None,
             predicted_encode_size: 38889644,
             encode_percent: 41.25,
             predicted_encode_time: Duration::from_secs(1560),
@@ -506,6 +791,7 @@ fn test_sample() -> Sample {
         },
         crf: 34.0,
         q: 34,
+        fast: false,
     }
 }
 
@@ -528,12 +814,38 @@ fn error_json_message() {
     );
 }
 
+#[test]
+fn skipped_json_message() {
+    let skip = Skip::SampleTooLarge {
+        crf: 55.0,
+        encode_percent: 91.2,
+    };
+    assert_eq!(
+        skipped_json(&skip).to_string(),
+        r#"{"reason":"sample at crf 55 is already 91% of the input","type":"crf-search-skipped"}"#
+    );
+}
+
 #[test]
 fn parse_stdout_format() {
     Args::try_parse_from(["crf-search", "-i", "vid.mkv", "--stdout-format", "json"])
         .expect("--stdout-format json should parse");
 }
 
+/// Produce a q value between given samples interpolating each min score `worse_q` fails
+/// to deliver. Uses the most binding, i.e. lowest, q.
+fn lerp_q(min_scores: &[MinScore], worse_q: &Sample, better_q: &Sample) -> i64 {
+    min_scores
+        .iter()
+        .filter(|m| {
+            let worse = m.score(&worse_q.enc);
+            worse <= m.min && worse < m.score(&better_q.enc)
+        })
+        .map(|m| vmaf_lerp_q(m, worse_q, better_q))
+        .min()
+        .unwrap_or_else(|| ((worse_q.q + better_q.q) / 2).clamp(better_q.q + 1, worse_q.q - 1))
+}
+
 /// Produce a q value between given samples using vmaf score linear interpolation
 /// so the output q value should produce the `min_vmaf`.
 ///
@@ -545,22 +857,41 @@ fn parse_stdout_format() {
 /// though it seems to work better than a binary search.
 /// Perhaps a better approximation of a general crf->vmaf model could be found.
 /// This would be helpful particularly for small crf-increments.
-fn vmaf_lerp_q(min_vmaf: f32, worse_q: &Sample, better_q: &Sample) -> i64 {
+fn vmaf_lerp_q(min_vmaf: &MinScore, worse_q: &Sample, better_q: &Sample) -> i64 {
+    let worse_vmaf = min_vmaf.score(&worse_q.enc);
+    let better_vmaf = min_vmaf.score(&better_q.enc);
     assert!(
-        worse_q.enc.single_score() <= min_vmaf
-            && worse_q.enc.single_score() < better_q.enc.single_score()
-            && worse_q.q > better_q.q,
-        "invalid vmaf_lerp_crf usage: ({min_vmaf}, {worse_q:?}, {better_q:?})"
+        worse_vmaf <= min_vmaf.min && worse_vmaf < better_vmaf && worse_q.q > better_q.q,
+        "invalid vmaf_lerp_crf usage: ({min_vmaf:?}, {worse_q:?}, {better_q:?})"
     );
 
-    let vmaf_diff = better_q.enc.single_score() - worse_q.enc.single_score();
-    let vmaf_factor = (min_vmaf - worse_q.enc.single_score()) / vmaf_diff;
+    let vmaf_diff = better_vmaf - worse_vmaf;
+    let vmaf_factor = (min_vmaf.min - worse_vmaf) / vmaf_diff;
 
     let q_diff = worse_q.q - better_q.q;
     let lerp = (worse_q.q as f32 - q_diff as f32 * vmaf_factor).round() as i64;
     lerp.clamp(better_q.q + 1, worse_q.q - 1)
 }
 
+/// The most binding failing constraint, needing the best quality, drives the next q.
+#[test]
+fn lerp_q_most_binding() {
+    let sample = |q, vmaf, xpsnr| {
+        let mut s = test_sample();
+        s.q = q;
+        s.enc.scores = [(ScoreKind::Vmaf, vmaf), (ScoreKind::Xpsnr, xpsnr)].into();
+        s
+    };
+    let worse = sample(40, 94.0, 40.0);
+    let better = sample(30, 96.0, 44.0);
+
+    let vmaf = MinScore::mean(ScoreKind::Vmaf, 95.0);
+    let xpsnr = MinScore::mean(ScoreKind::Xpsnr, 43.0);
+    assert_eq!(lerp_q(&[vmaf], &worse, &better), 35);
+    assert_eq!(lerp_q(&[xpsnr], &worse, &better), 33);
+    assert_eq!(lerp_q(&[vmaf, xpsnr], &worse, &better), 33);
+}
+
 /// sample_progress: [0, 1]
 pub fn guess_progress(run: usize, sample_progress: f32, thorough: bool) -> f64 {
     let total_runs_guess = match () {
@@ -670,4 +1001,24 @@ pub enum Update {
     /// Run result (excludes successful final runs)
     RunResult(Sample),
     Done(Sample),
+    /// Search skipped by `--skip-efficient` pre-flight checks, ends the search.
+    Skipped(Skip),
+}
+
+#[test]
+fn sample_deficit() {
+    let sample = |vmaf, encode_percent| {
+        let mut s = test_sample();
+        s.enc.scores = [(ScoreKind::Vmaf, vmaf)].into();
+        s.enc.encode_percent = encode_percent;
+        s
+    };
+    let min_scores = [MinScore::mean(ScoreKind::Vmaf, 95.0)];
+
+    assert_eq!(sample(96.0, 50.0).deficit(&min_scores, 80.0), 0.0);
+    // 1% vmaf shortfall is closer than a 10% size excess
+    let low_vmaf = sample(94.05, 50.0).deficit(&min_scores, 80.0);
+    let too_large = sample(96.0, 88.0).deficit(&min_scores, 80.0);
+    assert!((low_vmaf - 0.01).abs() < 1e-4, "{low_vmaf}");
+    assert!((too_large - 0.1).abs() < 1e-4, "{too_large}");
 }
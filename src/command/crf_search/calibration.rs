@@ -0,0 +1,68 @@
+use crate::command::sample_encode::{Output, ScoreKind};
+
+/// Learned offsets between `--fast-score` & full fidelity VMAF scores of the same crf,
+/// used to correct fast scores of the input.
+#[derive(Debug, Default)]
+pub struct Calibration {
+    /// `(q, full - fast)` VMAF offsets.
+    points: Vec<(i64, f32)>,
+}
+
+impl Calibration {
+    /// Add a calibration point from fast & full VMAF scores of the same `q`.
+    pub fn add(&mut self, q: i64, fast: f32, full: f32) {
+        self.points.retain(|(point_q, _)| *point_q != q);
+        self.points.push((q, full - fast));
+    }
+
+    /// Returns the offset of the calibration point nearest `q`, or 0 if uncalibrated.
+    pub fn offset(&self, q: i64) -> f32 {
+        self.points
+            .iter()
+            .min_by_key(|(point_q, _)| point_q.abs_diff(q))
+            .map_or(0.0, |(_, offset)| *offset)
+    }
+
+    /// Returns the fast output with VMAF scores corrected using the offset for `q`.
+    pub fn apply(&self, q: i64, mut fast: Output) -> Output {
+        let offset = self.offset(q);
+        if let Some(vmaf) = fast.scores.get_mut(&ScoreKind::Vmaf) {
+            *vmaf += offset;
+        }
+        for frame in &mut fast.vmaf_frames {
+            *frame += offset;
+        }
+        fast
+    }
+}
+
+#[test]
+fn calibrate_nearest() {
+    use std::time::Duration;
+
+    let output = |vmaf| Output {
+        scores: [(ScoreKind::Vmaf, vmaf)].into(),
+        vmaf_frames: vec![vmaf - 1.0, vmaf],
+        fast_vmaf: None,
+        predicted_encode_size: 1000,
+        encode_percent: 50.0,
+        predicted_encode_time: Duration::from_secs(10),
+        from_cache: false,
+    };
+
+    let mut calibration = Calibration::default();
+    assert_eq!(calibration.offset(30), 0.0);
+
+    calibration.add(30, 96.0, 95.0);
+    calibration.add(40, 92.0, 90.5);
+    assert_eq!(calibration.offset(20), -1.0);
+    assert_eq!(calibration.offset(38), -1.5);
+
+    let fast = calibration.apply(33, output(95.5));
+    assert_eq!(fast.scores[&ScoreKind::Vmaf], 94.5);
+    assert_eq!(fast.vmaf_frames, [93.5, 94.5]);
+
+    // re-calibrating a q replaces the point
+    calibration.add(30, 96.0, 95.5);
+    assert_eq!(calibration.offset(30), -0.5);
+}
@@ -0,0 +1,112 @@
+use crate::{
+    command::sample_encode::{self, ScoreKind},
+    float::TerseF32,
+    provenance::SearchScore,
+};
+use anyhow::Context;
+use std::{collections::BTreeMap, fmt};
+
+/// A min score the search must deliver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MinScore {
+    pub kind: ScoreKind,
+    /// Use this percentile of all sample frame scores instead of the mean. VMAF only.
+    pub percentile: Option<f32>,
+    pub min: f32,
+}
+
+impl MinScore {
+    pub fn mean(kind: ScoreKind, min: f32) -> Self {
+        Self {
+            kind,
+            percentile: None,
+            min,
+        }
+    }
+
+    /// Parse `--min-vmaf-percentile` values, e.g. "5=88".
+    pub fn parse_vmaf_percentile(arg: &str) -> anyhow::Result<Self> {
+        let (percentile, min) = arg.split_once('=').context("expected PERCENTILE=SCORE")?;
+        let percentile: f32 = percentile.trim().parse().context("invalid percentile")?;
+        anyhow::ensure!(
+            (0.0..=100.0).contains(&percentile),
+            "percentile must be in 0..=100"
+        );
+        Ok(Self {
+            kind: ScoreKind::Vmaf,
+            percentile: Some(percentile),
+            min: min.trim().parse().context("invalid score")?,
+        })
+    }
+
+    /// Returns the score of `enc` this min applies to.
+    pub fn score(&self, enc: &sample_encode::Output) -> f32 {
+        self.score_of(&enc.scores, &enc.vmaf_frames)
+    }
+
+    /// Returns the score this min applies to from mean `scores` & sorted `vmaf_frames`.
+    pub fn score_of(&self, scores: &BTreeMap<ScoreKind, f32>, vmaf_frames: &[f32]) -> f32 {
+        match self.percentile {
+            Some(p) => sample_encode::percentile(vmaf_frames, p),
+            None => scores.get(&self.kind).copied(),
+        }
+        .unwrap_or_default()
+    }
+
+    /// Returns this min & the score of `enc` for provenance.
+    pub fn search_score(&self, enc: &sample_encode::Output) -> SearchScore {
+        SearchScore {
+            kind: self.to_string(),
+            min: self.min,
+            score: self.score(enc),
+        }
+    }
+
+    /// Whether `enc` delivers this min score.
+    pub fn passes(&self, enc: &sample_encode::Output) -> bool {
+        self.score(enc) > self.min
+    }
+}
+
+/// Label, e.g. "VMAF" or "VMAF p5".
+impl fmt::Display for MinScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.percentile {
+            Some(p) => write!(f, "{} p{}", self.kind, TerseF32(p)),
+            None => self.kind.fmt(f),
+        }
+    }
+}
+
+#[test]
+fn parse_vmaf_percentile() {
+    let min = MinScore::parse_vmaf_percentile("5=88").unwrap();
+    assert_eq!(
+        min,
+        MinScore {
+            kind: ScoreKind::Vmaf,
+            percentile: Some(5.0),
+            min: 88.0,
+        }
+    );
+    assert_eq!(min.to_string(), "VMAF p5");
+
+    assert!(MinScore::parse_vmaf_percentile("88").is_err());
+    assert!(MinScore::parse_vmaf_percentile("101=88").is_err());
+}
+
+#[test]
+fn score_of_mean_and_percentile() {
+    let scores = [(ScoreKind::Vmaf, 95.5), (ScoreKind::Xpsnr, 42.5)].into();
+    let frames: Vec<_> = (0..=100).map(|s| s as f32).collect();
+
+    let xpsnr = MinScore::mean(ScoreKind::Xpsnr, 42.0);
+    assert_eq!(xpsnr.score_of(&scores, &frames), 42.5);
+    let p5 = MinScore::parse_vmaf_percentile("5=88").unwrap();
+    assert_eq!(p5.score_of(&scores, &frames), 5.0);
+    // missing scores are 0
+    assert_eq!(
+        MinScore::mean(ScoreKind::Ssim, 0.9).score_of(&scores, &[]),
+        0.0
+    );
+}
@@ -0,0 +1,108 @@
+//! `--skip-efficient` pre-flight checks.
+use crate::{
+    ffprobe::{Ffprobe, StreamKind},
+    float::TerseF32,
+};
+use std::fmt;
+
+/// Video codecs efficient enough that low bitrate inputs are not worth re-encoding.
+const EFFICIENT_CODECS: &[&str] = &["av1", "hevc", "vp9", "vvc"];
+
+/// Bits per pixel per frame a re-encode of an efficient codec input is expected to need
+/// at least, e.g. 1080p 24fps at ~2Mb/s.
+///
+/// So inputs under `MIN_ENCODED_BPP * 100 / max_encoded_percent` are not expected to
+/// re-encode within `--max-encoded-percent`. E.g. under 0.05 bpp for the default 80%.
+const MIN_ENCODED_BPP: f64 = 0.04;
+
+/// Reason a crf-search is skipped as re-encoding isn't expected to meet --max-encoded-percent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Skip {
+    /// Input already uses an efficient codec at a low bitrate.
+    Efficient { codec: String, bpp: f64 },
+    /// A single sample encoded at the lowest quality crf is too large.
+    SampleTooLarge { crf: f32, encode_percent: f64 },
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Efficient { codec, bpp } => {
+                write!(f, "input is already {codec} at {bpp:.3} bits per pixel")
+            }
+            Self::SampleTooLarge {
+                crf,
+                encode_percent,
+            } => write!(
+                f,
+                "sample at crf {} is already {encode_percent:.0}% of the input",
+                TerseF32(*crf)
+            ),
+        }
+    }
+}
+
+/// Returns a [`Skip::Efficient`] if the probed input already uses an efficient codec
+/// at a bitrate too low to re-encode within `max_encoded_percent`.
+///
+/// Bitrate is calculated from the whole `input_size` so includes audio & other streams,
+/// overestimating the video bits per pixel. This errs on the side of not skipping.
+pub fn check_probe(probe: &Ffprobe, input_size: u64, max_encoded_percent: f32) -> Option<Skip> {
+    if probe.is_image {
+        return None;
+    }
+    let codec = probe
+        .streams
+        .iter()
+        .filter(|s| s.kind == StreamKind::Video)
+        .nth(probe.video_stream)?
+        .codec
+        .clone()?;
+    if !EFFICIENT_CODECS.contains(&codec.as_str()) {
+        return None;
+    }
+
+    let secs = probe.duration.as_ref().ok()?.as_secs_f64();
+    let fps = *probe.fps.as_ref().ok()?;
+    let (w, h) = probe.resolution?;
+    let pixels_per_sec = f64::from(w) * f64::from(h) * fps;
+    if secs <= 0.0 || pixels_per_sec <= 0.0 {
+        return None;
+    }
+    let bpp = input_size as f64 * 8.0 / secs / pixels_per_sec;
+
+    let efficient_bpp = MIN_ENCODED_BPP * 100.0 / f64::from(max_encoded_percent.max(1.0));
+    (bpp < efficient_bpp).then_some(Skip::Efficient { codec, bpp })
+}
+
+#[test]
+fn check_probe_efficient() {
+    use crate::ffprobe::Stream;
+
+    let probe = |codec: &str| Ffprobe {
+        streams: vec![Stream {
+            kind: StreamKind::Video,
+            codec: Some(codec.into()),
+            ..<_>::default()
+        }],
+        ..<_>::default()
+    };
+    // 2Mb/s
+    let low = 60 * 2_000_000 / 8;
+
+    let Some(Skip::Efficient { codec, bpp }) = check_probe(&probe("hevc"), low, 80.0) else {
+        panic!("expected skip");
+    };
+    assert_eq!(codec, "hevc");
+    assert!((bpp - 0.0402).abs() < 0.001, "{bpp}");
+
+    // higher bitrate is worth encoding
+    assert_eq!(check_probe(&probe("hevc"), low * 2, 80.0), None);
+    // inefficient codec is worth encoding
+    assert_eq!(check_probe(&probe("h264"), low, 80.0), None);
+
+    // a higher --max-encoded-percent allows smaller savings, so lower bitrates are encoded
+    assert_eq!(check_probe(&probe("hevc"), low, 100.0), None);
+    // a lower --max-encoded-percent requires larger savings
+    assert!(check_probe(&probe("hevc"), low * 2, 40.0).is_some());
+}
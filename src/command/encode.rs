@@ -1,13 +1,22 @@
+mod audio;
+mod streams;
+mod verify_score;
+
+pub use verify_score::{VerifyScore, VerifyScoreMode};
+
 use crate::{
     command::{
-        PROGRESS_CHARS, SmallDuration,
+        PROGRESS_CHARS, SmallDuration, analyse_input,
         args::{self, Encoder},
     },
     console_ext::style,
     ffmpeg,
     ffprobe::{self, Ffprobe},
+    float::TerseF32,
     log::ProgressLogger,
     process::FfmpegOut,
+    provenance::{self, Provenance},
+    script, sequence,
     temporary::{self, TempKind},
 };
 use anyhow::{Context, ensure};
@@ -18,6 +27,7 @@ use log::info;
 use same_file::is_same_file;
 use std::{
     ffi::OsString,
+    fmt::Write,
     path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, Instant},
@@ -49,7 +59,7 @@ pub struct Args {
     pub encode: args::EncodeToOutput,
 }
 
-pub async fn encode(args: Args) -> anyhow::Result<()> {
+pub async fn encode(mut args: Args) -> anyhow::Result<()> {
     let bar = ProgressBar::new(1).with_style(
         ProgressStyle::default_bar()
             .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})")?
@@ -57,33 +67,50 @@ pub async fn encode(args: Args) -> anyhow::Result<()> {
     );
     bar.enable_steady_tick(Duration::from_millis(100));
 
-    let probe = ffprobe::probe(&args.args.input);
-    run(args, probe.into(), &bar).await
+    args.args.validate_fps()?;
+    args.args.validate_encoder(&[args.crf]).await?;
+    let probe = ffprobe::probe(&args.args.input, args.args.fps);
+    analyse_input(&mut args.args, None, &probe, &bar).await?;
+    run(args, probe.into(), None, None, &bar).await
 }
 
+/// Encode to output, `search` is the crf-search result the crf was chosen by, if any.
+///
+/// `verify_score` scores the encode, failing or warning if below any `search` min score.
 pub async fn run(
-    Args {
-        args,
-        crf,
-        encode:
-            args::EncodeToOutput {
-                output,
-                audio_codec,
-                downmix_to_stereo,
-                video_only,
-                overwrite_input,
-                verify,
-                verify_decode,
-                verify_duration,
-                fail_fast,
-            },
-    }: Args,
+    Args { args, crf, encode }: Args,
     probe: Arc<Ffprobe>,
+    search: Option<provenance::SearchResult>,
+    verify_score: Option<VerifyScore>,
     bar: &ProgressBar,
 ) -> anyhow::Result<()> {
+    let provenance = Provenance {
+        command: Some(args.encode_hint(crf) + &encode.encode_hint_args()),
+        search: search.clone(),
+        ..<_>::default()
+    };
+    let args::EncodeToOutput {
+        output,
+        audio_codec,
+        downmix_to_stereo,
+        transcode_audio,
+        opus_channel_bitrate,
+        stereo_compat,
+        loudnorm,
+        languages,
+        drop_commentary,
+        secondary_video_crf,
+        video_only,
+        overwrite_input,
+        verify,
+        verify_decode,
+        verify_duration,
+        verify_streams,
+        fail_fast,
+    } = encode;
+
     let defaulting_output = output.is_none();
-    let output =
-        output.unwrap_or_else(|| default_output_name(&args.input, &args.encoder, probe.is_image));
+    let output = output.unwrap_or_else(|| default_output_name(&args.input, &args.encoder, &probe));
 
     anyhow::ensure!(
         overwrite_input || !is_same_file(&output, &args.input).unwrap_or(false),
@@ -109,6 +136,7 @@ pub async fn run(
     let has_audio = probe.has_audio;
     let verify_decode = verify || verify_decode;
     let verify_duration = verify || verify_duration;
+    let verify_streams = verify || verify_streams;
     if let Ok(d) = &probe.duration {
         let mut len = d.as_micros_u64();
         if verify_decode {
@@ -125,6 +153,43 @@ pub async fn run(
         anyhow::bail!("--stereo-downmix cannot be used with --acodec copy");
     }
 
+    let mut streams = match video_only {
+        true => <_>::default(),
+        false => {
+            let plan = streams::plan(
+                &probe,
+                streams::SelectRules {
+                    languages: &languages,
+                    drop_commentary,
+                },
+                audio::AudioRules {
+                    transcode: &transcode_audio,
+                    opus_channel_kbps: opus_channel_bitrate,
+                    stereo_compat,
+                    downmix_to_stereo: stereo_downmix,
+                },
+                output
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or_default(),
+            );
+            for line in &plan.report {
+                bar.println(style!("{line}").dim().to_string());
+            }
+            plan.streams
+        }
+    };
+    if let Some(crf) = secondary_video_crf
+        && !video_only
+    {
+        streams.secondary_video = streams::secondary_video(&probe, crf);
+    }
+    if loudnorm && !streams.audio.is_empty() {
+        bar.set_message("loudnorm, ");
+        audio::add_loudnorm(&args.input, &mut streams.audio).await?;
+        bar.set_message("encoding, ");
+    }
+
     info!(
         "encoding {}",
         output.file_name().and_then(|n| n.to_str()).unwrap_or("")
@@ -133,13 +198,26 @@ pub async fn run(
     let tmp_output = tmp_output_name(&output)?;
     temporary::add(&tmp_output, TempKind::NotKeepable);
 
+    let expected_streams = streams::expected_stream_count(
+        &probe,
+        &streams,
+        video_only,
+        output
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default(),
+    );
+
+    let score_enc_args = verify_score.is_some().then(|| enc_args.clone());
     let mut enc = ffmpeg::encode(
         enc_args,
         &tmp_output,
         has_audio,
         audio_codec,
+        &streams,
         stereo_downmix,
         fail_fast,
+        provenance,
     )?;
     let mut logger = ProgressLogger::new(module_path!(), Instant::now());
     let mut stream_sizes = None;
@@ -162,7 +240,6 @@ pub async fn run(
             } => stream_sizes = Some((video, audio, subtitle, other)),
         }
     }
-    enc.wait().await?; // ensure process has exited
 
     // verified before moving into place, so a failed check leaves no output behind
     if verify_decode {
@@ -181,12 +258,15 @@ pub async fn run(
         })
         .await?;
     }
+    let output_probe = (verify_duration || verify_streams || verify_score.is_some())
+        .then(|| ffprobe::probe(&tmp_output, None));
     if verify_duration
+        && let Some(output_probe) = &output_probe
         && let Ok(expected) = &probe.duration
         // zero means no declared duration, e.g. images & raw streams
         && !expected.is_zero()
     {
-        let actual = ffprobe::probe(&tmp_output).duration?;
+        let actual = output_probe.duration.clone()?;
         ensure!(
             expected.abs_diff(actual) <= VERIFY_DURATION_TOLERANCE,
             "verify: output duration {} does not match input duration {}",
@@ -194,6 +274,74 @@ pub async fn run(
             humantime::format_duration(floor_ms(*expected)),
         );
     }
+    if verify_streams && let Some(output_probe) = &output_probe {
+        ensure!(
+            output_probe.streams.len() == expected_streams,
+            "verify: output has {} streams, expected {expected_streams}",
+            output_probe.streams.len(),
+        );
+        ensure!(
+            output_probe.chapters == probe.chapters,
+            "verify: output has {} chapters, input has {}",
+            output_probe.chapters,
+            probe.chapters,
+        );
+    }
+    if let Some(verify) = &verify_score
+        && let Some(search) = &search
+        && let Some(output_probe) = &output_probe
+        && let Some(enc_args) = &score_enc_args
+    {
+        let scores = verify
+            .run(
+                &args.input,
+                &probe,
+                &tmp_output,
+                output_probe,
+                enc_args,
+                bar,
+            )
+            .await?;
+        let mut verified = vec![];
+        let mut below_min = vec![];
+        for ((score, min_score), searched) in
+            scores.iter().zip(&verify.min_scores).zip(&search.scores)
+        {
+            let kind = &searched.kind;
+            let decimals = min_score.kind.decimals();
+            verified.push(format!(
+                "{kind} {score:.decimals$} (predicted {:.decimals$})",
+                searched.score
+            ));
+            if *score < searched.min - verify.tolerance {
+                below_min.push(format!(
+                    "{kind} {score:.decimals$} is below the min {kind} {}",
+                    TerseF32(searched.min)
+                ));
+            }
+        }
+        let mut report = format!("Verified {}", verified.join(", "));
+        if let Some((video, ..)) = stream_sizes {
+            write!(
+                report,
+                ", video size {} (predicted {})",
+                HumanBytes(video),
+                HumanBytes(search.predicted_size)
+            )
+            .unwrap();
+        }
+        bar.println(style!("{report}").dim().to_string());
+
+        if !below_min.is_empty() {
+            let msg = format!(
+                "verify: {} by more than {}",
+                below_min.join(", "),
+                TerseF32(verify.tolerance),
+            );
+            ensure!(verify.warn, msg);
+            bar.println(style!("Warning: {msg}").yellow().to_string());
+        }
+    }
     bar.finish();
 
     std::fs::rename(&tmp_output, &output)?;
@@ -201,7 +349,7 @@ pub async fn run(
 
     // print output info
     let output_size = fs::metadata(&output).await?.len();
-    let output_percent = 100.0 * output_size as f64 / fs::metadata(&args.input).await?.len() as f64;
+    let output_percent = 100.0 * output_size as f64 / script::input_size(&args.input).await? as f64;
     let output_size = style(HumanBytes(output_size)).dim().bold();
     let output_percent = style!("{}%", output_percent.round()).dim().bold();
     eprint!(
@@ -232,8 +380,9 @@ pub async fn run(
 /// * vid.mp4 -> "mp4"
 /// * vid.??? -> "mkv"
 /// * image.??? -> "avif"
-pub fn default_output_ext(input: &Path, encoder: &Encoder, is_image: bool) -> &'static str {
-    if is_image {
+/// * animated.gif -> "avif"
+pub fn default_output_ext(input: &Path, encoder: &Encoder, probe: &Ffprobe) -> &'static str {
+    if probe.is_image || probe.animated {
         return encoder.default_image_ext();
     }
     match input.extension().and_then(|e| e.to_str()) {
@@ -242,10 +391,14 @@ pub fn default_output_ext(input: &Path, encoder: &Encoder, is_image: bool) -> &'
     }
 }
 
-/// E.g. vid.mkv -> "vid.av1.mkv"
-pub fn default_output_name(input: &Path, encoder: &Encoder, is_image: bool) -> PathBuf {
+/// E.g. vid.mkv -> "vid.av1.mkv", frames/f_%05d.png -> "frames/f.av1.mkv"
+pub fn default_output_name(input: &Path, encoder: &Encoder, probe: &Ffprobe) -> PathBuf {
     let pre = ffmpeg::pre_extension_name(encoder.as_str());
-    let ext = default_output_ext(input, encoder, is_image);
+    let ext = default_output_ext(input, encoder, probe);
+    let input = match sequence::is_sequence(input) {
+        true => sequence::output_base(input),
+        false => input.into(),
+    };
     input.with_extension(format!("{pre}.{ext}"))
 }
 
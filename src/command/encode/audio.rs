@@ -0,0 +1,199 @@
+//! Per audio stream encoding rules.
+use crate::{ffmpeg::AudioStreamArgs, ffprobe::Stream, loudnorm};
+use std::path::Path;
+
+/// Audio encoding rules from `EncodeToOutput` args.
+#[derive(Debug, Clone, Copy)]
+pub struct AudioRules<'a> {
+    pub transcode: &'a [String],
+    pub opus_channel_kbps: u32,
+    pub stereo_compat: bool,
+    /// All audio is downmixed to stereo, i.e. `--downmix-to-stereo`.
+    pub downmix_to_stereo: bool,
+}
+
+/// Downmix to stereo, used before loudnorm so it applies to the output channels.
+const STEREO_DOWNMIX: &str = "aformat=channel_layouts=stereo";
+
+impl AudioRules<'_> {
+    /// Returns per stream args for the output `audio` streams the rules apply to.
+    /// Each stream is paired with its input audio stream number.
+    ///
+    /// Streams without args use the general `-c:a` behaviour.
+    pub fn plan(&self, audio: &[(usize, &Stream)]) -> Vec<AudioStreamArgs> {
+        let mut plan: Vec<_> = audio
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, s))| self.transcode.iter().any(|c| codec_matches(s, c)))
+            .map(|(out_idx, (in_idx, s))| {
+                let channels = match self.downmix_to_stereo {
+                    true => 2,
+                    false => s.channels.unwrap_or(2),
+                };
+                self.opus(*in_idx, out_idx, channels)
+            })
+            .collect();
+
+        if self.stereo_compat
+            && let Some((in_idx, _)) = audio
+                .iter()
+                .find(|(_, s)| s.channels.is_some_and(|c| c > 2))
+        {
+            plan.push(AudioStreamArgs {
+                additional: true,
+                title: Some("Stereo".into()),
+                ..self.opus(*in_idx, audio.len(), 2)
+            });
+        }
+        plan
+    }
+
+    fn opus(&self, input_idx: usize, output_idx: usize, channels: u32) -> AudioStreamArgs {
+        AudioStreamArgs {
+            input_idx,
+            output_idx,
+            additional: false,
+            codec: "libopus".into(),
+            bitrate_kbps: Some(self.opus_channel_kbps * channels),
+            channels: (channels == 2).then_some(2),
+            // libopus rejects some layouts, e.g. "5.1(side)", so convert to the nearest supported
+            filter: (channels > 2).then(|| "aformat=channel_layouts=7.1|5.1|stereo".into()),
+            title: None,
+        }
+    }
+}
+
+/// Two-pass EBU R128 normalise the planned streams, measuring each input stream once
+/// per output channel layout.
+///
+/// Stereo output streams are downmixed before measuring & normalising, so a stereo compat
+/// stream is normalised rather than its multichannel source.
+pub async fn add_loudnorm(input: &Path, plan: &mut [AudioStreamArgs]) -> anyhow::Result<()> {
+    let mut measured: Vec<(usize, Option<&str>, String)> = vec![];
+    for stream in plan {
+        let downmix = (stream.channels == Some(2)).then_some(STEREO_DOWNMIX);
+        let afilter = match measured
+            .iter()
+            .find(|(idx, d, _)| *idx == stream.input_idx && *d == downmix)
+        {
+            Some((.., f)) => f.clone(),
+            None => {
+                let f = loudnorm::measure(input, stream.input_idx, downmix)
+                    .await?
+                    .afilter();
+                measured.push((stream.input_idx, downmix, f.clone()));
+                f
+            }
+        };
+        stream.filter = Some(loudnorm_filter(&afilter, downmix, stream.filter.as_deref()));
+    }
+    Ok(())
+}
+
+/// Returns the stream filter applying loudnorm `afilter` after any `downmix`.
+fn loudnorm_filter(afilter: &str, downmix: Option<&str>, filter: Option<&str>) -> String {
+    [downmix, Some(afilter), filter]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Returns true if `stream` uses `codec`, with "dts-hd" matching DTS-HD MA & HRA.
+fn codec_matches(stream: &Stream, codec: &str) -> bool {
+    match codec.to_ascii_lowercase().as_str() {
+        "dts-hd" => {
+            stream.codec.as_deref() == Some("dts")
+                && stream
+                    .profile
+                    .as_deref()
+                    .is_some_and(|p| p.starts_with("DTS-HD"))
+        }
+        codec => stream.codec.as_deref() == Some(codec),
+    }
+}
+
+#[test]
+fn plan_transcode_and_stereo_compat() {
+    use crate::ffprobe::StreamKind;
+
+    let audio = |index, codec: &str, profile: Option<&str>, channels| Stream {
+        index,
+        kind: StreamKind::Audio,
+        codec: Some(codec.into()),
+        profile: profile.map(Into::into),
+        channels: Some(channels),
+        ..<_>::default()
+    };
+    let streams = [
+        audio(1, "truehd", None, 8),
+        audio(2, "dts", Some("DTS-HD MA"), 6),
+        audio(3, "dts", Some("DTS"), 6),
+        audio(4, "ac3", None, 2),
+    ];
+    let audio: Vec<_> = streams.iter().enumerate().collect();
+    let transcode = ["truehd".to_owned(), "dts-hd".to_owned()];
+    let plan = AudioRules {
+        transcode: &transcode,
+        opus_channel_kbps: 64,
+        stereo_compat: true,
+        downmix_to_stereo: false,
+    }
+    .plan(&audio);
+
+    assert_eq!(plan.len(), 3);
+    assert_eq!((plan[0].input_idx, plan[0].output_idx), (0, 0));
+    assert_eq!(plan[0].bitrate_kbps, Some(512));
+    assert_eq!((plan[1].input_idx, plan[1].output_idx), (1, 1));
+    assert_eq!(plan[1].bitrate_kbps, Some(384));
+
+    let compat = &plan[2];
+    assert!(compat.additional);
+    assert_eq!((compat.input_idx, compat.output_idx), (0, 4));
+    assert_eq!(compat.channels, Some(2));
+    assert_eq!(compat.bitrate_kbps, Some(128));
+    assert_eq!(compat.filter, None);
+}
+
+#[test]
+fn plan_downmix_to_stereo_bitrate() {
+    use crate::ffprobe::StreamKind;
+
+    let streams = [Stream {
+        index: 1,
+        kind: StreamKind::Audio,
+        codec: Some("truehd".into()),
+        channels: Some(8),
+        ..<_>::default()
+    }];
+    let audio: Vec<_> = streams.iter().enumerate().collect();
+    let transcode = ["truehd".to_owned()];
+    let plan = AudioRules {
+        transcode: &transcode,
+        opus_channel_kbps: 64,
+        stereo_compat: false,
+        downmix_to_stereo: true,
+    }
+    .plan(&audio);
+
+    assert_eq!(plan.len(), 1);
+    assert_eq!(plan[0].bitrate_kbps, Some(128));
+    assert_eq!(plan[0].channels, Some(2));
+    assert_eq!(plan[0].filter, None);
+}
+
+#[test]
+fn loudnorm_after_downmix() {
+    assert_eq!(
+        loudnorm_filter("loudnorm=I=-23", Some(STEREO_DOWNMIX), None),
+        "aformat=channel_layouts=stereo,loudnorm=I=-23"
+    );
+    assert_eq!(
+        loudnorm_filter(
+            "loudnorm=I=-23",
+            None,
+            Some("aformat=channel_layouts=7.1|5.1|stereo")
+        ),
+        "loudnorm=I=-23,aformat=channel_layouts=7.1|5.1|stereo"
+    );
+}
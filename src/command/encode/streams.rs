@@ -0,0 +1,470 @@
+//! Output stream selection rules.
+use super::audio::AudioRules;
+use crate::{
+    ffmpeg::OutputStreams,
+    ffprobe::{Ffprobe, Stream, StreamKind},
+};
+use std::fmt::Write;
+
+/// Stream selection rules from `EncodeToOutput` args.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectRules<'a> {
+    pub languages: &'a [String],
+    pub drop_commentary: bool,
+}
+
+impl SelectRules<'_> {
+    fn is_active(&self) -> bool {
+        !self.languages.is_empty() || self.drop_commentary
+    }
+
+    /// Returns the reason to drop an audio or subtitle stream, if any.
+    fn drop_reason(&self, stream: &Stream) -> Option<&'static str> {
+        if self.drop_commentary && stream.is_commentary() {
+            return Some("commentary");
+        }
+        let language = stream.language.as_deref().filter(|l| *l != "und")?;
+        match self.languages.is_empty()
+            || self
+                .languages
+                .iter()
+                .any(|l| l.eq_ignore_ascii_case(language))
+        {
+            true => None,
+            false => Some("language"),
+        }
+    }
+}
+
+/// Output streams & a human readable report of the decisions.
+#[derive(Debug, Default)]
+pub struct StreamPlan {
+    pub streams: OutputStreams,
+    /// Report lines, empty if all streams are kept as normal.
+    pub report: Vec<String>,
+}
+
+/// Decide which input streams to keep & how, for an output using `output_ext`.
+pub fn plan(
+    probe: &Ffprobe,
+    select: SelectRules<'_>,
+    audio_rules: AudioRules<'_>,
+    output_ext: &str,
+) -> StreamPlan {
+    let mut kept = vec![];
+    let mut dropped = vec![];
+    for stream in &probe.streams {
+        let reason = match stream.kind {
+            StreamKind::Audio => select.drop_reason(stream),
+            // forced subtitles are kept regardless of language
+            StreamKind::Subtitle if stream.forced && !stream.is_commentary() => None,
+            StreamKind::Subtitle => select.drop_reason(stream),
+            // webm is matroska based, but rejects attachments
+            StreamKind::Attachment if output_ext != "mkv" => Some("unsupported by container"),
+            _ => None,
+        };
+        match reason {
+            None => kept.push(stream),
+            Some(reason) => dropped.push((stream, reason)),
+        }
+    }
+
+    // keep some audio even if no language matches
+    let is_audio = |s: &&Stream| s.kind == StreamKind::Audio;
+    if !kept.iter().any(is_audio)
+        && let Some(pos) = dropped
+            .iter()
+            .position(|(s, r)| is_audio(s) && *r == "language")
+    {
+        let (stream, _) = dropped.remove(pos);
+        kept.push(stream);
+        kept.sort_by_key(|s| s.index);
+    }
+
+    // convert or drop subtitles the container doesn't support
+    let mut subtitle_codecs = vec![];
+    let mut conversions = vec![];
+    let mut sub_n = 0;
+    kept.retain(|s| {
+        if s.kind != StreamKind::Subtitle {
+            return true;
+        }
+        match subtitle_conversion(output_ext, s.codec.as_deref().unwrap_or_default()) {
+            Ok(None) => {}
+            Ok(Some(codec)) => {
+                subtitle_codecs.push((sub_n, codec));
+                conversions.push((s.index, codec));
+            }
+            Err(reason) => {
+                dropped.push((s, reason));
+                return false;
+            }
+        }
+        sub_n += 1;
+        true
+    });
+
+    let audio: Vec<_> = probe
+        .audio_streams()
+        .enumerate()
+        .filter(|(_, s)| kept.iter().any(|k| k.index == s.index))
+        .collect();
+    let audio_args = audio_rules.plan(&audio);
+
+    let mut dispositions = vec![];
+    if select.is_active() {
+        for (n, _) in audio.iter().enumerate() {
+            dispositions.push((format!("a:{n}"), if n == 0 { "default" } else { "0" }));
+        }
+        let subs = kept.iter().filter(|s| s.kind == StreamKind::Subtitle);
+        let mut default_set = false;
+        for (n, sub) in subs.enumerate() {
+            let disposition = match sub.forced {
+                true if !default_set => {
+                    default_set = true;
+                    "default+forced"
+                }
+                true => "forced",
+                false => "0",
+            };
+            dispositions.push((format!("s:{n}"), disposition));
+        }
+    }
+    // additional streams copy their source's disposition, so clear it to avoid e.g. two
+    // default audio streams
+    for a in audio_args.iter().filter(|a| a.additional) {
+        dispositions.push((format!("a:{}", a.output_idx), "0"));
+    }
+
+    let mut report = vec![];
+    if !dropped.is_empty() || !conversions.is_empty() || !audio_args.is_empty() {
+        for stream in &kept {
+            let mut line = describe(stream);
+            if let Some((_, codec)) = conversions.iter().find(|(idx, _)| *idx == stream.index) {
+                write!(line, " -> {codec}").unwrap();
+            }
+            if let Some((in_idx, _)) = audio.iter().find(|(_, s)| s.index == stream.index)
+                && let Some(a) = audio_args
+                    .iter()
+                    .find(|a| !a.additional && a.input_idx == *in_idx)
+            {
+                write!(line, " -> {}", a.codec).unwrap();
+                if let Some(kbps) = a.bitrate_kbps {
+                    write!(line, " {kbps}k").unwrap();
+                }
+            }
+            report.push(line);
+        }
+        for a in audio_args.iter().filter(|a| a.additional) {
+            let title = a.title.as_deref().unwrap_or_default();
+            let mut line = format!("+ audio {title} from audio {} -> {}", a.input_idx, a.codec);
+            if let Some(kbps) = a.bitrate_kbps {
+                write!(line, " {kbps}k").unwrap();
+            }
+            report.push(line);
+        }
+        dropped.sort_by_key(|(s, _)| s.index);
+        for (stream, reason) in &dropped {
+            report.push(format!("- {} dropped: {reason}", describe(stream)));
+        }
+    }
+
+    StreamPlan {
+        streams: OutputStreams {
+            map: (!dropped.is_empty()).then(|| kept.iter().map(|s| s.index).collect()),
+            audio: audio_args,
+            subtitle_codecs,
+            dispositions,
+            secondary_video: vec![],
+        },
+        report,
+    }
+}
+
+/// Returns the expected number of output streams.
+pub fn expected_stream_count(
+    probe: &Ffprobe,
+    streams: &OutputStreams,
+    video_only: bool,
+    output_ext: &str,
+) -> usize {
+    if video_only {
+        return 1;
+    }
+    // data streams are not supported by matroska & dropped
+    let matroska = matches!(output_ext, "mkv" | "webm");
+    let mapped = probe
+        .streams
+        .iter()
+        .filter(|s| streams.map.as_ref().is_none_or(|m| m.contains(&s.index)))
+        .filter(|s| !(matroska && s.kind == StreamKind::Other))
+        .count();
+    mapped + streams.audio.iter().filter(|a| a.additional).count()
+}
+
+/// Returns output video stream numbers of secondary video streams, i.e. not the primary
+/// video or attached pictures, paired with `crf`.
+pub fn secondary_video(probe: &Ffprobe, crf: f32) -> Vec<(usize, f32)> {
+    // video streams are never dropped so output & input stream numbers match
+    probe
+        .streams
+        .iter()
+        .filter(|s| s.kind == StreamKind::Video)
+        .enumerate()
+        .filter(|(n, s)| *n != probe.video_stream && !s.attached_pic)
+        .map(|(n, _)| (n, crf))
+        .collect()
+}
+
+/// Returns the codec a subtitle stream should be converted to, if any,
+/// or an error reason if the stream must be dropped.
+fn subtitle_conversion(
+    output_ext: &str,
+    codec: &str,
+) -> Result<Option<&'static str>, &'static str> {
+    const TEXT: &[&str] = &["subrip", "ass", "ssa", "webvtt", "mov_text", "text"];
+
+    let is_text = TEXT.contains(&codec);
+    match output_ext {
+        "mp4" | "m4v" | "mov" => match codec {
+            "mov_text" => Ok(None),
+            _ if is_text => Ok(Some("mov_text")),
+            _ => Err("unsupported by mp4"),
+        },
+        "mkv" => match codec {
+            "mov_text" => Ok(Some("srt")),
+            _ => Ok(None),
+        },
+        "webm" => match codec {
+            "webvtt" => Ok(None),
+            _ if is_text => Ok(Some("webvtt")),
+            _ => Err("unsupported by webm"),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// E.g. "#2 audio eng truehd 8ch (commentary)".
+fn describe(stream: &Stream) -> String {
+    let kind = match stream.kind {
+        StreamKind::Video => "video",
+        StreamKind::Audio => "audio",
+        StreamKind::Subtitle => "subtitle",
+        StreamKind::Attachment => "attachment",
+        StreamKind::Other => "data",
+    };
+    let mut out = format!("#{} {kind}", stream.index);
+    if let Some(language) = &stream.language {
+        write!(out, " {language}").unwrap();
+    }
+    if let Some(codec) = &stream.codec {
+        write!(out, " {codec}").unwrap();
+    }
+    if let Some(channels) = stream.channels {
+        write!(out, " {channels}ch").unwrap();
+    }
+    if let Some(title) = &stream.title {
+        write!(out, " {title:?}").unwrap();
+    }
+    if stream.forced {
+        out.push_str(" (forced)");
+    }
+    if stream.comment {
+        out.push_str(" (commentary)");
+    }
+    out
+}
+
+#[cfg(test)]
+fn test_probe(streams: Vec<Stream>) -> Ffprobe {
+    Ffprobe {
+        has_audio: true,
+        streams,
+        ..<_>::default()
+    }
+}
+
+#[test]
+fn plan_select_languages_and_subtitles() {
+    let stream = |index, kind, codec: &str, language: &str| Stream {
+        index,
+        kind,
+        codec: Some(codec.into()),
+        language: Some(language.into()),
+        ..<_>::default()
+    };
+    let probe = test_probe(vec![
+        stream(0, StreamKind::Video, "h264", "und"),
+        stream(1, StreamKind::Audio, "aac", "jpn"),
+        stream(2, StreamKind::Audio, "aac", "eng"),
+        Stream {
+            title: Some("Director's Commentary".into()),
+            ..stream(3, StreamKind::Audio, "aac", "eng")
+        },
+        stream(4, StreamKind::Subtitle, "subrip", "eng"),
+        stream(5, StreamKind::Subtitle, "hdmv_pgs_subtitle", "eng"),
+        Stream {
+            forced: true,
+            ..stream(6, StreamKind::Subtitle, "ass", "fre")
+        },
+        stream(7, StreamKind::Subtitle, "subrip", "ger"),
+    ]);
+    let languages = ["eng".to_owned()];
+    let plan = plan(
+        &probe,
+        SelectRules {
+            languages: &languages,
+            drop_commentary: true,
+        },
+        AudioRules {
+            transcode: &[],
+            opus_channel_kbps: 64,
+            stereo_compat: false,
+            downmix_to_stereo: false,
+        },
+        "mp4",
+    );
+
+    assert_eq!(plan.streams.map, Some(vec![0, 2, 4, 6]));
+    assert_eq!(
+        plan.streams.subtitle_codecs,
+        [(0, "mov_text"), (1, "mov_text")]
+    );
+    assert_eq!(
+        plan.streams.dispositions,
+        [
+            ("a:0".into(), "default"),
+            ("s:0".into(), "0"),
+            ("s:1".into(), "default+forced"),
+        ]
+    );
+    assert_eq!(
+        plan.report.last().unwrap(),
+        "- #7 subtitle ger subrip dropped: language"
+    );
+}
+
+#[test]
+fn plan_keeps_all_by_default() {
+    let probe = test_probe(vec![
+        Stream {
+            index: 0,
+            kind: StreamKind::Video,
+            ..<_>::default()
+        },
+        Stream {
+            index: 1,
+            kind: StreamKind::Subtitle,
+            codec: Some("hdmv_pgs_subtitle".into()),
+            ..<_>::default()
+        },
+    ]);
+    let plan = plan(
+        &probe,
+        SelectRules {
+            languages: &[],
+            drop_commentary: false,
+        },
+        AudioRules {
+            transcode: &[],
+            opus_channel_kbps: 64,
+            stereo_compat: false,
+            downmix_to_stereo: false,
+        },
+        "mkv",
+    );
+    assert_eq!(plan.streams, OutputStreams::default());
+    assert!(plan.report.is_empty());
+}
+
+#[test]
+fn plan_stereo_compat_not_default() {
+    let probe = test_probe(vec![
+        Stream {
+            index: 0,
+            kind: StreamKind::Video,
+            ..<_>::default()
+        },
+        Stream {
+            index: 1,
+            kind: StreamKind::Audio,
+            codec: Some("eac3".into()),
+            channels: Some(6),
+            ..<_>::default()
+        },
+    ]);
+    let plan = plan(
+        &probe,
+        SelectRules {
+            languages: &[],
+            drop_commentary: false,
+        },
+        AudioRules {
+            transcode: &[],
+            opus_channel_kbps: 64,
+            stereo_compat: true,
+            downmix_to_stereo: false,
+        },
+        "mkv",
+    );
+    assert_eq!(plan.streams.audio.len(), 1);
+    assert_eq!(plan.streams.dispositions, [("a:1".into(), "0")]);
+}
+
+#[test]
+fn secondary_video_skips_primary_and_attached_pic() {
+    let video = |index, attached_pic| Stream {
+        index,
+        kind: StreamKind::Video,
+        attached_pic,
+        ..<_>::default()
+    };
+    let probe = Ffprobe {
+        video_stream: 1,
+        ..test_probe(vec![video(0, true), video(1, false), video(2, false)])
+    };
+    assert_eq!(secondary_video(&probe, 40.0), [(2, 40.0)]);
+}
+
+#[test]
+fn expected_stream_count_of_plan() {
+    let stream = |index, kind| Stream {
+        index,
+        kind,
+        ..<_>::default()
+    };
+    let probe = test_probe(vec![
+        stream(0, StreamKind::Video),
+        stream(1, StreamKind::Audio),
+        stream(2, StreamKind::Other),
+        stream(3, StreamKind::Attachment),
+    ]);
+    let planned = |ext| {
+        let select = SelectRules {
+            languages: &[],
+            drop_commentary: false,
+        };
+        let audio = AudioRules {
+            transcode: &[],
+            opus_channel_kbps: 64,
+            stereo_compat: false,
+            downmix_to_stereo: false,
+        };
+        plan(&probe, select, audio, ext).streams
+    };
+
+    // mkv drops data, keeps attachments
+    let mkv = planned("mkv");
+    assert_eq!(expected_stream_count(&probe, &mkv, false, "mkv"), 3);
+    assert_eq!(expected_stream_count(&probe, &mkv, true, "mkv"), 1);
+
+    // mp4 keeps data, attachments are dropped by the plan
+    let mp4 = planned("mp4");
+    assert_eq!(mp4.map, Some(vec![0, 1, 2]));
+    assert_eq!(expected_stream_count(&probe, &mp4, false, "mp4"), 3);
+
+    // webm drops data & attachments
+    let webm = planned("webm");
+    assert_eq!(webm.map, Some(vec![0, 1, 2]));
+    assert_eq!(expected_stream_count(&probe, &webm, false, "webm"), 2);
+}
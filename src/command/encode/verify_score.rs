@@ -0,0 +1,203 @@
+//! `--verify-score` logic.
+use crate::{
+    command::{
+        args::{self, PixelFormat},
+        crf_search::MinScore,
+        xpsnr::lavfi,
+    },
+    ffmpeg::FfmpegEncodeArgs,
+    ffprobe::Ffprobe,
+    frame_report::{self, FrameReport},
+    metric::{self, MetricOut, ScoreKind, select_streams},
+    process::FfmpegOut,
+    sample,
+    temporary::{self, TempKind},
+    vmaf, xpsnr,
+};
+use indicatif::ProgressBar;
+use std::{collections::BTreeMap, fmt::Write, path::Path, pin::Pin, time::Duration};
+use tokio_stream::Stream;
+use tokio_stream::StreamExt;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[clap(rename_all = "lower")]
+pub enum VerifyScoreMode {
+    /// Score samples at positions distinct from the crf-search samples.
+    #[default]
+    Samples,
+    /// Score the whole encode.
+    Full,
+}
+
+/// Score the final encode to verify the crf-search prediction.
+#[derive(Debug, Clone)]
+pub struct VerifyScore {
+    pub mode: VerifyScoreMode,
+    /// Allowed score shortfall below the min score.
+    pub tolerance: f32,
+    /// Warn, rather than fail, when the score is too low.
+    pub warn: bool,
+    /// Number of crf-search samples.
+    pub samples: u64,
+    pub sample_duration: Duration,
+    /// crf-search min scores, each is verified.
+    pub min_scores: Vec<MinScore>,
+    pub vmaf: args::Vmaf,
+    pub score: args::ScoreArgs,
+    pub xpsnr: args::Xpsnr,
+}
+
+impl VerifyScore {
+    /// Returns the score of `output` encoded using `enc` vs the `input` for each min score.
+    pub async fn run(
+        &self,
+        input: &Path,
+        probe: &Ffprobe,
+        output: &Path,
+        output_probe: &Ffprobe,
+        enc: &FfmpegEncodeArgs<'_>,
+        bar: &ProgressBar,
+    ) -> anyhow::Result<Vec<f32>> {
+        let ranges: Vec<_> = match (self.mode, &probe.duration) {
+            (VerifyScoreMode::Samples, Ok(duration))
+                if *duration > self.sample_duration * self.samples as _ =>
+            {
+                (0..self.samples)
+                    .map(|idx| {
+                        let start = sample::between_start(
+                            idx,
+                            self.samples,
+                            self.sample_duration,
+                            *duration,
+                        );
+                        Some((start, self.sample_duration))
+                    })
+                    .collect()
+            }
+            // images & short inputs are scored in full
+            _ => vec![None],
+        };
+
+        let pix_fmt = PixelFormat::opt_max(enc.pix_fmt, probe.pixel_format());
+        let ref_vfilter = self.score.ref_vfilter(enc.vfilter);
+        let output_stream = match enc.video_only {
+            true => 0,
+            false => enc.video_stream,
+        };
+
+        let mut kinds = vec![];
+        for MinScore { kind, .. } in &self.min_scores {
+            if !kinds.contains(kind) {
+                kinds.push(*kind);
+            }
+        }
+        let vmaf_log = self
+            .min_scores
+            .iter()
+            .any(|m| m.percentile.is_some())
+            .then(|| output.with_extension("vmaf.json"));
+        if let Some(log) = &vmaf_log {
+            temporary::add(log, TempKind::NotKeepable);
+        }
+
+        let mut totals = BTreeMap::<ScoreKind, f32>::new();
+        let mut vmaf_frames = vec![];
+        for (idx, range) in ranges.iter().enumerate() {
+            let label = match ranges.len() {
+                1 => String::new(),
+                n => format!(" {}/{n}", idx + 1),
+            };
+            for kind in kinds.iter().copied() {
+                bar.set_message(format!("verifying {kind}{label}, "));
+                let mut out: Pin<Box<dyn Stream<Item = MetricOut>>> = match kind {
+                    ScoreKind::Vmaf => {
+                        let mut lavfi = self.vmaf.ffmpeg_lavfi(
+                            output_probe.resolution,
+                            pix_fmt,
+                            ref_vfilter.as_deref(),
+                        );
+                        if let Some(log) = &vmaf_log {
+                            _ = write!(
+                                lavfi,
+                                ":log_fmt=json:log_path={}",
+                                frame_report::filter_path(log)
+                            );
+                        }
+                        let lavfi = select_streams(&lavfi, output_stream, enc.video_stream);
+                        Box::pin(
+                            vmaf::run(
+                                input,
+                                enc.input_fps,
+                                output,
+                                &lavfi,
+                                self.vmaf.fps(),
+                                self.score.grain_synthesis,
+                                *range,
+                            )?
+                            .map(MetricOut::from),
+                        )
+                    }
+                    ScoreKind::Xpsnr => {
+                        let lavfi = lavfi(
+                            ref_vfilter.as_deref(),
+                            self.xpsnr.xpsnr_pix_format.or(pix_fmt),
+                        );
+                        let lavfi = select_streams(&lavfi, enc.video_stream, output_stream);
+                        Box::pin(
+                            xpsnr::run(
+                                input,
+                                enc.input_fps,
+                                output,
+                                &lavfi,
+                                self.xpsnr.fps(),
+                                self.score.grain_synthesis,
+                                *range,
+                            )?
+                            .map(MetricOut::from),
+                        )
+                    }
+                    kind => Box::pin(metric::run(
+                        kind,
+                        input,
+                        enc.input_fps,
+                        output,
+                        (enc.video_stream, output_stream),
+                        ref_vfilter.as_deref(),
+                        pix_fmt,
+                        self.score.grain_synthesis,
+                        *range,
+                        None,
+                    )?),
+                };
+                let mut score = None;
+                while let Some(next) = out.next().await {
+                    match next {
+                        MetricOut::Done(s) => score = Some(s),
+                        MetricOut::Progress(FfmpegOut::Progress { fps, .. }) if fps > 0.0 => {
+                            bar.set_message(format!("verifying {kind}{label} {fps} fps, "));
+                        }
+                        MetricOut::Progress(_) => {}
+                        MetricOut::Err(e) => return Err(e),
+                    }
+                }
+                let score = score.ok_or_else(|| anyhow::anyhow!("verify: no {kind} score"))?;
+                *totals.entry(kind).or_default() += score;
+
+                if let (ScoreKind::Vmaf, Some(log)) = (kind, &vmaf_log) {
+                    let report = FrameReport::from_vmaf_log(&tokio::fs::read(log).await?, 0.0)?;
+                    vmaf_frames.extend(report.scores().map(|s| s as f32));
+                }
+            }
+        }
+        for total in totals.values_mut() {
+            *total /= ranges.len() as f32;
+        }
+        vmaf_frames.sort_by(f32::total_cmp);
+
+        Ok(self
+            .min_scores
+            .iter()
+            .map(|m| m.score_of(&totals, &vmaf_frames))
+            .collect())
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    encoder::{self, Backend, Capabilities, EncoderProfile},
+    ffmpeg,
+    float::TerseF32,
+};
+use anyhow::Context;
+use clap::Parser;
+use console::style;
+
+/// List ffmpeg video encoders, or print the capabilities of an encoder.
+///
+/// Encoders with a specific ab-av1 profile, defining crf & preset args & search defaults,
+/// are highlighted. Other encoders use generic defaults.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    /// Encoder to print the ffmpeg capabilities & ab-av1 profile of, e.g. libsvtav1.
+    pub encoder: Option<String>,
+}
+
+pub async fn encoders(Args { encoder }: Args) -> anyhow::Result<()> {
+    match encoder {
+        Some(encoder) => print_encoder(&encoder).await,
+        None => print_list().await,
+    }
+}
+
+async fn print_list() -> anyhow::Result<()> {
+    let encoders = encoder::list().await?;
+    anyhow::ensure!(!encoders.is_empty(), "no ffmpeg video encoders found");
+    let width = encoders
+        .iter()
+        .map(|e| e.name.len())
+        .max()
+        .unwrap_or_default();
+    for enc in encoders {
+        let name = format!("{:width$}", enc.name);
+        let name = match EncoderProfile::is_known(&enc.name) {
+            true => style(name).green().bold(),
+            false => style(name),
+        };
+        println!("{name} {}", style(enc.description).dim());
+    }
+    Ok(())
+}
+
+async fn print_encoder(encoder: &str) -> anyhow::Result<()> {
+    let caps = Capabilities::query(encoder)
+        .await?
+        .with_context(|| format!("encoder `{encoder}` is not available in ffmpeg"))?;
+    let profile = EncoderProfile::get(encoder);
+    let range = |arg: &str| match caps.option(arg).and_then(|o| o.range) {
+        Some((from, to)) => format!("{arg} (from {from} to {to})"),
+        None => arg.to_owned(),
+    };
+
+    println!(
+        "{} {}",
+        style(encoder).bold(),
+        style(&caps.description).dim()
+    );
+    if !caps.pix_fmts.is_empty() {
+        println!("pixel formats: {}", caps.pix_fmts.join(" "));
+    }
+    println!(
+        "profile: {}",
+        match EncoderProfile::is_known(encoder) {
+            true => profile.name,
+            false => "generic",
+        }
+    );
+    println!("  crf arg: {}", range(profile.crf_arg));
+    println!("  preset arg: {}", range(profile.preset_arg));
+    println!(
+        "  crf-search: --min-crf {} --max-crf {} --crf-increment {}",
+        TerseF32(profile.min_crf),
+        TerseF32(profile.max_crf),
+        TerseF32(profile.crf_increment),
+    );
+    if let Some(preset) = profile.preset {
+        println!("  default preset: {preset}");
+    }
+    if let Some(pix_fmt) = profile.pix_fmt {
+        println!("  default pixel format: {pix_fmt}");
+    }
+    println!(
+        "  output pre-extension: {}",
+        ffmpeg::pre_extension_name(encoder)
+    );
+    if let Some(native) = profile.native {
+        println!(
+            "  native backend: {}{}",
+            profile.native_bin.unwrap_or(native.bin()),
+            match profile.backend {
+                Backend::Native => " (default)",
+                Backend::Ffmpeg => "",
+            }
+        );
+    }
+    if !caps.options.is_empty() {
+        println!("options:");
+        for opt in &caps.options {
+            println!("  {} <{}> {}", opt.name, opt.kind, style(&opt.help).dim());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use crate::{
+    ffprobe,
+    float::TerseF32,
+    provenance::{METADATA_TAG, Provenance},
+};
+use anyhow::Context;
+use clap::{Parser, ValueHint};
+use console::style;
+use indicatif::HumanBytes;
+use std::path::PathBuf;
+
+/// Print how an ab-av1 output was encoded, read from the output metadata.
+///
+/// Includes the ab-av1 & encoder versions, encode settings, crf-search target & result,
+/// predicted vs actual video stream size & the command to reproduce the encode.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    /// ab-av1 encoded file.
+    #[arg(value_hint = ValueHint::FilePath)]
+    pub input: PathBuf,
+
+    /// Only print the ab-av1 command to reproduce the encode.
+    #[arg(long)]
+    pub command: bool,
+}
+
+pub async fn inspect(Args { input, command }: Args) -> anyhow::Result<()> {
+    let json = ffprobe::read_format_tag(&input, METADATA_TAG)
+        .context("ffprobe")?
+        .with_context(|| format!("no {METADATA_TAG} metadata, not encoded by ab-av1?"))?;
+    let provenance = Provenance::from_json(&json).context("invalid provenance metadata")?;
+
+    if command {
+        let command = provenance
+            .command
+            .context("no encode command recorded, encoded by an older ab-av1?")?;
+        println!("{command}");
+        return Ok(());
+    }
+
+    let Provenance {
+        version,
+        vcodec,
+        crf,
+        preset,
+        pix_fmt,
+        vfilter,
+        input_args,
+        output_args,
+        encoder_version,
+        command,
+        search,
+    } = provenance;
+    let size = tokio::fs::metadata(&input).await?.len();
+    let video_stream = ffprobe::probe(&input, None).video_stream;
+    let video_size = ffprobe::video_stream_size(&input, video_stream)
+        .await
+        .context("ffprobe video stream size")?;
+
+    let label = |l: &str| style(format!("{l:<12}")).dim();
+    println!("{}ab-av1 {version}", label("Encoded by"));
+    match encoder_version {
+        Some(v) => println!("{}{vcodec} {v}", label("Encoder")),
+        None => println!("{}{vcodec}", label("Encoder")),
+    }
+    println!("{}{}", label("crf"), TerseF32(crf));
+    for (name, value) in [
+        ("preset", preset),
+        ("pix_fmt", pix_fmt),
+        ("vfilter", vfilter),
+    ] {
+        if let Some(value) = value {
+            println!("{}{value}", label(name));
+        }
+    }
+    if !input_args.is_empty() {
+        println!("{}{}", label("input args"), input_args.join(" "));
+    }
+    if !output_args.is_empty() {
+        println!("{}{}", label("output args"), output_args.join(" "));
+    }
+    if let Some(search) = search {
+        let scores: Vec<_> = search
+            .scores
+            .iter()
+            .map(|s| format!("{} {:.2}, min {}", s.kind, s.score, TerseF32(s.min)))
+            .collect();
+        println!(
+            "{}{}, max size {}%",
+            label("Searched"),
+            scores.join(", "),
+            TerseF32(search.max_encoded_percent),
+        );
+        println!(
+            "{}{} video ({:.0}%)",
+            label("Predicted"),
+            HumanBytes(search.predicted_size),
+            search.predicted_percent,
+        );
+        if !search.fallbacks.is_empty() {
+            println!("{}{}", label("Fallbacks"), search.fallbacks.join(", "));
+        }
+    }
+    println!("{}{} video", label("Size"), HumanBytes(video_size));
+    println!("{}{}", label("File size"), HumanBytes(size));
+    if let Some(command) = command {
+        println!(
+            "\n{} {}",
+            style("Encode with:").dim(),
+            style(command).italic()
+        );
+    }
+    Ok(())
+}
@@ -1,18 +1,23 @@
 mod cache;
 
+pub use crate::metric::ScoreKind;
+
 use crate::{
     command::{
-        PROGRESS_CHARS, SmallDuration,
-        args::{self, PixelFormat},
+        PROGRESS_CHARS, SmallDuration, analyse_input,
+        args::{self, PixelFormat, SampleExtract},
     },
     console_ext::style,
     ffmpeg::{self, FfmpegEncodeArgs, remove_arg},
     ffprobe::{self, Ffprobe},
+    frame_report::{self, FrameReport},
     log::ProgressLogger,
+    metric::{self, MetricOut},
     process::FfmpegOut,
-    sample, temporary,
-    vmaf::{self, VmafOut},
-    xpsnr::{self, XpsnrOut},
+    sample::{self, SampleLen},
+    script, sequence,
+    temporary::{self, TempKind},
+    vmaf, xpsnr,
 };
 use anyhow::{Context, ensure};
 use clap::{ArgAction, Parser};
@@ -21,10 +26,11 @@ use futures_util::Stream;
 use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
 use log::info;
 use std::{
-    fmt::Display,
+    collections::BTreeMap,
+    fmt::Write,
     io::{self, IsTerminal},
-    path::{Path, PathBuf},
-    pin::pin,
+    path::PathBuf,
+    pin::{Pin, pin},
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -79,6 +85,41 @@ pub struct Args {
     /// Calculate a XPSNR score instead of VMAF.
     #[arg(long)]
     pub xpsnr: bool,
+
+    /// Calculate these scores instead of VMAF, e.g. `--metric ssim,psnr`.
+    /// The first is used as the main score.
+    ///
+    /// ssimulacra2 & butteraugli use the libjxl `ssimulacra2` & `butteraugli_main` tools.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub metric: Vec<ScoreKind>,
+
+    /// Collect per-frame VMAF scores, used for VMAF percentiles.
+    #[arg(skip)]
+    pub vmaf_frames: bool,
+
+    /// Also score each encoded sample with these VMAF options, e.g. crf-search `--fast-score`.
+    /// See [`Output::fast_vmaf`].
+    #[arg(skip)]
+    pub fast_vmaf: Option<args::Vmaf>,
+}
+
+impl Args {
+    /// Score kinds to calculate, the main score first.
+    pub fn score_kinds(&self) -> Vec<ScoreKind> {
+        let mut kinds = self.metric.clone();
+        if self.xpsnr && !kinds.contains(&ScoreKind::Xpsnr) {
+            kinds.insert(0, ScoreKind::Xpsnr);
+        }
+        match self.vmaf.and_vmaf.unwrap_or(kinds.is_empty()) {
+            true if !kinds.contains(&ScoreKind::Vmaf) => kinds.push(ScoreKind::Vmaf),
+            true => {}
+            false => kinds.retain(|k| *k != ScoreKind::Vmaf),
+        }
+        if self.vmaf_frames && !kinds.contains(&ScoreKind::Vmaf) {
+            kinds.push(ScoreKind::Vmaf);
+        }
+        kinds
+    }
 }
 
 pub async fn sample_encode(mut args: Args) -> anyhow::Result<()> {
@@ -92,7 +133,10 @@ pub async fn sample_encode(mut args: Args) -> anyhow::Result<()> {
     );
     bar.enable_steady_tick(Duration::from_millis(100));
 
-    let probe = ffprobe::probe(&args.args.input);
+    args.args.validate_fps()?;
+    args.args.validate_encoder(&[args.crf]).await?;
+    let probe = ffprobe::probe(&args.args.input, args.args.fps);
+    analyse_input(&mut args.args, Some(&mut args.score), &probe, &bar).await?;
     args.sample
         .set_extension_from_input(&args.args.input, &args.args.encoder, &probe);
 
@@ -141,8 +185,9 @@ pub async fn sample_encode(mut args: Args) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn run(
-    Args {
+pub fn run(args: Args, input_probe: Arc<Ffprobe>) -> impl Stream<Item = anyhow::Result<Update>> {
+    let score_kinds = args.score_kinds();
+    let Args {
         args,
         crf,
         sample: sample_args,
@@ -150,16 +195,24 @@ pub fn run(
         stdout_format: _,
         vmaf,
         score,
-        xpsnr,
+        xpsnr: _,
         xpsnr_opts,
-    }: Args,
-    input_probe: Arc<Ffprobe>,
-) -> impl Stream<Item = anyhow::Result<Update>> {
+        metric: _,
+        vmaf_frames,
+        fast_vmaf,
+    } = args;
+    // the fast VMAF pass follows the other scores
+    let score_passes: Vec<_> = score_kinds
+        .iter()
+        .map(|kind| (*kind, false))
+        .chain(fast_vmaf.iter().map(|_| (ScoreKind::Vmaf, true)))
+        .collect();
+
     async_stream::try_stream! {
         let input = Arc::new(args.input.clone());
         let input_pix_fmt = input_probe.pixel_format();
         let input_is_image = input_probe.is_image;
-        let input_len = fs::metadata(&*input).await?.len();
+        let input_len = script::input_size(&input).await?;
         let sample_out_ext = sample_args.extension.as_deref().unwrap_or("mkv");
         let mut enc_args = args.to_ffmpeg_args(crf, &input_probe, sample_out_ext)?;
         // ignore user -fps_mode for sample encoding, as we always use passthrough
@@ -171,14 +224,17 @@ pub fn run(
         let samples = sample_args.sample_count(duration).max(1);
         let keep = sample_args.keep;
         let temp_dir = sample_args.temp_dir;
+        let extract = sample_args.sample_extract;
 
         let (samples, sample_duration, full_pass) = {
             if input_is_image {
                 (1, duration.max(Duration::from_secs(1)), true)
-            } else if sample_args.sample_duration.is_zero()
+            } else if input_probe.animated
+                || sample_args.sample_duration.is_zero()
                 || sample_args.sample_duration * samples as _ >= duration.mul_f64(0.85)
             {
-                // if the sample time is most of the full input time just encode the whole thing
+                // if the sample time is most of the full input time just encode the whole thing,
+                // animated images are also short enough & don't stream copy well
                 (1, duration, true)
             } else {
                 let sample_duration = if input_fps > 0.0 {
@@ -197,20 +253,37 @@ pub fn run(
         let (tx, mut sample_tasks) = tokio::sync::mpsc::unbounded_channel();
         let sample_temp = temp_dir.clone();
         let sample_in = input.clone();
+        let sample_fps = enc_args.input_fps;
+        let sample_probe = input_probe.clone();
         let sample_task = tokio::task::spawn_local(async move {
-            if full_pass {
+            if full_pass && sample_probe.video_stream == 0 {
                 // Use the entire video as a single sample
                 let _ = tx.send((0, Ok((sample_in.clone(), input_len))));
+            } else if full_pass {
+                // Copy the entire primary video stream, skipping e.g. cover art
+                let sample = sample(
+                    sample_in.clone(),
+                    sample_fps,
+                    0,
+                    1,
+                    duration,
+                    &sample_probe,
+                    sample_temp.clone(),
+                    extract,
+                )
+                .await;
+                let _ = tx.send((0, sample));
             } else {
                 for sample_idx in 0..samples {
                     let sample = sample(
                         sample_in.clone(),
+                        sample_fps,
                         sample_idx,
                         samples,
                         sample_duration,
-                        duration,
-                        input_fps,
+                        &sample_probe,
                         sample_temp.clone(),
+                        extract,
                     )
                     .await;
                     if tx.send((sample_idx, sample)).is_err() {
@@ -248,7 +321,7 @@ pub fn run(
                 input_len,
                 full_pass,
                 &enc_args,
-                (&score, &vmaf, &xpsnr),
+                (&score, &vmaf, &score_kinds, vmaf_frames, &fast_vmaf),
             )
             .await
             {
@@ -283,15 +356,15 @@ pub fn run(
                             logger.update(sample_duration, time, fps);
                         }
                     }
-                    output.wait().await?; // ensure process has exited
 
                     let encode_time = b.elapsed();
                     let encoded_size = fs::metadata(&encoded_sample).await?.len();
-                    let encoded_probe = ffprobe::probe(&encoded_sample);
+                    let encoded_probe = ffprobe::probe(&encoded_sample, None);
 
                     let mut result = EncodeResult {
-                        vmaf_score: None,
-                        xpsnr_score: None,
+                        scores: <_>::default(),
+                        vmaf_frames: vec![],
+                        fast_vmaf: None,
                         sample_size,
                         encoded_size,
                         encode_time,
@@ -303,113 +376,109 @@ pub fn run(
                         from_cache: false,
                     };
 
-                    let do_vmaf = vmaf.and_vmaf.unwrap_or(!xpsnr);
-                    if xpsnr {
+                    let pix_fmt = PixelFormat::opt_max(enc_args.pix_fmt, input_pix_fmt);
+                    let ref_vfilter = score.ref_vfilter(args.vfilter.as_deref());
+                    let vmaf_log = vmaf_frames.then(|| encoded_sample.with_extension("vmaf.json"));
+                    if let Some(log) = &vmaf_log {
+                        temporary::add(log, TempKind::NotKeepable);
+                    }
+                    for (pass_idx, (kind, fast)) in score_passes.iter().copied().enumerate() {
+                        // scoring is the 2nd half of each sample's progress, split between passes
+                        let score_progress = |p: f32| {
+                            (sample_idx as f32
+                                + 0.5
+                                + (pass_idx as f32 + p.min(1.0)) / (score_passes.len() * 2) as f32)
+                                / samples as f32
+                        };
+                        let vmaf = fast_vmaf.as_ref().filter(|_| fast).unwrap_or(&vmaf);
+                        let vmaf_log = vmaf_log.as_ref().filter(|_| !fast);
                         yield Update::Status(Status {
-                            work: Work::Score(ScoreKind::Xpsnr),
+                            work: Work::Score(kind),
                             fps: 0.0,
-                            progress: (sample_idx as f32 + 0.5) / samples as f32,
+                            progress: score_progress(0.0),
                             full_pass,
                             sample: sample_n,
                             samples,
                         });
 
-                        let lavfi = super::xpsnr::lavfi(
-                            score.reference_vfilter.as_deref().or(args.vfilter.as_deref()),
-                            xpsnr_opts.xpsnr_pix_format
-                                .or_else(|| PixelFormat::opt_max(enc_args.pix_fmt, input_pix_fmt)),
-                        );
-                        let xpsnr_out = xpsnr::run(&sample, &encoded_sample, &lavfi, xpsnr_opts.fps())?;
-                        let mut xpsnr_out = pin!(xpsnr_out);
-                        let mut logger = ProgressLogger::new("ab_av1::xpsnr", Instant::now());
-                        while let Some(next) = xpsnr_out.next().await {
-                            match next {
-                                XpsnrOut::Done(s) => {
-                                    result.xpsnr_score = Some(s);
-                                }
-                                XpsnrOut::Progress(FfmpegOut::Progress { time, fps, .. }) => {
-                                    let progress = match do_vmaf {
-                                        false => (sample_duration_us +
-                                            time.as_micros_u64() +
-                                            sample_idx * sample_duration_us * 2) as f32
-                                            / (sample_duration_us * samples * 2) as f32,
-                                        true => (sample_duration_us +
-                                            time.as_micros_u64() / 2 +
-                                            sample_idx * sample_duration_us * 2) as f32
-                                            / (sample_duration_us * samples * 2) as f32
-                                    };
-                                    yield Update::Status(Status {
-                                        work: Work::Score(ScoreKind::Xpsnr),
-                                        fps,
-                                        progress,
-                                        full_pass,
-                                        sample: sample_n,
-                                        samples,
-                                    });
-                                    logger.update(sample_duration, time, fps);
+                        let mut score_out: Pin<Box<dyn Stream<Item = MetricOut>>> = match kind {
+                            ScoreKind::Vmaf => {
+                                let mut lavfi = vmaf.ffmpeg_lavfi(
+                                    encoded_probe.resolution,
+                                    pix_fmt,
+                                    ref_vfilter.as_deref(),
+                                );
+                                if let Some(log) = vmaf_log {
+                                    _ = write!(
+                                        lavfi,
+                                        ":log_fmt=json:log_path={}",
+                                        frame_report::filter_path(log)
+                                    );
                                 }
-                                XpsnrOut::Progress(_) => {}
-                                XpsnrOut::Err(e) => Err(e)?,
+                                Box::pin(vmaf::run(
+                                    &sample,
+                                    enc_args.input_fps,
+                                    &encoded_sample,
+                                    &lavfi,
+                                    vmaf.fps(),
+                                    score.grain_synthesis,
+                                    None,
+                                )?.map(MetricOut::from))
                             }
-                        }
-                    }
-                    if do_vmaf {
-                        let init_progress = match xpsnr {
-                            false => (sample_idx as f32 + 0.5) / samples as f32,
-                            true => (sample_idx as f32 + 0.75) / samples as f32,
+                            ScoreKind::Xpsnr => Box::pin(xpsnr::run(
+                                &sample,
+                                enc_args.input_fps,
+                                &encoded_sample,
+                                &super::xpsnr::lavfi(
+                                    ref_vfilter.as_deref(),
+                                    xpsnr_opts.xpsnr_pix_format.or(pix_fmt),
+                                ),
+                                xpsnr_opts.fps(),
+                                score.grain_synthesis,
+                                None,
+                            )?.map(MetricOut::from)),
+                            kind => Box::pin(metric::run(
+                                kind,
+                                &sample,
+                                enc_args.input_fps,
+                                &encoded_sample,
+                                (0, 0),
+                                ref_vfilter.as_deref(),
+                                pix_fmt,
+                                score.grain_synthesis,
+                                None,
+                                temp_dir.clone(),
+                            )?),
                         };
-                        yield Update::Status(Status {
-                            work: Work::Score(ScoreKind::Vmaf),
-                            fps: 0.0,
-                            progress: init_progress,
-                            full_pass,
-                            sample: sample_n,
-                            samples,
-                        });
-                        let vmaf = vmaf::run(
-                            &sample,
-                            &encoded_sample,
-                            &vmaf.ffmpeg_lavfi(
-                                encoded_probe.resolution,
-                                PixelFormat::opt_max(enc_args.pix_fmt, input_pix_fmt),
-                                score.reference_vfilter.as_deref().or(args.vfilter.as_deref()),
-                            ),
-                            vmaf.fps(),
-                        )?;
-                        let mut vmaf = pin!(vmaf);
-                        let mut logger = ProgressLogger::new("ab_av1::vmaf", Instant::now());
-                        while let Some(vmaf) = vmaf.next().await {
-                            match vmaf {
-                                VmafOut::Done(score) => {
-                                    result.vmaf_score = Some(score);
+                        let mut logger = ProgressLogger::new(kind.log_target(), Instant::now());
+                        while let Some(next) = score_out.next().await {
+                            match next {
+                                MetricOut::Done(score) if fast => result.fast_vmaf = Some(score),
+                                MetricOut::Done(score) => {
+                                    result.scores.insert(kind, score);
                                 }
-                                VmafOut::Progress(FfmpegOut::Progress { time, fps, .. }) => {
-                                    let progress = match xpsnr {
-                                        false => (sample_duration_us +
-                                            time.as_micros_u64() +
-                                            sample_idx * sample_duration_us * 2) as f32
-                                            / (sample_duration_us * samples * 2) as f32,
-                                        true => (sample_duration_us + sample_duration_us / 2 +
-                                            time.as_micros_u64() / 2 +
-                                            sample_idx * sample_duration_us * 2) as f32
-                                            / (sample_duration_us * samples * 2) as f32,
-                                    };
+                                MetricOut::Progress(FfmpegOut::Progress { time, fps, .. }) => {
                                     yield Update::Status(Status {
-                                        work: Work::Score(ScoreKind::Vmaf),
+                                        work: Work::Score(kind),
                                         fps,
-                                        progress,
+                                        progress: score_progress(
+                                            time.as_micros_u64() as f32 / sample_duration_us as f32
+                                        ),
                                         full_pass,
                                         sample: sample_n,
                                         samples,
                                     });
                                     logger.update(sample_duration, time, fps);
                                 }
-                                VmafOut::Progress(_) => {}
-                                VmafOut::Err(e) => Err(e)?,
+                                MetricOut::Progress(_) => {}
+                                MetricOut::Err(e) => Err(e)?,
                             }
                         }
+                        if let (ScoreKind::Vmaf, Some(log)) = (kind, vmaf_log) {
+                            let report = FrameReport::from_vmaf_log(&fs::read(log).await?, 0.0)?;
+                            result.vmaf_frames = report.scores().map(|s| s as f32).collect();
+                        }
                     }
-
                     if samples > 1 {
                         result.log_attempt(sample_n, samples, crf);
                     }
@@ -419,7 +488,7 @@ pub fn run(
                     }
 
                     // Early clean. Note: Avoid cleaning copy samples
-                    temporary::clean(true).await;
+                    temporary::clean_early().await;
                     if !keep {
                         let _ = tokio::fs::remove_file(encoded_sample).await;
                     }
@@ -435,25 +504,29 @@ pub fn run(
         sample_task.await.context("sample copy task")?;
 
         let output = Output {
-            vmaf_score: results.mean_vmaf_score(),
-            xpsnr_score: results.mean_xpsnr_score(),
+            scores: results.mean_scores(),
+            vmaf_frames: {
+                let mut frames: Vec<_> = results.iter().flat_map(|r| r.vmaf_frames.clone()).collect();
+                frames.sort_by(f32::total_cmp);
+                frames
+            },
+            fast_vmaf: results
+                .iter()
+                .map(|r| r.fast_vmaf)
+                .sum::<Option<f32>>()
+                .map(|total| total / results.len() as f32),
             // Using file size * encode_percent can over-estimate. However, if it ends up less
             // than the duration estimation it may turn out to be more accurate.
             predicted_encode_size: results
                 .estimate_encode_size_by_duration(duration, full_pass)
-                .min(estimate_encode_size_by_file_percent(&results, &input, full_pass).await?),
+                .min(results.estimate_encode_size_by_file_percent(input_len, full_pass)),
             encode_percent: results.encoded_percent_size(),
             predicted_encode_time: results.estimate_encode_time(duration, full_pass),
             from_cache: results.iter().all(|r| r.from_cache),
         };
         info!(
-            "crf {crf}{}{} predicted video stream size {} ({:.0}%) taking {}{}",
-            output.vmaf_score
-                .map(|s| format!(" VMAF {s:.2}"))
-                .unwrap_or_default(),
-            output.xpsnr_score
-                .map(|s| format!(" XPSNR {s:.2}"))
-                .unwrap_or_default(),
+            "crf {crf}{} predicted video stream size {} ({:.0}%) taking {}{}",
+            fmt_scores(&output.scores),
             HumanBytes(output.predicted_encode_size),
             output.encode_percent,
             HumanDuration(output.predicted_encode_time),
@@ -465,41 +538,109 @@ pub fn run(
 }
 
 /// Copy a sample from the input to the temp_dir (or input dir).
+///
+/// With [`SampleExtract::Auto`] the sample is extracted losslessly instead if the copy
+/// would start too far before the sample start, or the copy fails.
+/// Image sequences are read at `input_fps`, i.e. `--fps`.
+#[allow(clippy::too_many_arguments)]
 async fn sample(
     input: Arc<PathBuf>,
+    input_fps: Option<f64>,
     sample_idx: u64,
     samples: u64,
     sample_duration: Duration,
-    duration: Duration,
-    fps: f64,
+    probe: &Ffprobe,
     temp_dir: Option<PathBuf>,
+    extract: SampleExtract,
 ) -> anyhow::Result<(Arc<PathBuf>, u64)> {
-    let sample_n = sample_idx + 1;
+    let duration = probe.duration.clone()?;
+    let fps = probe.fps.clone()?;
+    let sample_start = sample::start(sample_idx, samples, sample_duration, duration);
+
+    // vfr frame counts at the average fps don't match the duration, so cut by time
+    let len = match probe.vfr {
+        true => SampleLen::Time(sample_duration),
+        false => SampleLen::Frames(((sample_duration.as_secs_f64() * fps).round() as u32).max(1)),
+    };
+    let floor_to_sec = sample_duration >= Duration::from_secs(2);
 
-    let sample_start = (duration.saturating_sub(sample_duration * samples as _)
-        / (samples as u32 + 1))
-        * sample_n as _
-        + sample_duration * sample_idx as _;
+    // scripts & image sequences are already cut by frame
+    let frame_accurate = script::is_script(&input) || sequence::is_sequence(&input);
+    let lossless = !frame_accurate
+        && match extract {
+            SampleExtract::Copy => false,
+            SampleExtract::Lossless => true,
+            SampleExtract::Auto => ffprobe::seek_keyframe(&input, probe.video_stream, sample_start)
+                .await
+                .ok()
+                .flatten()
+                .is_some_and(|key| sample_start.saturating_sub(key) > sample_duration),
+        };
 
-    let sample_frames = ((sample_duration.as_secs_f64() * fps).round() as u32).max(1);
-    let floor_to_sec = sample_duration >= Duration::from_secs(2);
+    if !lossless {
+        let copied = sample::copy(
+            &input,
+            input_fps,
+            probe.video_stream,
+            sample_start,
+            floor_to_sec,
+            len,
+            temp_dir.clone(),
+        )
+        .await;
+        let copy_err = match copied {
+            Ok(sample) => {
+                let sample_size = script::input_size(&sample).await?;
+                // ffmpeg copy may fail successfully and give us a small/empty output
+                if sample_size > 1024 {
+                    return Ok((sample.into(), sample_size));
+                }
+                anyhow::anyhow!("ffmpeg copy failed: encoded sample too small")
+            }
+            Err(err) => err,
+        };
+        ensure!(lossless_after_copy_err(frame_accurate, extract), copy_err);
+        info!("sample copy failed, extracting losslessly: {copy_err:#}");
+    }
 
-    let sample = sample::copy(&input, sample_start, floor_to_sec, sample_frames, temp_dir).await?;
-    let sample_size = fs::metadata(&sample).await?.len();
-    ensure!(
-        // ffmpeg copy may fail successfully and give us a small/empty output
-        sample_size > 1024,
-        "ffmpeg copy failed: encoded sample too small"
-    );
-    Ok((sample.into(), sample_size))
+    let sample = sample::lossless(
+        &input,
+        probe.video_stream,
+        sample_start,
+        floor_to_sec,
+        len,
+        temp_dir,
+    )
+    .await?;
+    // a lossless sample's size isn't comparable to the input, so use the
+    // input size of the same duration for encoded percent calculations
+    let input_len = script::input_size(&input).await?;
+    let sample_size = (input_len as f64 * sample_duration.as_secs_f64()
+        / duration.as_secs_f64().max(f64::EPSILON))
+    .round() as u64;
+    Ok((sample.into(), sample_size.max(1)))
+}
+
+/// Whether a failed sample stream copy should fall back to lossless extraction.
+///
+/// Only `--sample-extract auto` falls back & frame accurate inputs have no other way to cut.
+fn lossless_after_copy_err(frame_accurate: bool, extract: SampleExtract) -> bool {
+    !frame_accurate && extract == SampleExtract::Auto
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct EncodeResult {
     pub sample_size: u64,
     pub encoded_size: u64,
-    pub vmaf_score: Option<f32>,
-    pub xpsnr_score: Option<f32>,
+    /// Scores by kind, e.g. VMAF.
+    #[serde(default)]
+    pub scores: BTreeMap<ScoreKind, f32>,
+    /// Per-frame VMAF scores, if requested.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vmaf_frames: Vec<f32>,
+    /// Fast VMAF score, if requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fast_vmaf: Option<f32>,
     pub encode_time: Duration,
     /// Duration of the sample.
     ///
@@ -514,22 +655,16 @@ impl EncodeResult {
         let Self {
             sample_size,
             encoded_size,
-            vmaf_score,
-            xpsnr_score,
+            scores,
             from_cache,
             ..
         } = self;
         bar.println(
             style!(
-                "- {}Sample {sample_n} ({:.0}%){}{}{}",
+                "- {}Sample {sample_n} ({:.0}%){}{}",
                 crf.map(|crf| format!("crf {crf}: ")).unwrap_or_default(),
                 100.0 * *encoded_size as f32 / *sample_size as f32,
-                vmaf_score
-                    .map(|s| format!(" VMAF {s:.2}"))
-                    .unwrap_or_default(),
-                xpsnr_score
-                    .map(|s| format!(" XPSNR {s:.2}"))
-                    .unwrap_or_default(),
+                fmt_scores(scores),
                 if *from_cache { " (cache)" } else { "" },
             )
             .dim()
@@ -541,61 +676,32 @@ impl EncodeResult {
         let Self {
             sample_size,
             encoded_size,
-            vmaf_score,
-            xpsnr_score,
+            scores,
             from_cache,
             ..
         } = self;
         info!(
-            "sample {sample_n}/{samples} crf {crf}{}{} ({:.0}%){}",
-            vmaf_score
-                .map(|s| format!(" VMAF {s:.2}"))
-                .unwrap_or_default(),
-            xpsnr_score
-                .map(|s| format!(" XPSNR {s:.2}"))
-                .unwrap_or_default(),
+            "sample {sample_n}/{samples} crf {crf}{} ({:.0}%){}",
+            fmt_scores(scores),
             100.0 * *encoded_size as f32 / *sample_size as f32,
             if *from_cache { " (cache)" } else { "" }
         );
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
-pub enum ScoreKind {
-    Vmaf,
-    Xpsnr,
-}
-
-impl ScoreKind {
-    /// Display label for fps in progress bar.
-    pub fn fps_label(&self) -> &'static str {
-        match self {
-            Self::Vmaf => "vmaf",
-            Self::Xpsnr => "xpsnr",
-        }
-    }
-
-    /// General display name.
-    pub fn display_str(&self) -> &'static str {
-        match self {
-            Self::Vmaf => "VMAF",
-            Self::Xpsnr => "XPSNR",
-        }
-    }
-}
-
-impl Display for ScoreKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.display_str())
+/// Format scores, e.g. " VMAF 95.12 SSIM 0.9961".
+fn fmt_scores(scores: &BTreeMap<ScoreKind, f32>) -> String {
+    let mut out = String::new();
+    for (kind, score) in scores {
+        _ = write!(out, " {kind} {score:.*}", kind.decimals());
     }
+    out
 }
 
 trait EncodeResults {
     fn encoded_percent_size(&self) -> f64;
 
-    fn mean_vmaf_score(&self) -> Option<f32>;
-
-    fn mean_xpsnr_score(&self) -> Option<f32>;
+    fn mean_scores(&self) -> BTreeMap<ScoreKind, f32>;
 
     /// Return estimated encoded **video stream** size by multiplying sample size by duration.
     fn estimate_encode_size_by_duration(
@@ -604,6 +710,12 @@ trait EncodeResults {
         single_full_pass: bool,
     ) -> u64;
 
+    /// Return estimated encoded **video stream** size by applying the sample percentage
+    /// change to the `input_len`, see [`script::input_size`].
+    ///
+    /// This can over-estimate the larger the non-video proportion of the input.
+    fn estimate_encode_size_by_file_percent(&self, input_len: u64, single_full_pass: bool) -> u64;
+
     fn estimate_encode_time(&self, input_duration: Duration, single_full_pass: bool) -> Duration;
 }
 
@@ -617,16 +729,15 @@ impl EncodeResults for Vec<EncodeResult> {
         encoded * 100.0 / sample
     }
 
-    fn mean_vmaf_score(&self) -> Option<f32> {
-        let mut scores = self.iter().filter_map(|r| r.vmaf_score).peekable();
-        scores.peek()?;
-        Some(scores.sum::<f32>() / self.len() as f32)
-    }
-
-    fn mean_xpsnr_score(&self) -> Option<f32> {
-        let mut scores = self.iter().filter_map(|r| r.xpsnr_score).peekable();
-        scores.peek()?;
-        Some(scores.sum::<f32>() / self.len() as f32)
+    fn mean_scores(&self) -> BTreeMap<ScoreKind, f32> {
+        let mut totals = BTreeMap::new();
+        for (kind, score) in self.iter().flat_map(|r| &r.scores) {
+            *totals.entry(*kind).or_insert(0.0) += score;
+        }
+        for total in totals.values_mut() {
+            *total /= self.len() as f32;
+        }
+        totals
     }
 
     fn estimate_encode_size_by_duration(
@@ -648,6 +759,16 @@ impl EncodeResults for Vec<EncodeResult> {
         (sample_encode_size * sample_factor).round() as _
     }
 
+    fn estimate_encode_size_by_file_percent(&self, input_len: u64, single_full_pass: bool) -> u64 {
+        if self.is_empty() {
+            return 0;
+        }
+        if single_full_pass {
+            return self[0].encoded_size;
+        }
+        (input_len as f64 * self.encoded_percent_size() / 100.0).round() as _
+    }
+
     fn estimate_encode_time(&self, input_duration: Duration, single_full_pass: bool) -> Duration {
         if self.is_empty() {
             return Duration::ZERO;
@@ -669,26 +790,6 @@ impl EncodeResults for Vec<EncodeResult> {
     }
 }
 
-/// Return estimated encoded **video stream** size by applying the sample percentage
-/// change to the input file size.
-///
-/// This can over-estimate the larger the non-video proportion of the input.
-async fn estimate_encode_size_by_file_percent(
-    results: &Vec<EncodeResult>,
-    input: &Path,
-    single_full_pass: bool,
-) -> anyhow::Result<u64> {
-    if results.is_empty() {
-        return Ok(0);
-    }
-    if single_full_pass {
-        return Ok(results[0].encoded_size);
-    }
-    let encode_proportion = results.encoded_percent_size() / 100.0;
-
-    Ok((fs::metadata(input).await?.len() as f64 * encode_proportion).round() as _)
-}
-
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
 pub enum StdoutFormat {
     Human,
@@ -700,25 +801,23 @@ impl StdoutFormat {
         match self {
             Self::Human => {
                 let Output {
-                    vmaf_score,
-                    xpsnr_score,
+                    scores,
+                    vmaf_frames: _,
+                    fast_vmaf: _,
                     predicted_encode_size,
                     encode_percent,
                     predicted_encode_time,
                     from_cache: _,
                 } = output;
-                let vmaf_fmt = match *vmaf_score {
-                    None => format_args!(""),
-                    Some(s) => match s {
-                        _ if s >= 95.0 => format_args!("VMAF {} ", style(s).bold().green()),
-                        _ if s < 80.0 => format_args!("VMAF {} ", style(s).bold().red()),
-                        _ => format_args!("VMAF {} ", style(s).bold()),
-                    },
-                };
-                let xpsnr_fmt = match *xpsnr_score {
-                    None => format_args!(""),
-                    Some(s) => format_args!("XPSNR {} ", style(s).bold()),
-                };
+                let mut scores_fmt = String::new();
+                for (kind, &s) in scores {
+                    let s = match kind {
+                        ScoreKind::Vmaf if s >= 95.0 => style(s).bold().green(),
+                        ScoreKind::Vmaf if s < 80.0 => style(s).bold().red(),
+                        _ => style(s).bold(),
+                    };
+                    _ = write!(scores_fmt, "{kind} {s} ");
+                }
                 let percent = encode_percent.round();
                 let size = match *predicted_encode_size {
                     v if percent < 80.0 => style(HumanBytes(v)).bold().green(),
@@ -736,7 +835,7 @@ impl StdoutFormat {
                     false => "video stream",
                 };
                 println!(
-                    "{vmaf_fmt}{xpsnr_fmt}predicted {enc_description} size {size} ({percent}) taking {time}"
+                    "{scores_fmt}predicted {enc_description} size {size} ({percent}) taking {time}"
                 );
             }
             Self::Json => println!("{}", output.sample_encode_done_json(crf)),
@@ -744,13 +843,22 @@ impl StdoutFormat {
     }
 }
 
+/// Returns the `percentile` of `sorted` scores, `None` if empty.
+pub fn percentile(sorted: &[f32], percentile: f32) -> Option<f32> {
+    let last = sorted.len().checked_sub(1)?;
+    let idx = (percentile / 100.0 * last as f32).round() as usize;
+    sorted.get(idx).copied()
+}
+
 /// Sample encode result.
 #[derive(Debug, Clone)]
 pub struct Output {
-    /// Sample mean VMAF score.
-    pub vmaf_score: Option<f32>,
-    /// Sample mean XPSNR score.
-    pub xpsnr_score: Option<f32>,
+    /// Sample mean scores by kind.
+    pub scores: BTreeMap<ScoreKind, f32>,
+    /// All sample per-frame VMAF scores, sorted, if requested.
+    pub vmaf_frames: Vec<f32>,
+    /// Sample mean VMAF score using [`Args::fast_vmaf`] options, if requested.
+    pub fast_vmaf: Option<f32>,
     /// Estimated full encoded **video stream** size.
     ///
     /// Encoded sample size multiplied by duration.
@@ -766,19 +874,6 @@ pub struct Output {
 }
 
 impl Output {
-    /// Extract vmaf or xpsnr score. Use when it is expected to have only 1 of these.
-    pub fn single_score(&self) -> f32 {
-        self.vmaf_score.or(self.xpsnr_score).unwrap_or_default()
-    }
-
-    /// Extract vmaf or xpsnr kind. Use when it is expected to have only 1 of these.
-    pub fn single_score_kind(&self) -> ScoreKind {
-        match self.vmaf_score {
-            Some(_) => ScoreKind::Vmaf,
-            _ => ScoreKind::Xpsnr,
-        }
-    }
-
     /// `sample-encode-done` json message, see _stdout-format-json.md_.
     pub fn sample_encode_done_json(&self, crf: f32) -> serde_json::Value {
         let mut json = serde_json::json!({
@@ -789,11 +884,8 @@ impl Output {
             "predicted_encode_percent": self.encode_percent,
             "predicted_encode_seconds": self.predicted_encode_time.as_secs_f64(),
         });
-        if let Some(score) = self.vmaf_score {
-            json["vmaf"] = score.into();
-        }
-        if let Some(score) = self.xpsnr_score {
-            json["xpsnr"] = score.into();
+        for (kind, score) in &self.scores {
+            json[kind.json_key()] = (*score).into();
         }
         json
     }
@@ -802,8 +894,9 @@ impl Output {
 #[test]
 fn sample_encode_done_json_message() {
     let mut output = Output {
-        vmaf_score: Some(95.5),
-        xpsnr_score: None,
+        scores: [(ScoreKind::Vmaf, 95.5)].into(),
+        vmaf_frames: vec![],
+        fast_vmaf: None,
         predicted_encode_size: 38889644,
         encode_percent: 41.25,
         predicted_encode_time: Duration::from_secs(1560),
@@ -814,19 +907,109 @@ fn sample_encode_done_json_message() {
         r#"{"crf":34.0,"from_cache":false,"predicted_encode_percent":41.25,"predicted_encode_seconds":1560.0,"predicted_encode_size":38889644,"type":"sample-encode-done","vmaf":95.5}"#
     );
 
-    output.vmaf_score = None;
-    output.xpsnr_score = Some(41.5);
+    output.scores = [(ScoreKind::Xpsnr, 41.5)].into();
     output.from_cache = true;
     assert_eq!(
         output.sample_encode_done_json(28.25).to_string(),
         r#"{"crf":28.25,"from_cache":true,"predicted_encode_percent":41.25,"predicted_encode_seconds":1560.0,"predicted_encode_size":38889644,"type":"sample-encode-done","xpsnr":41.5}"#
     );
 
-    output.vmaf_score = Some(95.5);
+    output.scores.insert(ScoreKind::Vmaf, 95.5);
     assert_eq!(
         output.sample_encode_done_json(28.25).to_string(),
         r#"{"crf":28.25,"from_cache":true,"predicted_encode_percent":41.25,"predicted_encode_seconds":1560.0,"predicted_encode_size":38889644,"type":"sample-encode-done","vmaf":95.5,"xpsnr":41.5}"#
     );
+
+    output.scores = [(ScoreKind::Ssim, 0.875), (ScoreKind::MsSsim, 0.9375)].into();
+    assert_eq!(
+        output.sample_encode_done_json(28.25).to_string(),
+        r#"{"crf":28.25,"from_cache":true,"ms_ssim":0.9375,"predicted_encode_percent":41.25,"predicted_encode_seconds":1560.0,"predicted_encode_size":38889644,"ssim":0.875,"type":"sample-encode-done"}"#
+    );
+}
+
+#[test]
+fn percentile_of_sorted() {
+    assert_eq!(percentile(&[], 5.0), None);
+
+    let frames: Vec<_> = (0..=100).map(|s| s as f32).collect();
+    assert_eq!(percentile(&frames, 0.0), Some(0.0));
+    assert_eq!(percentile(&frames, 5.0), Some(5.0));
+    assert_eq!(percentile(&frames, 100.0), Some(100.0));
+}
+
+#[test]
+fn copy_err_lossless_fallback() {
+    assert!(lossless_after_copy_err(false, SampleExtract::Auto));
+    // explicit copy fails rather than silently extracting losslessly
+    assert!(!lossless_after_copy_err(false, SampleExtract::Copy));
+    // scripts & sequences are cut by frame, so there is no lossless fallback
+    assert!(!lossless_after_copy_err(true, SampleExtract::Auto));
+}
+
+#[test]
+fn estimate_size_of_script_input() {
+    let result = EncodeResult {
+        sample_size: 1000,
+        encoded_size: 10,
+        scores: <_>::default(),
+        vmaf_frames: vec![],
+        fast_vmaf: None,
+        encode_time: Duration::from_secs(1),
+        sample_duration: Duration::from_secs(20),
+        from_cache: false,
+    };
+    let results = vec![result.clone(), result];
+    // scripts are sized by their rendered raw video, not the tiny script file
+    let script = script::Info {
+        width: 1920,
+        height: 1080,
+        frames: 240,
+        fps: 24.0,
+        pix_fmt: Some("yuv420p".into()),
+        frame_bytes: 1920 * 1080 * 3 / 2,
+    };
+    assert_eq!(
+        results.estimate_encode_size_by_file_percent(script.size(), false),
+        script.size() / 100
+    );
+    assert_eq!(results.estimate_encode_size_by_file_percent(0, true), 10);
+}
+
+#[test]
+fn estimate_size_of_sequence_input() {
+    let dir = std::env::temp_dir().join(format!("ab-av1-seq-size-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (n, len) in [(1, 1000), (2, 2000), (3, 3000)] {
+        std::fs::write(dir.join(format!("f_{n:05}.png")), vec![0; len]).unwrap();
+    }
+    let pattern = dir.join("f_%05d.png");
+    let input_size = |input| {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(script::input_size(input))
+    };
+    // sequences are sized by their frames, not the missing pattern file or the dir entry
+    let pattern_len = input_size(&pattern).unwrap();
+    let dir_len = input_size(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(pattern_len, 6000);
+    assert_eq!(dir_len, 6000);
+
+    let results = vec![EncodeResult {
+        sample_size: 100,
+        encoded_size: 25,
+        scores: <_>::default(),
+        vmaf_frames: vec![],
+        fast_vmaf: None,
+        encode_time: Duration::from_secs(1),
+        sample_duration: Duration::from_secs(1),
+        from_cache: false,
+    }];
+    assert_eq!(
+        results.estimate_encode_size_by_file_percent(pattern_len, false),
+        1500
+    );
 }
 
 /// Kinds of sample-encode work.
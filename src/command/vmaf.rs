@@ -2,14 +2,18 @@ use crate::{
     command::{
         PROGRESS_CHARS,
         args::{self, PixelFormat},
+        print_metrics,
     },
     ffprobe,
+    frame_report::{self, FrameReport},
     log::ProgressLogger,
+    metric::ScoreKind,
     process::FfmpegOut,
+    temporary::{self, TempKind},
     vmaf::{self, VmafOut},
 };
 use anyhow::Context;
-use clap::Parser;
+use clap::{Parser, ValueHint};
 use indicatif::{ProgressBar, ProgressStyle};
 use std::{
     path::PathBuf,
@@ -36,6 +40,17 @@ pub struct Args {
     #[arg(long)]
     pub distorted: PathBuf,
 
+    /// Write a per-frame score report to this file, json if it has a `.json` extension
+    /// otherwise csv. Also prints aggregate statistics & the worst time ranges.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub frame_report: Option<PathBuf>,
+
+    /// Also calculate these scores, e.g. `--metric ssim,psnr`, printed to stderr.
+    ///
+    /// ssimulacra2 & butteraugli use the libjxl `ssimulacra2` & `butteraugli_main` tools.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub metric: Vec<ScoreKind>,
+
     #[clap(flatten)]
     pub vmaf: args::Vmaf,
 
@@ -47,7 +62,9 @@ pub async fn vmaf(
     Args {
         reference,
         distorted,
-        vmaf,
+        frame_report,
+        metric,
+        mut vmaf,
         score,
     }: Args,
 ) -> anyhow::Result<()> {
@@ -59,23 +76,38 @@ pub async fn vmaf(
     bar.enable_steady_tick(Duration::from_millis(100));
     bar.set_message("vmaf running, ");
 
-    let dprobe = ffprobe::probe(&distorted);
-    let rprobe = ffprobe::probe(&reference);
+    let dprobe = ffprobe::probe(&distorted, None);
+    let rprobe = ffprobe::probe(&reference, None);
     let nframes = dprobe.nframes().or_else(|_| rprobe.nframes());
     let duration = dprobe.duration.as_ref().or(rprobe.duration.as_ref());
     if let Ok(nframes) = nframes {
         bar.set_length(nframes);
     }
 
+    let log = match &frame_report {
+        Some(_) => {
+            let log = temporary::process_dir(None)?.join("vmaf-frames.json");
+            temporary::add(&log, TempKind::NotKeepable);
+            let log_path = frame_report::filter_path(&log);
+            vmaf.vmaf_args
+                .push(format!("log_fmt=json:log_path={log_path}").into());
+            Some(log)
+        }
+        None => None,
+    };
+
     let mut vmaf = pin!(vmaf::run(
         &reference,
+        None,
         &distorted,
         &vmaf.ffmpeg_lavfi(
             dprobe.resolution,
             PixelFormat::opt_max(dprobe.pixel_format(), rprobe.pixel_format()),
-            score.reference_vfilter.as_deref(),
+            score.ref_vfilter(None).as_deref(),
         ),
         vmaf.fps(),
+        score.grain_synthesis,
+        None,
     )?);
     let mut logger = ProgressLogger::new(module_path!(), Instant::now());
     let mut vmaf_score = None;
@@ -101,8 +133,24 @@ pub async fn vmaf(
             VmafOut::Err(e) => return Err(e),
         }
     }
+    print_metrics(
+        &metric,
+        &reference,
+        &distorted,
+        &score,
+        PixelFormat::opt_max(dprobe.pixel_format(), rprobe.pixel_format()),
+        &bar,
+    )
+    .await?;
     bar.finish();
 
+    if let (Some(log), Some(path)) = (log, frame_report) {
+        let fps = dprobe.fps.or(rprobe.fps)?;
+        let report = FrameReport::from_vmaf_log(&tokio::fs::read(&log).await?, fps)?;
+        report.write(&path).await?;
+        report.print_summary();
+    }
+
     println!("{}", vmaf_score.context("no vmaf score")?);
     Ok(())
 }
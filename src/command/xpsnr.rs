@@ -2,14 +2,18 @@ use crate::{
     command::{
         PROGRESS_CHARS,
         args::{self, PixelFormat},
+        print_metrics,
     },
     ffprobe,
+    frame_report::{self, FrameReport},
     log::ProgressLogger,
+    metric::{self, ScoreKind},
     process::FfmpegOut,
+    temporary::{self, TempKind},
     xpsnr::{self, XpsnrOut},
 };
 use anyhow::Context;
-use clap::Parser;
+use clap::{Parser, ValueHint};
 use indicatif::{ProgressBar, ProgressStyle};
 use std::{
     fmt::Write,
@@ -33,6 +37,17 @@ pub struct Args {
     #[arg(long)]
     pub distorted: PathBuf,
 
+    /// Write a per-frame score report to this file, json if it has a `.json` extension
+    /// otherwise csv. Also prints aggregate statistics & the worst time ranges.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub frame_report: Option<PathBuf>,
+
+    /// Also calculate these scores, e.g. `--metric ssim,psnr`, printed to stderr.
+    ///
+    /// ssimulacra2 & butteraugli use the libjxl `ssimulacra2` & `butteraugli_main` tools.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub metric: Vec<ScoreKind>,
+
     #[clap(flatten)]
     pub score: args::ScoreArgs,
 
@@ -44,6 +59,8 @@ pub async fn xpsnr(
     Args {
         reference,
         distorted,
+        frame_report,
+        metric,
         score,
         xpsnr,
     }: Args,
@@ -56,31 +73,45 @@ pub async fn xpsnr(
     bar.enable_steady_tick(Duration::from_millis(100));
     bar.set_message("xpsnr running, ");
 
-    let dprobe = ffprobe::probe(&distorted);
-    let rprobe = ffprobe::probe(&reference);
+    let dprobe = ffprobe::probe(&distorted, None);
+    let rprobe = ffprobe::probe(&reference, None);
     let nframes = dprobe.nframes().or_else(|_| rprobe.nframes());
     let duration = dprobe.duration.as_ref().or(rprobe.duration.as_ref());
     if let Ok(nframes) = nframes {
         bar.set_length(nframes);
     }
 
+    let mut lavfi = lavfi(
+        score.ref_vfilter(None).as_deref(),
+        xpsnr
+            .xpsnr_pix_format
+            .or_else(|| PixelFormat::opt_max(dprobe.pixel_format(), rprobe.pixel_format())),
+    );
+    let stats = match &frame_report {
+        Some(_) => {
+            let stats = temporary::process_dir(None)?.join("xpsnr-frames.log");
+            temporary::add(&stats, TempKind::NotKeepable);
+            write!(lavfi, "=stats_file={}", frame_report::filter_path(&stats)).unwrap();
+            Some(stats)
+        }
+        None => None,
+    };
+
     let mut xpsnr_out = pin!(xpsnr::run(
         &reference,
+        None,
         &distorted,
-        &lavfi(
-            score.reference_vfilter.as_deref(),
-            xpsnr
-                .xpsnr_pix_format
-                .or_else(|| PixelFormat::opt_max(dprobe.pixel_format(), rprobe.pixel_format())),
-        ),
+        &lavfi,
         xpsnr.fps(),
+        score.grain_synthesis,
+        None,
     )?);
     let mut logger = ProgressLogger::new(module_path!(), Instant::now());
-    let mut score = None;
+    let mut xpsnr_score = None;
     while let Some(next) = xpsnr_out.next().await {
         match next {
             XpsnrOut::Done(s) => {
-                score = Some(s);
+                xpsnr_score = Some(s);
             }
             XpsnrOut::Progress(FfmpegOut::Progress {
                 frame, fps, time, ..
@@ -99,49 +130,39 @@ pub async fn xpsnr(
             XpsnrOut::Err(e) => return Err(e),
         }
     }
+    print_metrics(
+        &metric,
+        &reference,
+        &distorted,
+        &score,
+        PixelFormat::opt_max(dprobe.pixel_format(), rprobe.pixel_format()),
+        &bar,
+    )
+    .await?;
     bar.finish();
+    let xpsnr_score = xpsnr_score.context("no xpsnr score")?;
+
+    if let (Some(stats), Some(path)) = (stats, frame_report) {
+        let fps = dprobe.fps.or(rprobe.fps)?;
+        let report = FrameReport::from_xpsnr_stats(&tokio::fs::read_to_string(&stats).await?, fps);
+        report.write(&path).await?;
+        report.print_summary();
+    }
+    let planes: Vec<_> = xpsnr_score
+        .planes
+        .iter()
+        .map(|(plane, s)| format!("{plane} {s}"))
+        .collect();
+    if !planes.is_empty() {
+        eprintln!("{}", planes.join(", "));
+    }
 
-    println!("{}", score.context("no xpsnr score")?);
+    println!("{}", xpsnr_score.min);
     Ok(())
 }
 
 pub fn lavfi(ref_vfilter: Option<&str>, pix_fmt: Option<PixelFormat>) -> String {
-    /// Add filter to `lavfi`, if necessary. If no filter added return `old_name`.
-    /// Otherwise return `new_name`.
-    fn add_filter(
-        lavfi: &mut String,
-        old_name: &'static str,
-        new_name: &'static str,
-        vfilter: Option<&str>,
-        pix_fmt: Option<PixelFormat>,
-    ) -> &'static str {
-        if vfilter.is_none() && pix_fmt.is_none() {
-            return old_name;
-        }
-
-        lavfi.push_str(old_name);
-        if let Some(pix_fmt) = pix_fmt {
-            _ = write!(lavfi, "format={pix_fmt}");
-        }
-        if let Some(vf) = vfilter {
-            if pix_fmt.is_some() {
-                lavfi.push(',');
-            }
-            lavfi.push_str(vf);
-        }
-        lavfi.push_str(new_name);
-        lavfi.push(';');
-        new_name
-    }
-
-    let mut lavfi = String::new();
-
-    let ref_stream = add_filter(&mut lavfi, "[0:v]", "[ref]", ref_vfilter, pix_fmt);
-    let dis_stream = add_filter(&mut lavfi, "[1:v]", "[dis]", None, pix_fmt);
-    lavfi.push_str(ref_stream);
-    lavfi.push_str(dis_stream);
-    lavfi.push_str("xpsnr");
-    lavfi
+    metric::lavfi("xpsnr", ref_vfilter, pix_fmt)
 }
 
 #[test]
@@ -0,0 +1,309 @@
+//! Configuration file & named profiles.
+//!
+//! Profiles set command argument defaults, so any args passed on the command line override them.
+//!
+//! ```toml
+//! # ~/.config/ab-av1/config.toml
+//! default-profile = "anime"
+//!
+//! [profile.anime]
+//! preset = 6
+//! svt = ["tune=0", "enable-qm=1"]
+//! keyint = "10s"
+//! min-vmaf = 94
+//!
+//! [encoder.libvvenc]
+//! crf-arg = "-qp"
+//! max-crf = 50
+//! ```
+use crate::encoder;
+use anyhow::{Context, bail};
+use clap::{Arg, ArgAction, Parser};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ConfigFile {
+    /// Profile used when `--profile` is not set.
+    default_profile: Option<String>,
+    #[serde(default)]
+    profile: BTreeMap<String, BTreeMap<String, toml::Value>>,
+    /// User encoder profiles.
+    #[serde(default)]
+    encoder: BTreeMap<String, encoder::UserProfile>,
+}
+
+/// A named profile of argument defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// Config file the profile was read from.
+    pub path: PathBuf,
+    /// Argument values by long name.
+    pub args: BTreeMap<String, Vec<String>>,
+}
+
+/// Parse command line args applying any `--config` & `--profile` defaults,
+/// and registering config encoder profiles.
+///
+/// Exits on error, like [`Parser::parse`].
+pub fn parse<P: Parser>() -> (P, Option<Profile>) {
+    let args: Vec<_> = std::env::args_os().collect();
+    let profile = match load(&args) {
+        Ok(p) => p,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            std::process::exit(2);
+        }
+    };
+    let mut cmd = with_config_args(P::command());
+    if let Some(profile) = &profile {
+        cmd = match apply_profile(cmd, profile) {
+            Ok(cmd) => cmd,
+            Err(err) => {
+                eprintln!("Error: {err:#}");
+                std::process::exit(2);
+            }
+        };
+    }
+    let matches = cmd.clone().get_matches_from(args);
+    let parsed = P::from_arg_matches(&matches).unwrap_or_else(|e| e.format(&mut cmd).exit());
+    (parsed, profile)
+}
+
+/// Add global `--config` & `--profile` args.
+pub fn with_config_args(cmd: clap::Command) -> clap::Command {
+    cmd.arg(
+        Arg::new("config")
+            .long("config")
+            .global(true)
+            .value_name("PATH")
+            .value_parser(clap::value_parser!(PathBuf))
+            .value_hint(clap::ValueHint::FilePath)
+            .help("Config file, by default `~/.config/ab-av1/config.toml`"),
+    )
+    .arg(
+        Arg::new("profile")
+            .long("profile")
+            .global(true)
+            .value_name("NAME")
+            .help("Config profile to use for argument defaults, overridden by command line args"),
+    )
+}
+
+/// Load the config, registering encoder profiles, & return the profile selected by
+/// raw command line `args`, if any.
+fn load(args: &[OsString]) -> anyhow::Result<Option<Profile>> {
+    let config = arg_value(args, "--config").map(PathBuf::from);
+    let name = arg_value(args, "--profile");
+
+    let path = match config {
+        Some(path) => path,
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ if name.is_some() => bail!("profile set but no config file found"),
+            _ => return Ok(None),
+        },
+    };
+    let file = read_config(&path)?;
+    encoder::set_user_profiles(file.encoder)
+        .with_context(|| format!("invalid config {}", path.display()))?;
+    let Some(name) = name.or(file.default_profile) else {
+        return Ok(None);
+    };
+    let Some(values) = file.profile.get(&name) else {
+        bail!("profile `{name}` not found in {}", path.display());
+    };
+    let mut args = BTreeMap::new();
+    for (key, value) in values {
+        let values = toml_arg_values(value)
+            .with_context(|| format!("profile `{name}` invalid value for `{key}`"))?;
+        args.insert(key.clone(), values);
+    }
+    Ok(Some(Profile { name, path, args }))
+}
+
+fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("ab-av1").join("config.toml"))
+}
+
+fn read_config(path: &Path) -> anyhow::Result<ConfigFile> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read config {}", path.display()))?;
+    toml::from_str(&data).with_context(|| format!("invalid config {}", path.display()))
+}
+
+/// Returns the value of raw arg `name`, e.g. `--profile anime` or `--profile=anime`.
+fn arg_value(args: &[OsString], name: &str) -> Option<String> {
+    let mut args = args
+        .iter()
+        .map(|a| a.to_string_lossy())
+        .take_while(|a| a != "--");
+    let mut value = None;
+    while let Some(arg) = args.next() {
+        if arg == name {
+            value = args.next().map(|v| v.into_owned());
+        } else if let Some(v) = arg.strip_prefix(name).and_then(|a| a.strip_prefix('=')) {
+            value = Some(v.to_owned());
+        }
+    }
+    value
+}
+
+fn toml_arg_values(value: &toml::Value) -> anyhow::Result<Vec<String>> {
+    Ok(match value {
+        toml::Value::String(s) => vec![s.clone()],
+        toml::Value::Integer(v) => vec![v.to_string()],
+        toml::Value::Float(v) => vec![v.to_string()],
+        toml::Value::Boolean(v) => vec![v.to_string()],
+        toml::Value::Array(values) => {
+            let mut out = Vec::with_capacity(values.len());
+            for v in values {
+                match v {
+                    toml::Value::Array(_) => bail!("nested arrays are not supported"),
+                    v => out.extend(toml_arg_values(v)?),
+                }
+            }
+            out
+        }
+        _ => bail!("expected a string, number, bool or array"),
+    })
+}
+
+/// Set `profile` values as the default of matching args in all subcommands.
+pub fn apply_profile(cmd: clap::Command, profile: &Profile) -> anyhow::Result<clap::Command> {
+    let mut unknown: BTreeSet<_> = profile.args.keys().map(String::as_str).collect();
+    let mut invalid = None;
+    let cmd = cmd.mut_subcommands(|sub| {
+        sub.mut_args(|arg| {
+            let Some(values) = arg.get_long().and_then(|long| profile.args.get(long)) else {
+                return arg;
+            };
+            let long = arg.get_long().unwrap_or_default();
+            unknown.remove(long);
+            match arg.get_action() {
+                ArgAction::Append => arg.default_values(values.clone()),
+                ArgAction::Set | ArgAction::SetTrue | ArgAction::SetFalse if values.len() == 1 => {
+                    arg.default_value(values[0].clone())
+                }
+                _ => {
+                    invalid = Some(long.to_owned());
+                    arg
+                }
+            }
+        })
+    });
+    if let Some(long) = invalid {
+        bail!("profile `{}` invalid value for `{long}`", profile.name);
+    }
+    if let Some(key) = unknown.first() {
+        bail!("profile `{}` unknown arg `{key}`", profile.name);
+    }
+    Ok(cmd)
+}
+
+#[cfg(test)]
+#[derive(Debug, Parser)]
+enum TestCommand {
+    Encode {
+        #[arg(long)]
+        preset: Option<String>,
+        #[arg(long)]
+        svt: Vec<String>,
+        #[arg(long)]
+        keep: bool,
+        #[arg(long, default_value_t = 95.0)]
+        min_vmaf: f32,
+    },
+}
+
+#[cfg(test)]
+fn test_profile(toml: &str) -> Profile {
+    let file: ConfigFile = toml::from_str(toml).unwrap();
+    let (name, values) = file.profile.into_iter().next().unwrap();
+    Profile {
+        name,
+        path: "config.toml".into(),
+        args: values
+            .iter()
+            .map(|(k, v)| (k.clone(), toml_arg_values(v).unwrap()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+fn test_parse(profile: &Profile, args: &[&str]) -> anyhow::Result<TestCommand> {
+    use clap::{CommandFactory, FromArgMatches};
+    let cmd = apply_profile(with_config_args(TestCommand::command()), profile)?;
+    let matches = cmd.try_get_matches_from(args)?;
+    Ok(TestCommand::from_arg_matches(&matches)?)
+}
+
+#[test]
+fn profile_defaults() {
+    let anime = test_profile(
+        r#"
+        [profile.anime]
+        preset = 6
+        svt = ["tune=0", "enable-qm=1"]
+        keep = true
+        min-vmaf = 94.5
+        "#,
+    );
+    let TestCommand::Encode {
+        preset,
+        svt,
+        keep,
+        min_vmaf,
+    } = test_parse(&anime, &["test", "encode", "--profile", "anime"]).unwrap();
+    assert_eq!(preset.as_deref(), Some("6"));
+    assert_eq!(svt, ["tune=0", "enable-qm=1"]);
+    assert!(keep);
+    assert_eq!(min_vmaf, 94.5);
+
+    // command line args override
+    let TestCommand::Encode {
+        preset,
+        svt,
+        min_vmaf,
+        ..
+    } = test_parse(
+        &anime,
+        &[
+            "test",
+            "encode",
+            "--preset",
+            "8",
+            "--svt",
+            "tune=2",
+            "--min-vmaf",
+            "96",
+        ],
+    )
+    .unwrap();
+    assert_eq!(preset.as_deref(), Some("8"));
+    assert_eq!(svt, ["tune=2"]);
+    assert_eq!(min_vmaf, 96.0);
+}
+
+#[test]
+fn profile_unknown_arg() {
+    let bad = test_profile("[profile.bad]\nprest = 6");
+    assert!(test_parse(&bad, &["test", "encode"]).is_err());
+}
+
+#[test]
+fn raw_arg_value() {
+    let args: Vec<OsString> = ["ab-av1", "encode", "--profile", "anime", "--config=a.toml"]
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    assert_eq!(arg_value(&args, "--profile").as_deref(), Some("anime"));
+    assert_eq!(arg_value(&args, "--config").as_deref(), Some("a.toml"));
+    assert_eq!(arg_value(&args[..2], "--profile"), None);
+}
@@ -0,0 +1,203 @@
+//! cropdetect logic
+use crate::{
+    ffprobe::Ffprobe,
+    process::{CommandExt, ensure_success},
+    sample,
+};
+use anyhow::Context;
+use log::{debug, info};
+use std::{fmt, path::Path, process::Stdio, time::Duration};
+use tokio::process::Command;
+
+/// Number of points across the input to run cropdetect at.
+const POSITIONS: u64 = 8;
+/// Frames analysed at each point.
+const POSITION_FRAMES: u32 = 48;
+/// Duration used to spread the positions, roughly covers `POSITION_FRAMES`.
+const POSITION_DURATION: Duration = Duration::from_secs(2);
+
+/// A ffmpeg `crop` filter value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Crop {
+    /// Smallest crop containing both `self` & `other`.
+    fn union(self, other: Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Self {
+            width: right - x,
+            height: bottom - y,
+            x,
+            y,
+        }
+    }
+
+    /// ffmpeg vfilter, e.g. "crop=1920:800:0:140".
+    pub fn vfilter(&self) -> String {
+        format!("crop={self}")
+    }
+}
+
+impl fmt::Display for Crop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            width,
+            height,
+            x,
+            y,
+        } = self;
+        write!(f, "{width}:{height}:{x}:{y}")
+    }
+}
+
+/// Detect black bars by running ffmpeg cropdetect at several points across the input.
+///
+/// The consensus is the smallest crop containing every point's detected area, so a
+/// dark scene cannot cause picture to be cropped away.
+/// Image sequences are read at `fps`. Returns `None` if nothing should be cropped.
+pub async fn detect(
+    input: &Path,
+    fps: Option<f64>,
+    probe: &Ffprobe,
+) -> anyhow::Result<Option<Crop>> {
+    let (width, height) = probe
+        .resolution
+        .context("cropdetect: no input resolution")?;
+    let duration = probe.duration.clone().unwrap_or_default();
+    let positions = match probe.is_image || duration <= POSITION_DURATION {
+        true => 1,
+        false => POSITIONS,
+    };
+    info!(
+        "cropdetect {} at {positions} positions",
+        input.file_name().and_then(|n| n.to_str()).unwrap_or(""),
+    );
+
+    let mut consensus: Option<Crop> = None;
+    for idx in 0..positions {
+        let start = match positions {
+            1 => Duration::ZERO,
+            _ => sample::start(idx, positions, POSITION_DURATION, duration),
+        };
+        let Some(crop) = detect_at(input, fps, probe.video_stream, start).await? else {
+            continue;
+        };
+        // all black frames produce nonsense, ignore areas under half the frame width/height
+        if crop.width < width / 2 || crop.height < height / 2 {
+            debug!("cropdetect ignoring {crop} at {start:?}");
+            continue;
+        }
+        consensus = Some(match consensus {
+            Some(c) => c.union(crop),
+            None => crop,
+        });
+    }
+
+    Ok(consensus.filter(|c| c.width < width || c.height < height))
+}
+
+async fn detect_at(
+    input: &Path,
+    fps: Option<f64>,
+    video_stream: usize,
+    start: Duration,
+) -> anyhow::Result<Option<Crop>> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.kill_on_drop(true)
+        .stdin(Stdio::null())
+        .arg_input(input, fps, Some(start))?
+        .arg2("-map", format!("0:v:{video_stream}"))
+        .arg2("-frames:v", POSITION_FRAMES)
+        // reset=0 accumulates the area over all analysed frames
+        .arg2("-vf", "cropdetect=round=2:reset=0")
+        .arg("-an")
+        .arg("-sn")
+        .arg("-dn")
+        .arg2("-f", "null")
+        .arg("-");
+    debug!("cmd `{}`", cmd.to_cmd_str());
+
+    let out = cmd.output().await.context("ffmpeg cropdetect")?;
+    ensure_success("ffmpeg cropdetect", &out)?;
+
+    Ok(String::from_utf8_lossy(&out.stderr)
+        .lines()
+        .rev()
+        .find_map(crop_from_line))
+}
+
+// E.g. "[Parsed_cropdetect_0 @ 0x5f1a] x1:0 x2:1919 y1:140 y2:939 w:1920 h:800 x:0 y:140 pts:1001 t:0.041708 limit:0.094118 crop=1920:800:0:140"
+fn crop_from_line(line: &str) -> Option<Crop> {
+    if !line.contains("cropdetect") {
+        return None;
+    }
+    let value = line.split_once("crop=")?.1.split_whitespace().next()?;
+    let mut parts = value.split(':').map(|v| v.parse::<i64>().ok());
+    let mut next = || u32::try_from(parts.next()??).ok();
+    let crop = Crop {
+        width: next()?,
+        height: next()?,
+        x: next()?,
+        y: next()?,
+    };
+    (crop.width > 0 && crop.height > 0).then_some(crop)
+}
+
+#[test]
+fn parse_crop_line() {
+    let crop = crop_from_line(
+        "[Parsed_cropdetect_0 @ 0x5f1a2b3c4d00] x1:0 x2:1919 y1:140 y2:939 w:1920 h:800 x:0 y:140 pts:1001 t:0.041708 limit:0.094118 crop=1920:800:0:140",
+    );
+    assert_eq!(
+        crop,
+        Some(Crop {
+            width: 1920,
+            height: 800,
+            x: 0,
+            y: 140
+        })
+    );
+}
+
+/// All black frames produce negative crop sizes.
+#[test]
+fn parse_crop_line_black_frame() {
+    let crop = crop_from_line(
+        "[Parsed_cropdetect_0 @ 0x5f1a2b3c4d00] x1:1919 x2:0 y1:1079 y2:0 w:-1918 h:-1078 x:1918 y:1078 pts:1001 t:0.041708 limit:0.094118 crop=-1918:-1078:1918:1078",
+    );
+    assert_eq!(crop, None);
+}
+
+#[test]
+fn crop_union() {
+    let a = Crop {
+        width: 1920,
+        height: 800,
+        x: 0,
+        y: 140,
+    };
+    let b = Crop {
+        width: 1800,
+        height: 816,
+        x: 60,
+        y: 132,
+    };
+    assert_eq!(
+        a.union(b),
+        Crop {
+            width: 1920,
+            height: 816,
+            x: 0,
+            y: 132
+        }
+    );
+    assert_eq!(a.union(b).vfilter(), "crop=1920:816:0:132");
+}
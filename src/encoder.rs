@@ -0,0 +1,681 @@
+//! Encoder profile registry & ffmpeg encoder capability discovery.
+use crate::{command::args::PixelFormat, native::NativeEncoder};
+use anyhow::Context;
+use serde::Deserialize;
+use std::{collections::BTreeMap, process::Stdio, sync::OnceLock};
+use tokio::process::Command;
+
+/// Encoder specific behaviour & defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncoderProfile {
+    /// ffmpeg encoder name, or a family suffix pattern e.g. `*_vaapi`.
+    pub name: &'static str,
+    /// Arg to use crf values with, normally `-crf`.
+    pub crf_arg: &'static str,
+    /// Arg to use preset values with, normally `-preset`.
+    pub preset_arg: &'static str,
+    /// Default crf-search `--crf-increment`.
+    pub crf_increment: f32,
+    /// Default crf-search `--min-crf`.
+    pub min_crf: f32,
+    /// Default crf-search `--max-crf`.
+    pub max_crf: f32,
+    /// Max crf value to pass to ffmpeg `crf_arg`, if lower than `max_crf`.
+    pub ffmpeg_max_crf: Option<f32>,
+    /// Higher crf values mean higher quality.
+    pub high_crf_means_hq: bool,
+    /// Default `--preset`.
+    pub preset: Option<&'static str>,
+    /// Default `--pix-format`.
+    pub pix_fmt: Option<PixelFormat>,
+    /// Output extension for image crf-searches.
+    pub image_ext: &'static str,
+    /// Default output name pre-extension, e.g. "av1" for `vid.av1.mkv`.
+    /// By default the encoder name without any "lib" prefix.
+    pub pre_extension: Option<&'static str>,
+    /// Additional ffmpeg output arg defaults.
+    pub output_args: &'static [(&'static str, &'static str)],
+    /// Additional ffmpeg output arg defaults for mp4 & mov outputs.
+    pub mp4_output_args: &'static [(&'static str, &'static str)],
+    /// Additional ffmpeg input arg defaults.
+    pub input_args: &'static [(&'static str, &'static str)],
+    /// Standalone encoder app usable with `--backend native`.
+    pub native: Option<NativeEncoder>,
+    /// Native encoder binary, by default the app's usual binary name.
+    pub native_bin: Option<&'static str>,
+    /// Default `--backend`.
+    pub backend: Backend,
+}
+
+/// Encode backend.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[clap(rename_all = "lower")]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Encode with ffmpeg's encoder wrapper.
+    #[default]
+    Ffmpeg,
+    /// Pipe ffmpeg decoded y4m into the standalone encoder binary.
+    Native,
+}
+
+/// Profile for encoders not otherwise in [`PROFILES`].
+const DEFAULT: EncoderProfile = EncoderProfile {
+    name: "*",
+    crf_arg: "-crf",
+    preset_arg: "-preset",
+    crf_increment: 1.0,
+    min_crf: 10.0,
+    max_crf: 55.0,
+    ffmpeg_max_crf: None,
+    high_crf_means_hq: false,
+    preset: None,
+    pix_fmt: None,
+    image_ext: "avif",
+    pre_extension: None,
+    output_args: &[],
+    mp4_output_args: &[],
+    input_args: &[],
+    native: None,
+    native_bin: None,
+    backend: Backend::Ffmpeg,
+};
+
+/// Encoder profiles, exact names first.
+const PROFILES: &[EncoderProfile] = &[
+    EncoderProfile {
+        name: "libsvtav1",
+        crf_increment: 0.25,
+        min_crf: 5.0,
+        max_crf: 70.0,
+        // ffmpeg svt-av1 crf above 63 don't work, but up to 70 does work in -svtav1-params
+        ffmpeg_max_crf: Some(63.0),
+        preset: Some("8"),
+        pix_fmt: Some(PixelFormat::Yuv420p10le),
+        pre_extension: Some("av1"),
+        native: Some(NativeEncoder::SvtAv1),
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "libaom-av1",
+        preset_arg: "-cpu-used",
+        pix_fmt: Some(PixelFormat::Yuv420p10le),
+        // "constant quality" mode
+        output_args: &[("-b:v", "0")],
+        native: Some(NativeEncoder::Aom),
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "librav1e",
+        // https://ffmpeg.org//ffmpeg-codecs.html#librav1e
+        crf_arg: "-qp",
+        preset_arg: "-speed",
+        max_crf: 255.0,
+        pix_fmt: Some(PixelFormat::Yuv420p10le),
+        native: Some(NativeEncoder::Rav1e),
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "libvpx-vp9",
+        preset_arg: "-cpu-used",
+        pre_extension: Some("vp9"),
+        // "constant quality" mode
+        output_args: &[("-b:v", "0")],
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "libx264",
+        crf_increment: 0.1,
+        max_crf: 46.0,
+        // ffmpeg doesn't currently have good heif support,
+        // these raw formats allow crf-search to work
+        image_ext: "264",
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "libx265",
+        crf_increment: 0.1,
+        max_crf: 46.0,
+        image_ext: "265",
+        // apple compat for hevc
+        mp4_output_args: &[("-tag:v", "hvc1")],
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "libjxl",
+        // butteraugli distance, 1.0 being visually lossless
+        crf_arg: "-distance",
+        preset_arg: "-effort",
+        crf_increment: 0.1,
+        min_crf: 0.5,
+        max_crf: 10.0,
+        preset: Some("7"),
+        image_ext: "jxl",
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "libvvenc",
+        // https://github.com/fraunhoferhhi/vvenc/wiki/FFmpeg-Integration#fix-qp-mode-constant-quality-mode
+        crf_arg: "-qp",
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "mpeg2video",
+        crf_arg: "-q",
+        min_crf: 2.0,
+        max_crf: 30.0,
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "hevc_videotoolbox",
+        crf_arg: "-q:v",
+        max_crf: 100.0,
+        high_crf_means_hq: true,
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "av1_vaapi",
+        crf_arg: "-q",
+        max_crf: 255.0,
+        input_args: VAAPI_INPUT_ARGS,
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "*_vaapi",
+        // https://ffmpeg.org//ffmpeg-codecs.html#VAAPI-encoders
+        crf_arg: "-q",
+        input_args: VAAPI_INPUT_ARGS,
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "*_vulkan",
+        crf_arg: "-qp",
+        input_args: &[("-hwaccel", "vulkan"), ("-hwaccel_output_format", "vulkan")],
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "*_nvenc",
+        crf_arg: "-cq",
+        // nvenc -cq range is 0 to 51
+        max_crf: 51.0,
+        ..DEFAULT
+    },
+    EncoderProfile {
+        name: "av1_qsv",
+        output_args: QSV_LOOKAHEAD_ARGS,
+        ..QSV
+    },
+    EncoderProfile {
+        name: "hevc_qsv",
+        output_args: QSV_LOOKAHEAD_ARGS,
+        ..QSV
+    },
+    EncoderProfile {
+        name: "h264_qsv",
+        output_args: QSV_LOOKAHEAD_ARGS,
+        ..QSV
+    },
+    QSV,
+];
+
+const QSV: EncoderProfile = EncoderProfile {
+    name: "*_qsv",
+    // https://ffmpeg.org//ffmpeg-codecs.html#QSV-Encoders
+    crf_arg: "-global_quality",
+    ..DEFAULT
+};
+
+/// Enable lookahead mode for qsv encoders supporting it.
+const QSV_LOOKAHEAD_ARGS: &[(&str, &str)] = &[
+    ("-look_ahead", "1"),
+    ("-extbrc", "1"),
+    ("-look_ahead_depth", "40"),
+];
+
+const VAAPI_INPUT_ARGS: &[(&str, &str)] =
+    &[("-hwaccel", "vaapi"), ("-hwaccel_output_format", "vaapi")];
+
+impl EncoderProfile {
+    fn matches(&self, encoder: &str) -> bool {
+        match self.name.strip_prefix('*') {
+            Some(suffix) => encoder.ends_with(suffix),
+            None => self.name == encoder,
+        }
+    }
+
+    /// Returns the profile for `encoder`, user profiles taking precedence.
+    pub fn get(encoder: &str) -> &'static Self {
+        Self::find(encoder).unwrap_or(&DEFAULT)
+    }
+
+    /// Whether `encoder` has a specific profile.
+    pub fn is_known(encoder: &str) -> bool {
+        Self::find(encoder).is_some()
+    }
+
+    fn find(encoder: &str) -> Option<&'static Self> {
+        let user = USER_PROFILES.get().map(Vec::as_slice).unwrap_or_default();
+        user.iter().chain(PROFILES).find(|p| p.matches(encoder))
+    }
+
+    /// crf value to pass to ffmpeg.
+    pub fn ffmpeg_crf(&self, crf: f32) -> f32 {
+        match self.ffmpeg_max_crf {
+            Some(max) => crf.min(max),
+            None => crf,
+        }
+    }
+}
+
+/// User encoder profiles, set once at startup from the config file.
+static USER_PROFILES: OnceLock<Vec<EncoderProfile>> = OnceLock::new();
+
+/// User config `[encoder.NAME]` profile. Unset fields use the built-in profile for `NAME`, if any,
+/// otherwise generic defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UserProfile {
+    crf_arg: Option<String>,
+    preset_arg: Option<String>,
+    crf_increment: Option<f32>,
+    min_crf: Option<f32>,
+    max_crf: Option<f32>,
+    ffmpeg_max_crf: Option<f32>,
+    high_crf_means_hq: Option<bool>,
+    preset: Option<String>,
+    pix_format: Option<String>,
+    image_ext: Option<String>,
+    pre_extension: Option<String>,
+    output_args: Option<BTreeMap<String, String>>,
+    mp4_output_args: Option<BTreeMap<String, String>>,
+    input_args: Option<BTreeMap<String, String>>,
+    native_bin: Option<String>,
+    backend: Option<Backend>,
+}
+
+/// Register user encoder profiles by encoder name or `*_suffix` pattern.
+///
+/// Profile strings are leaked as they live for the rest of the process.
+pub fn set_user_profiles(profiles: BTreeMap<String, UserProfile>) -> anyhow::Result<()> {
+    let mut out = Vec::with_capacity(profiles.len());
+    for (name, user) in profiles {
+        let profile = user
+            .into_profile(&name)
+            .with_context(|| format!("invalid encoder profile `{name}`"))?;
+        out.push(profile);
+    }
+    // exact names first
+    out.sort_by_key(|p| p.name.starts_with('*'));
+    _ = USER_PROFILES.set(out);
+    Ok(())
+}
+
+impl UserProfile {
+    fn into_profile(self, name: &str) -> anyhow::Result<EncoderProfile> {
+        fn leak(s: String) -> &'static str {
+            Box::leak(s.into_boxed_str())
+        }
+        fn leak_args(args: BTreeMap<String, String>) -> &'static [(&'static str, &'static str)] {
+            let args: Vec<_> = args.into_iter().map(|(k, v)| (leak(k), leak(v))).collect();
+            Box::leak(args.into_boxed_slice())
+        }
+
+        let base = PROFILES
+            .iter()
+            .find(|p| p.matches(name))
+            .unwrap_or(&DEFAULT);
+        let pix_fmt = match self.pix_format {
+            Some(pf) => Some(
+                PixelFormat::try_from(pf.as_str())
+                    .map_err(|_| anyhow::anyhow!("unsupported pix-format `{pf}`"))?,
+            ),
+            None => base.pix_fmt,
+        };
+        let min_crf = self.min_crf.unwrap_or(base.min_crf);
+        let max_crf = self.max_crf.unwrap_or(base.max_crf);
+        anyhow::ensure!(min_crf < max_crf, "min-crf must be less than max-crf");
+        let backend = self.backend.unwrap_or(base.backend);
+        anyhow::ensure!(
+            backend == Backend::Ffmpeg || base.native.is_some(),
+            "backend native is only supported by libsvtav1, libaom-av1 & librav1e"
+        );
+        Ok(EncoderProfile {
+            name: leak(name.to_owned()),
+            crf_arg: self.crf_arg.map_or(base.crf_arg, leak),
+            preset_arg: self.preset_arg.map_or(base.preset_arg, leak),
+            crf_increment: self.crf_increment.unwrap_or(base.crf_increment),
+            min_crf,
+            max_crf,
+            ffmpeg_max_crf: self.ffmpeg_max_crf.or(base.ffmpeg_max_crf),
+            high_crf_means_hq: self.high_crf_means_hq.unwrap_or(base.high_crf_means_hq),
+            preset: self.preset.map(leak).or(base.preset),
+            pix_fmt,
+            image_ext: self.image_ext.map_or(base.image_ext, leak),
+            pre_extension: self.pre_extension.map(leak).or(base.pre_extension),
+            output_args: self.output_args.map_or(base.output_args, leak_args),
+            mp4_output_args: self.mp4_output_args.map_or(base.mp4_output_args, leak_args),
+            input_args: self.input_args.map_or(base.input_args, leak_args),
+            native: base.native,
+            native_bin: self.native_bin.map(leak).or(base.native_bin),
+            backend,
+        })
+    }
+}
+
+/// A video encoder listed by `ffmpeg -encoders`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedEncoder {
+    pub name: String,
+    pub description: String,
+}
+
+/// Returns all video encoders available in ffmpeg.
+pub async fn list() -> anyhow::Result<Vec<ListedEncoder>> {
+    let out = ffmpeg_stdout(&["-encoders"]).await?;
+    Ok(parse_encoders(&out))
+}
+
+fn parse_encoders(out: &str) -> Vec<ListedEncoder> {
+    out.lines()
+        .skip_while(|l| !l.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut parts = line.trim_start().splitn(3, char::is_whitespace);
+            let flags = parts.next()?;
+            if !flags.starts_with('V') {
+                return None;
+            }
+            let name = parts.next()?.to_owned();
+            let description = parts.next().unwrap_or_default().trim().to_owned();
+            Some(ListedEncoder { name, description })
+        })
+        .collect()
+}
+
+/// ffmpeg encoder capabilities, from `ffmpeg -h encoder=NAME`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Capabilities {
+    pub description: String,
+    /// Supported pixel formats, empty if unknown.
+    pub pix_fmts: Vec<String>,
+    pub options: Vec<EncoderOption>,
+}
+
+/// An encoder AVOption.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncoderOption {
+    /// Name including the `-` prefix.
+    pub name: String,
+    /// Value type, e.g. `int`.
+    pub kind: String,
+    pub help: String,
+    /// Value range `(from, to)`, if any.
+    pub range: Option<(f64, f64)>,
+}
+
+impl Capabilities {
+    /// Query ffmpeg for `encoder` capabilities, `None` if ffmpeg doesn't have the encoder.
+    pub async fn query(encoder: &str) -> anyhow::Result<Option<Self>> {
+        let out = ffmpeg_stdout(&["-h", &format!("encoder={encoder}")]).await?;
+        Ok(Self::parse(&out))
+    }
+
+    fn parse(help: &str) -> Option<Self> {
+        let mut lines = help.lines();
+        let description = lines
+            .next()?
+            .strip_prefix("Encoder ")?
+            .split_once(' ')
+            .map(|(_, d)| d.trim_end_matches(':').trim_matches(['[', ']']).to_owned())
+            .unwrap_or_default();
+        let mut caps = Self {
+            description,
+            ..<_>::default()
+        };
+        for line in lines {
+            let line = line.trim();
+            if let Some(fmts) = line.strip_prefix("Supported pixel formats:") {
+                caps.pix_fmts = fmts.split_whitespace().map(str::to_owned).collect();
+            } else if line.starts_with('-')
+                && let Some(opt) = EncoderOption::parse(line)
+            {
+                caps.options.push(opt);
+            }
+        }
+        Some(caps)
+    }
+
+    pub fn option(&self, name: &str) -> Option<&EncoderOption> {
+        self.options.iter().find(|o| o.name == name)
+    }
+}
+
+impl EncoderOption {
+    /// Parse option lines, e.g.
+    /// "-crf <int> E..V....... Constant Rate Factor value (from 0 to 63) (default 0)"
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let name = parts.next()?.to_owned();
+        let kind = parts.next()?.trim_matches(['<', '>']).to_owned();
+        let _flags = parts.next()?;
+        let help = parts.collect::<Vec<_>>().join(" ");
+        let range = help.split_once("(from ").and_then(|(_, r)| {
+            let (from, to) = r.split_once(" to ")?;
+            let to = to.split_once(')')?.0;
+            Some((from.parse().ok()?, to.parse().ok()?))
+        });
+        Some(Self {
+            name,
+            kind,
+            help,
+            range,
+        })
+    }
+}
+
+/// Check ffmpeg has `encoder` & supports the `pix_fmt`, `preset` & `crfs` that will be used.
+pub async fn validate(
+    encoder: &str,
+    pix_fmt: Option<PixelFormat>,
+    preset: Option<&str>,
+    crfs: &[f32],
+) -> anyhow::Result<()> {
+    let caps = Capabilities::query(encoder).await?.with_context(|| {
+        format!("encoder `{encoder}` is not available in ffmpeg, see `ab-av1 encoders`")
+    })?;
+    validate_caps(encoder, &caps, pix_fmt, preset, crfs)
+}
+
+fn validate_caps(
+    encoder: &str,
+    caps: &Capabilities,
+    pix_fmt: Option<PixelFormat>,
+    preset: Option<&str>,
+    crfs: &[f32],
+) -> anyhow::Result<()> {
+    if let Some(pix_fmt) = pix_fmt {
+        anyhow::ensure!(
+            caps.pix_fmts.is_empty() || caps.pix_fmts.iter().any(|f| f == pix_fmt.as_str()),
+            "encoder `{encoder}` does not support pixel format {pix_fmt}, supported: {}",
+            caps.pix_fmts.join(" "),
+        );
+    }
+    let profile = EncoderProfile::get(encoder);
+    if let (Some(preset), Some(opt)) = (preset, caps.option(profile.preset_arg))
+        && let (Ok(preset), Some((from, to))) = (preset.parse::<f64>(), opt.range)
+    {
+        anyhow::ensure!(
+            (from..=to).contains(&preset),
+            "encoder `{encoder}` {} {preset} out of range {from} to {to}",
+            profile.preset_arg,
+        );
+    }
+    if let Some((from, to)) = caps.option(profile.crf_arg).and_then(|o| o.range) {
+        for crf in crfs.iter().map(|crf| profile.ffmpeg_crf(*crf)) {
+            anyhow::ensure!(
+                (from..=to).contains(&f64::from(crf)),
+                "encoder `{encoder}` {} {crf} out of range {from} to {to}",
+                profile.crf_arg,
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn ffmpeg_stdout(args: &[&str]) -> anyhow::Result<String> {
+    let out = Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .context("ffmpeg")?;
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+#[test]
+fn profile_lookup() {
+    assert_eq!(EncoderProfile::get("libsvtav1").crf_increment, 0.25);
+    assert_eq!(EncoderProfile::get("av1_vaapi").max_crf, 255.0);
+    assert_eq!(EncoderProfile::get("hevc_vaapi").crf_arg, "-q");
+    assert_eq!(EncoderProfile::get("hevc_vaapi").max_crf, 55.0);
+    assert_eq!(EncoderProfile::get("h264_nvenc").crf_arg, "-cq");
+    assert_eq!(EncoderProfile::get("av1_nvenc").max_crf, 51.0);
+    assert_eq!(
+        EncoderProfile::get("hevc_qsv").output_args,
+        QSV_LOOKAHEAD_ARGS
+    );
+    assert_eq!(EncoderProfile::get("vp9_qsv").crf_arg, "-global_quality");
+    assert!(EncoderProfile::get("vp9_qsv").output_args.is_empty());
+    assert_eq!(EncoderProfile::get("libjxl").crf_arg, "-distance");
+    assert_eq!(EncoderProfile::get("libjxl").image_ext, "jxl");
+    assert_eq!(EncoderProfile::get("libfoo").name, "*");
+    assert!(!EncoderProfile::is_known("libfoo"));
+}
+
+#[test]
+fn user_profile() {
+    let user: UserProfile = toml::from_str(
+        r#"
+        crf-arg = "-qp"
+        max-crf = 50
+        preset = "medium"
+        pix-format = "yuv420p10le"
+        pre-extension = "vvc"
+        output-args = { "-tier" = "high" }
+        "#,
+    )
+    .unwrap();
+    let profile = user.into_profile("libvvenc").unwrap();
+    assert_eq!(
+        profile,
+        EncoderProfile {
+            name: "libvvenc",
+            crf_arg: "-qp",
+            max_crf: 50.0,
+            preset: Some("medium"),
+            pix_fmt: Some(PixelFormat::Yuv420p10le),
+            pre_extension: Some("vvc"),
+            output_args: &[("-tier", "high")],
+            ..DEFAULT
+        }
+    );
+
+    // based on the built-in profile
+    let user: UserProfile = toml::from_str("max-crf = 60").unwrap();
+    let profile = user.into_profile("libsvtav1").unwrap();
+    assert_eq!(profile.max_crf, 60.0);
+    assert_eq!(profile.crf_increment, 0.25);
+
+    let user: UserProfile = toml::from_str("pix-format = \"rgb\"").unwrap();
+    assert!(user.into_profile("libfoo").is_err());
+
+    let user: UserProfile =
+        toml::from_str("backend = \"native\"\nnative-bin = \"/opt/SvtAv1EncApp\"").unwrap();
+    let profile = user.into_profile("libsvtav1").unwrap();
+    assert_eq!(profile.backend, Backend::Native);
+    assert_eq!(profile.native_bin, Some("/opt/SvtAv1EncApp"));
+
+    let user: UserProfile = toml::from_str("backend = \"native\"").unwrap();
+    assert!(user.into_profile("libx264").is_err());
+}
+
+#[test]
+fn parse_encoders_list() {
+    let out = "Encoders:\n \
+        V..... = Video\n \
+        A..... = Audio\n \
+        ------\n \
+        V....D libsvtav1            SVT-AV1(Scalable Video Technology for AV1) encoder (codec av1)\n \
+        A....D libopus              libopus Opus (codec opus)\n \
+        V....D libx264              libx264 H.264 / AVC / MPEG-4 AVC (codec h264)\n";
+    let names: Vec<_> = parse_encoders(out).into_iter().map(|e| e.name).collect();
+    assert_eq!(names, ["libsvtav1", "libx264"]);
+}
+
+#[cfg(test)]
+const SVT_HELP: &str = "\
+Encoder libsvtav1 [SVT-AV1(Scalable Video Technology for AV1) encoder]:
+    General capabilities: dr1 delay threads
+    Threading capabilities: other
+    Supported pixel formats: yuv420p yuv420p10le
+libsvtav1 AVOptions:
+  -hielevel          <int>        E..V....... Hierarchical prediction levels setting (from 3 to 4) (default 4level)
+  -preset            <int>        E..V....... Encoding preset (from -2 to 13) (default -2)
+  -crf               <int>        E..V....... Constant Rate Factor value (from 0 to 63) (default 0)
+  -svtav1-params     <dictionary> E..V....... Set the SVT-AV1 configuration using a :-separated list of key=value parameters
+";
+
+#[test]
+fn parse_capabilities() {
+    let caps = Capabilities::parse(SVT_HELP).unwrap();
+    assert_eq!(
+        caps.description,
+        "SVT-AV1(Scalable Video Technology for AV1) encoder"
+    );
+    assert_eq!(caps.pix_fmts, ["yuv420p", "yuv420p10le"]);
+    assert_eq!(caps.option("-preset").unwrap().range, Some((-2.0, 13.0)));
+    assert_eq!(caps.option("-svtav1-params").unwrap().range, None);
+    assert_eq!(
+        Capabilities::parse("Codec 'foo' is not recognized by FFmpeg."),
+        None
+    );
+}
+
+#[test]
+fn validate_capabilities() {
+    let caps = Capabilities::parse(SVT_HELP).unwrap();
+    let yuv10 = Some(PixelFormat::Yuv420p10le);
+    validate_caps("libsvtav1", &caps, yuv10, Some("8"), &[5.0, 70.0]).unwrap();
+    assert!(validate_caps("libsvtav1", &caps, yuv10, Some("14"), &[]).is_err());
+    assert!(
+        validate_caps(
+            "libsvtav1",
+            &caps,
+            Some(PixelFormat::Yuv444p10le),
+            None,
+            &[]
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn validate_nvenc_default_crf_range() {
+    let caps = Capabilities::parse(
+        "\
+Encoder h264_nvenc [NVIDIA NVENC H.264 encoder (codec h264)]:
+    General capabilities: dr1 delay hardware
+    Supported pixel formats: yuv420p nv12 p010le yuv444p
+h264_nvenc AVOptions:
+  -preset            <int>        E..V....... Set the encoding preset (from 0 to 18) (default p4)
+  -cq                <float>      E..V....... Set target quality level (0 to 51, 0 means automatic) for constant quality mode in VBR rate control (from 0 to 51) (default 0)
+",
+    )
+    .unwrap();
+    let profile = EncoderProfile::get("h264_nvenc");
+    let crfs = [profile.min_crf, profile.max_crf];
+    validate_caps("h264_nvenc", &caps, None, None, &crfs).unwrap();
+    assert!(validate_caps("h264_nvenc", &caps, None, None, &[55.0]).is_err());
+}
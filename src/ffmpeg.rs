@@ -1,12 +1,16 @@
 //! ffmpeg encoding logic
 use crate::{
     command::args::PixelFormat,
+    encoder::EncoderProfile,
     float::TerseF32,
-    process::{Chunks, CommandExt, FfmpegOut, FfmpegOutStream, exit_ok_stderr},
+    native::{self, Native},
+    process::{Chunks, CommandExt, FfmpegOut, exit_ok_stderr},
+    provenance::{self, Provenance},
     temporary::{self, TempKind},
 };
 use anyhow::Context;
 use bstr::ByteSlice;
+use futures_util::stream::LocalBoxStream;
 use log::{debug, info};
 use std::{
     collections::HashSet,
@@ -21,10 +25,21 @@ use tokio::process::Command;
 use tokio_process_stream::{Item, ProcessChunkStream};
 use tokio_stream::StreamExt;
 
+static SVT_AV1_V: LazyLock<String> = LazyLock::new(|| {
+    ffmpeg_svtav1_version()
+        .inspect_err(|e| debug!("read_ffmpeg_svtav1_version: {e}"))
+        .unwrap_or_default()
+});
+
+/// Encode progress & output stream sizes.
+pub type EncodeStream = LocalBoxStream<'static, anyhow::Result<FfmpegOut>>;
+
 /// Exposed ffmpeg encoding args.
 #[derive(Debug, Clone)]
 pub struct FfmpegEncodeArgs<'a> {
     pub input: &'a Path,
+    /// Image sequence input frame rate, i.e. `--fps`.
+    pub input_fps: Option<f64>,
     pub vcodec: Arc<str>,
     pub vfilter: Option<&'a str>,
     pub pix_fmt: Option<PixelFormat>,
@@ -33,16 +48,14 @@ pub struct FfmpegEncodeArgs<'a> {
     pub output_args: Vec<Arc<String>>,
     pub input_args: Vec<Arc<String>>,
     pub video_only: bool,
+    /// Input video stream number of the primary video, i.e. `0:v:N`.
+    pub video_stream: usize,
+    /// Encode the primary video with a native encoder binary, rather than ffmpeg.
+    pub native: Option<Native>,
 }
 
 impl FfmpegEncodeArgs<'_> {
     pub fn sample_encode_hash(&self, state: &mut impl Hasher) {
-        static SVT_AV1_V: LazyLock<String> = LazyLock::new(|| {
-            ffmpeg_svtav1_version()
-                .inspect_err(|e| debug!("read_ffmpeg_svtav1_version: {e}"))
-                .unwrap_or_default()
-        });
-
         // hashing svt-av1 version means new encoder releases will avoid old cache data
         if &*self.vcodec == "libsvtav1" {
             SVT_AV1_V.hash(state);
@@ -56,13 +69,110 @@ impl FfmpegEncodeArgs<'_> {
         self.preset.hash(state);
         self.output_args.hash(state);
         self.input_args.hash(state);
+        if let Some(fps) = self.input_fps {
+            fps.to_bits().hash(state);
+        }
+        if let Some(native) = &self.native {
+            native.sample_encode_hash(state);
+        }
+    }
+}
+
+/// Output stream selection & per-stream args.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputStreams {
+    /// Input stream indexes to map, in order. `None` maps all streams.
+    pub map: Option<Vec<usize>>,
+    pub audio: Vec<AudioStreamArgs>,
+    /// Output subtitle stream number & codec to convert to, overriding `-c:s copy`.
+    pub subtitle_codecs: Vec<(usize, &'static str)>,
+    /// Output stream specifier & disposition, e.g. ("a:0", "default").
+    pub dispositions: Vec<(String, &'static str)>,
+    /// Output video stream number & crf of secondary video streams to encode,
+    /// rather than copy.
+    pub secondary_video: Vec<(usize, f32)>,
+}
+
+impl OutputStreams {
+    fn map_args(&self) -> Vec<String> {
+        let mut args = match &self.map {
+            None => vec!["-map".into(), "0".into()],
+            Some(map) => map
+                .iter()
+                .flat_map(|idx| ["-map".into(), format!("0:{idx}")])
+                .collect(),
+        };
+        for audio in self.audio.iter().filter(|a| a.additional) {
+            args.extend(["-map".into(), format!("0:a:{}", audio.input_idx)]);
+        }
+        args
+    }
+
+    /// Args to encode secondary video streams with the primary video codec & their own crf.
+    fn secondary_video_args(&self, vcodec: &Arc<str>, output_args: &[Arc<String>]) -> Vec<String> {
+        let svtav1_params = output_args
+            .iter()
+            .skip_while(|a| a.as_str() != "-svtav1-params")
+            .nth(1);
+        let mut args = vec![];
+        for (n, crf) in &self.secondary_video {
+            args.extend([format!("-c:v:{n}"), vcodec.to_string()]);
+            args.extend([
+                format!("{}:v:{n}", vcodec.crf_arg()),
+                vcodec.crf(*crf).to_string(),
+            ]);
+            if let Some(params) = svtav1_params {
+                // replace the primary crf included in svtav1-params
+                let params: Vec<_> = params
+                    .split(':')
+                    .map(|p| match p.starts_with("crf=") {
+                        true => format!("crf={crf}"),
+                        false => p.to_owned(),
+                    })
+                    .collect();
+                args.extend([format!("-svtav1-params:v:{n}"), params.join(":")]);
+            }
+        }
+        args
     }
+
+    fn stream_args(&self) -> Vec<String> {
+        let mut args: Vec<_> = self.audio.iter().flat_map(|a| a.to_args()).collect();
+        for (n, codec) in &self.subtitle_codecs {
+            args.extend([format!("-c:s:{n}"), codec.to_string()]);
+        }
+        for (spec, disposition) in &self.dispositions {
+            args.extend([format!("-disposition:{spec}"), disposition.to_string()]);
+        }
+        args
+    }
+}
+
+/// Per output audio stream encoding args, overriding `-c:a` etc.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioStreamArgs {
+    /// Input audio stream number, i.e. `0:a:N`.
+    pub input_idx: usize,
+    /// Output audio stream number.
+    pub output_idx: usize,
+    /// Additionally map the input stream, rather than configure its default mapping.
+    pub additional: bool,
+    pub codec: Arc<str>,
+    pub bitrate_kbps: Option<u32>,
+    pub channels: Option<u32>,
+    pub filter: Option<String>,
+    pub title: Option<Arc<str>>,
 }
 
 /// Encode a sample.
 pub fn encode_sample(
-    FfmpegEncodeArgs {
+    args: FfmpegEncodeArgs,
+    temp_dir: Option<PathBuf>,
+    dest_ext: &str,
+) -> anyhow::Result<(PathBuf, EncodeStream)> {
+    let FfmpegEncodeArgs {
         input,
+        input_fps,
         vcodec,
         vfilter,
         pix_fmt,
@@ -71,12 +181,11 @@ pub fn encode_sample(
         output_args,
         input_args,
         video_only: _,
-    }: FfmpegEncodeArgs,
-    temp_dir: Option<PathBuf>,
-    dest_ext: &str,
-) -> anyhow::Result<(PathBuf, FfmpegOutStream)> {
-    let pre = pre_extension_name(&vcodec);
-    let crf_str = format!("{}", TerseF32(crf)).replace('.', "_");
+        video_stream: _,
+        native,
+    } = &args;
+    let pre = pre_extension_name(vcodec);
+    let crf_str = format!("{}", TerseF32(*crf)).replace('.', "_");
     let dest_file_name = match &preset {
         Some(p) => input.with_extension(format!("{pre}.crf{crf_str}.{p}.{dest_ext}")),
         None => input.with_extension(format!("{pre}.crf{crf_str}.{dest_ext}")),
@@ -87,22 +196,27 @@ pub fn encode_sample(
 
     temporary::add(&dest, TempKind::Keepable);
 
+    if let Some(native) = native {
+        let stream = native::encode_sample(&args, native, dest.clone())?;
+        return Ok((dest, stream));
+    }
+
     let mut cmd = Command::new("ffmpeg");
     cmd.kill_on_drop(true)
+        .stdin(Stdio::null())
         .arg("-y")
         .args(input_args.iter().map(|a| &**a))
-        .arg2("-i", input)
-        .arg2("-c:v", &*vcodec)
+        .arg_input(input, *input_fps, None)?
+        .arg2("-c:v", &**vcodec)
         .args(output_args.iter().map(|a| &**a))
         // Avoid dropping or duplicating frames as this may negatively affect input/output analysis
         .arg2("-fps_mode", "passthrough")
-        .arg2(vcodec.crf_arg(), vcodec.crf(crf))
+        .arg2(vcodec.crf_arg(), vcodec.crf(*crf))
         .arg2_opt("-pix_fmt", pix_fmt.map(|v| v.as_str()))
-        .arg2_opt(vcodec.preset_arg(), preset)
-        .arg2_opt("-vf", vfilter)
+        .arg2_opt(vcodec.preset_arg(), preset.clone())
+        .arg2_opt("-vf", *vfilter)
         .arg("-an")
         .arg(&dest)
-        .stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::piped());
     let cmd_str = cmd.to_cmd_str();
@@ -111,13 +225,38 @@ pub fn encode_sample(
     let enc = cmd.spawn().context("ffmpeg encode_sample")?;
 
     let stream = FfmpegOut::stream(enc, "ffmpeg encode_sample", cmd_str);
-    Ok((dest, stream))
+    Ok((dest, Box::pin(stream)))
 }
 
 /// Encode to output.
+///
+/// `provenance` encode settings are filled in from the args & written to the output metadata.
+///
+/// With a native encoder the primary video is first encoded to an intermediate ivf,
+/// then muxed as the first output stream.
+#[allow(clippy::too_many_arguments)]
 pub fn encode(
-    FfmpegEncodeArgs {
+    args: FfmpegEncodeArgs,
+    output: &Path,
+    has_audio: bool,
+    audio_codec: Option<&str>,
+    streams: &OutputStreams,
+    downmix_to_stereo: bool,
+    fail_fast: bool,
+    provenance: Provenance,
+) -> anyhow::Result<EncodeStream> {
+    let native_video = match &args.native {
+        Some(native) => {
+            let ivf = output.with_extension("native.ivf");
+            temporary::add(&ivf, TempKind::NotKeepable);
+            let video = native::encode_video(&args, native, true, &ivf)?;
+            Some((ivf, video))
+        }
+        None => None,
+    };
+    let FfmpegEncodeArgs {
         input,
+        input_fps,
         vcodec,
         vfilter,
         pix_fmt,
@@ -126,17 +265,15 @@ pub fn encode(
         output_args,
         input_args,
         video_only,
-    }: FfmpegEncodeArgs,
-    output: &Path,
-    has_audio: bool,
-    audio_codec: Option<&str>,
-    downmix_to_stereo: bool,
-    fail_fast: bool,
-) -> anyhow::Result<FfmpegOutStream> {
+        video_stream,
+        native,
+    } = args;
+
     let oargs: HashSet<_> = output_args.iter().map(|a| a.as_str()).collect();
     let output_ext = output.extension().and_then(|e| e.to_str());
 
-    let add_faststart = output_ext == Some("mp4") && !oargs.contains("-movflags");
+    // +use_metadata_tags allows custom metadata, e.g. AB_AV1_SETTINGS, in mp4
+    let add_movflags = output_ext == Some("mp4") && !oargs.contains("-movflags");
     let matroska = matches!(output_ext, Some("mkv") | Some("webm"));
     let add_cues_to_front = matroska && !oargs.contains("-cues_to_front");
 
@@ -148,57 +285,160 @@ pub fn encode(
 
     let set_ba_128k = audio_codec == "libopus" && !oargs.contains("-b:a");
     let downmix_to_stereo = downmix_to_stereo && !oargs.contains("-ac");
-    let map = match video_only {
-        true => "0:v:0",
-        false => "0",
+    // output video stream number of the primary video
+    let (mut map_args, v) = match video_only {
+        true => (vec!["-map".into(), format!("0:v:{video_stream}")], 0),
+        false => (streams.map_args(), video_stream),
     };
-    // This doesn't seem to work on .mp4 files
-    let mut metadata = format!(
-        "AB_AV1_FFMPEG_ARGS=-c:v {vcodec} {} {crf}",
-        vcodec.crf_arg()
-    );
-    if let Some(preset) = &preset {
+    let mut secondary_video_args = match video_only {
+        true => vec![],
+        false => streams.secondary_video_args(&vcodec, &output_args),
+    };
+    let high_bit_depth = pix_fmt.is_some_and(|p| p.as_str().contains("10"));
+    let mut metadata = match &native {
+        Some(native) => format!(
+            "AB_AV1_NATIVE_ARGS={} {}",
+            native.bin,
+            native
+                .encode_args(crf, preset.as_deref(), high_bit_depth)
+                .join(" ")
+        ),
+        None => format!(
+            "AB_AV1_FFMPEG_ARGS=-c:v {vcodec} {} {crf}",
+            vcodec.crf_arg()
+        ),
+    };
+    if let Some(preset) = preset.as_ref().filter(|_| native.is_none()) {
         write!(&mut metadata, " {} {preset}", vcodec.preset_arg()).unwrap();
     }
+    let provenance = Provenance {
+        version: env!("CARGO_PKG_VERSION").into(),
+        vcodec: vcodec.to_string(),
+        crf,
+        preset: preset.as_deref().map(Into::into),
+        pix_fmt: pix_fmt.map(|p| p.as_str().into()),
+        vfilter: vfilter.map(Into::into),
+        input_args: input_args.iter().map(|a| a.to_string()).collect(),
+        output_args: match &native {
+            Some(native) => native.args.iter().map(|a| a.to_string()).collect(),
+            None => output_args.iter().map(|a| a.to_string()).collect(),
+        },
+        encoder_version: match &native {
+            Some(native) => Some(native.version.to_string()).filter(|v| !v.is_empty()),
+            None => encoder_version(&vcodec),
+        },
+        ..provenance
+    };
 
     let mut cmd = Command::new("ffmpeg");
     cmd.kill_on_drop(true)
+        .stdin(Stdio::null())
         .args(input_args.iter().map(|a| &**a))
         .arg("-y")
         .arg_if(fail_fast, "-xerror")
-        .arg2("-i", input)
-        .arg2("-map", map)
-        .arg2("-c:v", "copy")
-        .arg2("-c:v:0", &*vcodec)
+        .arg_input(input, input_fps, None)?;
+    let v = match &native_video {
+        Some((ivf, _)) => {
+            // natively encoded primary video first, replacing the input primary video
+            cmd.arg2("-i", ivf);
+            map_args.splice(0..0, ["-map".into(), "1:v:0".into()]);
+            if !video_only {
+                map_args.extend(["-map".into(), format!("-0:v:{video_stream}")]);
+            }
+            secondary_video_args = match video_only {
+                true => vec![],
+                false => OutputStreams {
+                    secondary_video: streams
+                        .secondary_video
+                        .iter()
+                        .map(|(n, crf)| (n + usize::from(*n < video_stream), *crf))
+                        .collect(),
+                    ..<_>::default()
+                }
+                .secondary_video_args(&vcodec, &output_args),
+            };
+            None
+        }
+        None => Some(v),
+    };
+    cmd.args(map_args).arg2("-c:v", "copy");
+    if let Some(v) = v {
+        cmd.arg2(format!("-c:v:{v}"), &*vcodec);
+    }
+    cmd.arg2("-map_metadata", "0")
+        .arg2("-map_chapters", "0")
         .arg2("-metadata", metadata)
+        .arg2(
+            "-metadata",
+            format!("{}={}", provenance::METADATA_TAG, provenance.to_json()),
+        )
         .arg2("-c:a", audio_codec)
         .arg2("-c:s", "copy")
-        .args(output_args.iter().map(|a| &**a))
-        .arg2(vcodec.crf_arg(), vcodec.crf(crf))
-        .arg2_opt("-pix_fmt", pix_fmt.map(|v| v.as_str()))
-        .arg2_opt(vcodec.preset_arg(), preset)
-        .arg2_opt("-vf", vfilter)
+        .arg2("-c:t", "copy");
+    if let Some(v) = v {
+        cmd.args(output_args.iter().map(|a| &**a))
+            .arg2(vcodec.crf_arg(), vcodec.crf(crf))
+            .arg2_opt("-pix_fmt", pix_fmt.map(|v| v.as_str()))
+            .arg2_opt(vcodec.preset_arg(), preset)
+            .arg2_opt(format!("-filter:v:{v}"), vfilter);
+    }
+    cmd.args(secondary_video_args)
         .arg_if(matroska, "-dn") // "Only audio, video, and subtitles are supported for Matroska"
         .arg2_if(downmix_to_stereo, "-ac", 2)
         .arg2_if(set_ba_128k, "-b:a", "128k")
-        .arg2_if(add_faststart, "-movflags", "+faststart")
+        // per-stream args after the -c:a, -c:s, -ac & -b:a defaults they override
+        .args(streams.stream_args())
+        .arg2_if(add_movflags, "-movflags", "+faststart+use_metadata_tags")
         .arg2_if(add_cues_to_front, "-cues_to_front", "y")
         .arg(output)
-        .stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::piped());
+
+    if let Some((ivf, video)) = native_video {
+        return Ok(native::encode_then_mux(video, cmd, ivf));
+    }
+
     let cmd_str = cmd.to_cmd_str();
     debug!("cmd `{cmd_str}`");
 
     let enc = cmd.spawn().context("ffmpeg encode")?;
 
-    Ok(FfmpegOut::stream(enc, "ffmpeg encode", cmd_str))
+    Ok(Box::pin(FfmpegOut::stream(enc, "ffmpeg encode", cmd_str)))
+}
+
+impl AudioStreamArgs {
+    fn to_args(&self) -> Vec<String> {
+        let Self {
+            output_idx: n,
+            codec,
+            bitrate_kbps,
+            channels,
+            filter,
+            title,
+            ..
+        } = self;
+        let mut args = vec![format!("-c:a:{n}"), codec.to_string()];
+        if let Some(kbps) = bitrate_kbps {
+            args.extend([format!("-b:a:{n}"), format!("{kbps}k")]);
+        }
+        if let Some(channels) = channels {
+            args.extend([format!("-ac:a:{n}"), channels.to_string()]);
+        }
+        if let Some(filter) = filter {
+            args.extend([format!("-filter:a:{n}"), filter.clone()]);
+        }
+        if let Some(title) = title {
+            args.extend([format!("-metadata:s:a:{n}"), format!("title={title}")]);
+        }
+        args
+    }
 }
 
 pub fn pre_extension_name(vcodec: &str) -> &str {
+    if let Some(pre) = EncoderProfile::get(vcodec).pre_extension {
+        return pre;
+    }
     match vcodec.strip_prefix("lib").filter(|s| !s.is_empty()) {
-        Some("svtav1") => "av1",
-        Some("vpx-vp9") => "vp9",
         Some(suffix) => suffix,
         _ => vcodec,
     }
@@ -214,37 +454,16 @@ trait VCodecSpecific {
 }
 impl VCodecSpecific for Arc<str> {
     fn preset_arg(&self) -> &str {
-        match &**self {
-            "libaom-av1" | "libvpx-vp9" => "-cpu-used",
-            "librav1e" => "-speed",
-            _ => "-preset",
-        }
+        EncoderProfile::get(self).preset_arg
     }
 
     fn crf_arg(&self) -> &str {
         // use crf-like args to support encoders that don't have crf
-        match &**self {
-            // https://ffmpeg.org//ffmpeg-codecs.html#librav1e
-            // https://github.com/fraunhoferhhi/vvenc/wiki/FFmpeg-Integration#fix-qp-mode-constant-quality-mode
-            "librav1e" | "libvvenc" => "-qp",
-            "mpeg2video" => "-q",
-            "hevc_videotoolbox" => "-q:v",
-            // https://ffmpeg.org//ffmpeg-codecs.html#VAAPI-encoders
-            e if e.ends_with("_vaapi") => "-q",
-            e if e.ends_with("_vulkan") => "-qp",
-            e if e.ends_with("_nvenc") => "-cq",
-            // https://ffmpeg.org//ffmpeg-codecs.html#QSV-Encoders
-            e if e.ends_with("_qsv") => "-global_quality",
-            _ => "-crf",
-        }
+        EncoderProfile::get(self).crf_arg
     }
 
     fn crf(&self, crf: f32) -> f32 {
-        match &**self {
-            // ffmpeg svt-av1 crf above 63 don't work, but up to 70 does work in -svtav1-params
-            "libsvtav1" => crf.min(63.0),
-            _ => crf,
-        }
+        EncoderProfile::get(self).ffmpeg_crf(crf)
     }
 }
 
@@ -263,6 +482,14 @@ pub fn remove_arg(args: &mut Vec<Arc<String>>, arg: &'static str) {
     });
 }
 
+/// Returns the encoder library version, if known for `vcodec`.
+fn encoder_version(vcodec: &str) -> Option<String> {
+    match vcodec {
+        "libsvtav1" => Some(SVT_AV1_V.clone()).filter(|v| !v.is_empty()),
+        _ => None,
+    }
+}
+
 fn ffmpeg_svtav1_version() -> anyhow::Result<String> {
     let mut ffmpeg = std::process::Command::new("ffmpeg")
         .args([
@@ -377,3 +604,53 @@ pub async fn decode(file: &Path, mut on_progress: impl FnMut(FfmpegOut)) -> anyh
 
     Ok(())
 }
+
+#[test]
+fn audio_stream_args() {
+    let args = AudioStreamArgs {
+        input_idx: 0,
+        output_idx: 2,
+        additional: true,
+        codec: "libopus".into(),
+        bitrate_kbps: Some(128),
+        channels: Some(2),
+        filter: None,
+        title: Some("Stereo".into()),
+    };
+    assert_eq!(
+        args.to_args(),
+        [
+            "-c:a:2",
+            "libopus",
+            "-b:a:2",
+            "128k",
+            "-ac:a:2",
+            "2",
+            "-metadata:s:a:2",
+            "title=Stereo"
+        ]
+    );
+}
+
+#[test]
+fn secondary_video_args() {
+    let streams = OutputStreams {
+        secondary_video: vec![(1, 40.0)],
+        ..<_>::default()
+    };
+    let output_args = [
+        "-svtav1-params".to_owned().into(),
+        "scd=0:crf=30:film-grain=8".to_owned().into(),
+    ];
+    assert_eq!(
+        streams.secondary_video_args(&"libsvtav1".into(), &output_args),
+        [
+            "-c:v:1",
+            "libsvtav1",
+            "-crf:v:1",
+            "40",
+            "-svtav1-params:v:1",
+            "scd=0:crf=40:film-grain=8"
+        ]
+    );
+}
@@ -1,7 +1,8 @@
 //! ffprobe logic
-use crate::command::args::PixelFormat;
+use crate::{command::args::PixelFormat, script, sequence};
 use anyhow::{Context, anyhow};
-use std::{fmt, fs::File, io::Read, path::Path, time::Duration};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, fs::File, io::Read, path::Path, time::Duration};
 
 pub struct Ffprobe {
     /// Duration of video.
@@ -14,7 +15,90 @@ pub struct Ffprobe {
     pub fps: Result<f64, ProbeError>,
     pub resolution: Option<(u32, u32)>,
     pub is_image: bool,
+    /// An animated image, e.g. GIF, APNG or animated WebP.
+    pub animated: bool,
+    /// Variable frame rate, e.g. phone recordings & screen captures.
+    pub vfr: bool,
     pub pix_fmt: Option<String>,
+    /// Video field order, e.g. "progressive", "tt".
+    pub field_order: Option<String>,
+    /// All input streams.
+    pub streams: Vec<Stream>,
+    /// Input video stream number of the primary video, i.e. `0:v:N`.
+    ///
+    /// This is the first video stream that isn't an attached picture, e.g. cover art.
+    pub video_stream: usize,
+    /// Number of chapters.
+    pub chapters: usize,
+}
+
+/// A probed input stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stream {
+    /// Input stream index.
+    pub index: usize,
+    pub kind: StreamKind,
+    /// ffmpeg codec name, e.g. "truehd".
+    pub codec: Option<String>,
+    /// Codec profile, e.g. "DTS-HD MA".
+    pub profile: Option<String>,
+    /// Audio number of channels.
+    pub channels: Option<u32>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    /// Default disposition.
+    pub default: bool,
+    /// Forced disposition.
+    pub forced: bool,
+    /// Commentary disposition.
+    pub comment: bool,
+    /// Attached picture disposition, e.g. cover art.
+    pub attached_pic: bool,
+}
+
+impl Stream {
+    /// Commentary disposition or title.
+    pub fn is_commentary(&self) -> bool {
+        self.comment
+            || self
+                .title
+                .as_deref()
+                .is_some_and(|t| t.to_ascii_lowercase().contains("commentary"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StreamKind {
+    Video,
+    Audio,
+    Subtitle,
+    Attachment,
+    #[default]
+    Other,
+}
+
+impl From<&ffprobe::Stream> for Stream {
+    fn from(s: &ffprobe::Stream) -> Self {
+        Self {
+            index: usize::try_from(s.index).unwrap_or_default(),
+            kind: match s.codec_type.as_deref() {
+                Some("video") => StreamKind::Video,
+                Some("audio") => StreamKind::Audio,
+                Some("subtitle") => StreamKind::Subtitle,
+                Some("attachment") => StreamKind::Attachment,
+                _ => StreamKind::Other,
+            },
+            codec: s.codec_name.clone(),
+            profile: s.profile.clone(),
+            channels: s.channels.and_then(|c| u32::try_from(c).ok()),
+            language: s.tags.as_ref().and_then(|t| t.language.clone()),
+            title: None,
+            default: s.disposition.default != 0,
+            forced: s.disposition.forced != 0,
+            comment: s.disposition.comment != 0,
+            attached_pic: s.disposition.attached_pic != 0,
+        }
+    }
 }
 
 impl Ffprobe {
@@ -23,6 +107,11 @@ impl Ffprobe {
         PixelFormat::try_from(pf).ok()
     }
 
+    /// Input audio streams, in order so the nth is `0:a:n`.
+    pub fn audio_streams(&self) -> impl Iterator<Item = &Stream> {
+        self.streams.iter().filter(|s| s.kind == StreamKind::Audio)
+    }
+
     pub fn nframes(&self) -> Result<u64, ProbeError> {
         match (&self.fps, &self.duration) {
             (Ok(fps), Ok(duration)) => {
@@ -39,24 +128,39 @@ impl Ffprobe {
 }
 
 /// Try to ffprobe the given input.
-pub fn probe(input: &Path) -> Ffprobe {
+///
+/// VapourSynth & AviSynth scripts are probed by script introspection instead.
+/// Image sequences are probed using the first frame, at `fps`, i.e. `--fps`.
+pub fn probe(input: &Path, fps: Option<f64>) -> Ffprobe {
+    if script::is_script(input) {
+        return script::probe(input).unwrap_or_else(|err| failed(format!("script: {err:#}")));
+    }
+    if sequence::is_sequence(input) {
+        return sequence::probe(input, fps)
+            .unwrap_or_else(|err| failed(format!("image sequence: {err:#}")));
+    }
+
     let is_image = is_image(input).unwrap_or(false);
 
-    let probe = match ffprobe::ffprobe(input) {
+    let (probe, extra) = match run_ffprobe(input) {
         Ok(p) => p,
-        Err(err) => {
-            return Ffprobe {
-                duration: Err(ProbeError(format!("ffprobe: {err}"))),
-                fps: Err(ProbeError(format!("ffprobe: {err}"))),
-                has_audio: true,
-                max_audio_channels: None,
-                resolution: None,
-                is_image: false,
-                pix_fmt: None,
-            };
-        }
+        Err(err) => return failed(format!("ffprobe: {err}")),
     };
 
+    let streams = probe
+        .streams
+        .iter()
+        .zip(
+            extra
+                .streams
+                .into_iter()
+                .chain(std::iter::repeat_with(<_>::default)),
+        )
+        .map(|(s, extra)| Stream {
+            title: extra.tag("title"),
+            ..Stream::from(s)
+        })
+        .collect();
     let fps = read_fps(&probe);
     let duration = read_duration(&probe);
     let has_audio = probe
@@ -70,21 +174,35 @@ pub fn probe(input: &Path) -> Ffprobe {
         .filter_map(|a| a.channels)
         .max();
 
-    let resolution = probe
+    let video_stream = primary_video_stream(&probe);
+    let video = probe
         .streams
         .iter()
         .filter(|s| s.codec_type.as_deref() == Some("video"))
-        .find_map(|s| {
-            let w = s.width.and_then(|w| u32::try_from(w).ok())?;
-            let h = s.height.and_then(|w| u32::try_from(w).ok())?;
-            Some((w, h))
-        });
+        .nth(video_stream);
 
-    let pix_fmt = probe
-        .streams
-        .into_iter()
-        .filter(|s| s.codec_type.as_deref() == Some("video"))
-        .find_map(|s| s.pix_fmt);
+    let resolution = video.and_then(|s| {
+        let w = s.width.and_then(|w| u32::try_from(w).ok())?;
+        let h = s.height.and_then(|w| u32::try_from(w).ok())?;
+        Some((w, h))
+    });
+    let pix_fmt = video.and_then(|s| s.pix_fmt.clone());
+    let field_order = video.and_then(|s| s.field_order.clone());
+
+    // e.g. animated gif, treated as a video rather than a still image
+    let animated = is_image
+        && video
+            .and_then(|s| s.nb_frames.as_deref()?.parse::<u64>().ok())
+            .or_else(|| {
+                Some((fps.as_ref().ok()? * duration.as_ref().ok()?.as_secs_f64()).round() as u64)
+            })
+            .is_some_and(|frames| frames > 1);
+
+    // differing frame rates are only a hint, e.g. field rate interlaced video,
+    // so check packet timestamps to confirm
+    let vfr = !is_image
+        && video.is_some_and(|v| frame_rates_differ(&v.r_frame_rate, &v.avg_frame_rate))
+        && read_packet_pts(input, video_stream).is_ok_and(timestamps_vary);
 
     Ffprobe {
         duration: duration.map_err(ProbeError::from),
@@ -92,11 +210,245 @@ pub fn probe(input: &Path) -> Ffprobe {
         has_audio,
         max_audio_channels,
         resolution,
-        is_image,
+        is_image: is_image && !animated,
+        animated,
+        vfr,
         pix_fmt,
+        field_order,
+        streams,
+        video_stream,
+        chapters: extra.chapters.len(),
+    }
+}
+
+fn failed(err: String) -> Ffprobe {
+    Ffprobe {
+        duration: Err(ProbeError(err.clone())),
+        fps: Err(ProbeError(err)),
+        has_audio: true,
+        max_audio_channels: None,
+        resolution: None,
+        is_image: false,
+        animated: false,
+        vfr: false,
+        pix_fmt: None,
+        field_order: None,
+        streams: vec![],
+        video_stream: 0,
+        chapters: 0,
+    }
+}
+
+/// A 60s 1080p 24fps video without audio.
+#[cfg(test)]
+impl Default for Ffprobe {
+    fn default() -> Self {
+        Self {
+            duration: Ok(Duration::from_secs(60)),
+            has_audio: false,
+            max_audio_channels: None,
+            fps: Ok(24.0),
+            resolution: Some((1920, 1080)),
+            is_image: false,
+            animated: false,
+            vfr: false,
+            pix_fmt: None,
+            field_order: None,
+            streams: vec![],
+            video_stream: 0,
+            chapters: 0,
+        }
+    }
+}
+
+/// Ffprobe json data not read by the ffprobe crate.
+#[derive(Debug, Default, Deserialize)]
+struct Extra {
+    #[serde(default)]
+    streams: Vec<ExtraStream>,
+    #[serde(default)]
+    chapters: Vec<serde::de::IgnoredAny>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ExtraStream {
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+impl ExtraStream {
+    /// Case insensitive tag value, as containers vary, e.g. "title" or "TITLE".
+    fn tag(&self, key: &str) -> Option<String> {
+        self.tags
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.clone())
     }
 }
 
+/// Run ffprobe using the same args as [`ffprobe::ffprobe`], plus chapters, also returning
+/// [`Extra`] data.
+fn run_ffprobe(input: &Path) -> anyhow::Result<(ffprobe::FfProbe, Extra)> {
+    let out = std::process::Command::new("ffprobe")
+        .args([
+            "-v",
+            "quiet",
+            "-show_format",
+            "-show_streams",
+            "-show_chapters",
+        ])
+        .args(["-print_format", "json"])
+        .arg(input)
+        .output()?;
+    anyhow::ensure!(
+        out.status.success(),
+        "ffprobe exit code {:?}",
+        out.status.code()
+    );
+
+    let probe = serde_json::from_slice(&out.stdout)?;
+    let extra = serde_json::from_slice(&out.stdout)?;
+    Ok((probe, extra))
+}
+
+/// Read a format, i.e. global, metadata tag value of `input`. Case insensitive.
+pub fn read_format_tag(input: &Path, key: &str) -> anyhow::Result<Option<String>> {
+    #[derive(Deserialize)]
+    struct Out {
+        // format tags have the same structure as stream tags
+        #[serde(default)]
+        format: ExtraStream,
+    }
+
+    let out = std::process::Command::new("ffprobe")
+        .args(["-v", "quiet", "-show_entries", "format_tags"])
+        .args(["-print_format", "json"])
+        .arg(input)
+        .output()?;
+    anyhow::ensure!(
+        out.status.success(),
+        "ffprobe exit code {:?}",
+        out.status.code()
+    );
+    let out: Out = serde_json::from_slice(&out.stdout)?;
+    Ok(out.format.tag(key))
+}
+
+/// Returns the total packet size of input video stream `0:v:{video_stream}`,
+/// i.e. the video stream size excluding audio, subtitles, etc.
+pub async fn video_stream_size(input: &Path, video_stream: usize) -> anyhow::Result<u64> {
+    let out = tokio::process::Command::new("ffprobe")
+        .args(["-v", "quiet"])
+        .args(["-select_streams", &format!("v:{video_stream}")])
+        .args(["-show_entries", "packet=size"])
+        .args(["-of", "csv=p=0"])
+        .arg(input)
+        .output()
+        .await?;
+    anyhow::ensure!(
+        out.status.success(),
+        "ffprobe exit code {:?}",
+        out.status.code()
+    );
+    Ok(sum_packet_sizes(&String::from_utf8_lossy(&out.stdout)))
+}
+
+/// Sum csv `size` lines.
+fn sum_packet_sizes(out: &str) -> u64 {
+    out.lines()
+        .filter_map(|l| l.trim().trim_end_matches(',').parse::<u64>().ok())
+        .sum()
+}
+
+/// Whether the `r_frame_rate` & `avg_frame_rate` differ by more than 1%.
+fn frame_rates_differ(r_frame_rate: &str, avg_frame_rate: &str) -> bool {
+    match (
+        parse_frame_rate(r_frame_rate),
+        parse_frame_rate(avg_frame_rate),
+    ) {
+        (Some(r), Some(avg)) => (r - avg).abs() / avg > 0.01,
+        _ => false,
+    }
+}
+
+/// Read the pts of the first 300 packets of input video stream `0:v:{video_stream}`.
+fn read_packet_pts(input: &Path, video_stream: usize) -> anyhow::Result<Vec<f64>> {
+    let out = std::process::Command::new("ffprobe")
+        .args(["-v", "quiet"])
+        .args(["-select_streams", &format!("v:{video_stream}")])
+        .args(["-read_intervals", "%+#300"])
+        .args(["-show_entries", "packet=pts_time"])
+        .args(["-of", "csv=p=0"])
+        .arg(input)
+        .output()?;
+    anyhow::ensure!(
+        out.status.success(),
+        "ffprobe exit code {:?}",
+        out.status.code()
+    );
+    Ok(String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|l| l.trim().trim_end_matches(',').parse().ok())
+        .collect())
+}
+
+/// Whether frame intervals vary, i.e. over 5% of intervals are more than 50% away from
+/// the median interval.
+fn timestamps_vary(mut pts: Vec<f64>) -> bool {
+    // packets are in decode order, b-frames are reordered
+    pts.sort_by(f64::total_cmp);
+    let mut intervals: Vec<_> = pts
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .filter(|i| *i > 0.0)
+        .collect();
+    if intervals.len() < 10 {
+        return false;
+    }
+    intervals.sort_by(f64::total_cmp);
+    let median = intervals[intervals.len() / 2];
+    let varying = intervals
+        .iter()
+        .filter(|i| (*i - median).abs() > median * 0.5)
+        .count();
+    varying * 20 > intervals.len()
+}
+
+/// Returns the timestamp of the keyframe a seek to `at` lands on, i.e. the keyframe
+/// before `at` that a `-ss` stream copy starts from.
+pub async fn seek_keyframe(
+    input: &Path,
+    video_stream: usize,
+    at: Duration,
+) -> anyhow::Result<Option<Duration>> {
+    let out = tokio::process::Command::new("ffprobe")
+        .args(["-v", "quiet"])
+        .args(["-select_streams", &format!("v:{video_stream}")])
+        .args(["-read_intervals", &format!("{}%+#1", at.as_secs_f64())])
+        .args(["-show_entries", "packet=pts_time,flags"])
+        .args(["-of", "csv=p=0"])
+        .arg(input)
+        .output()
+        .await?;
+    anyhow::ensure!(
+        out.status.success(),
+        "ffprobe exit code {:?}",
+        out.status.code()
+    );
+    Ok(parse_seek_keyframe(&String::from_utf8_lossy(&out.stdout)))
+}
+
+/// Parse the first keyframe packet pts of csv `pts_time,flags` lines.
+fn parse_seek_keyframe(out: &str) -> Option<Duration> {
+    out.lines().find_map(|line| {
+        let (pts, flags) = line.trim().split_once(',')?;
+        if !flags.starts_with('K') {
+            return None;
+        }
+        Duration::try_from_secs_f64(pts.parse().ok()?).ok()
+    })
+}
+
 fn is_image(path: &Path) -> anyhow::Result<bool> {
     let file = File::open(path)?;
     let mut file_header = Vec::with_capacity(8192);
@@ -118,11 +470,23 @@ fn read_duration(probe: &ffprobe::FfProbe) -> anyhow::Result<Duration> {
     }
 }
 
+/// Returns the video stream number of the first video stream that isn't an
+/// attached picture, or 0.
+fn primary_video_stream(probe: &ffprobe::FfProbe) -> usize {
+    probe
+        .streams
+        .iter()
+        .filter(|s| s.codec_type.as_deref() == Some("video"))
+        .position(|s| s.disposition.attached_pic == 0)
+        .unwrap_or(0)
+}
+
 fn read_fps(probe: &ffprobe::FfProbe) -> anyhow::Result<f64> {
     let vstream = probe
         .streams
         .iter()
-        .find(|s| s.codec_type.as_deref() == Some("video"))
+        .filter(|s| s.codec_type.as_deref() == Some("video"))
+        .nth(primary_video_stream(probe))
         .context("no video stream found")?;
 
     parse_frame_rate(&vstream.avg_frame_rate)
@@ -162,3 +526,51 @@ impl From<anyhow::Error> for ProbeError {
 }
 
 impl std::error::Error for ProbeError {}
+
+#[test]
+fn parse_seek_keyframe_packets() {
+    assert_eq!(
+        parse_seek_keyframe("130.213000,K__\n"),
+        Some(Duration::from_millis(130_213))
+    );
+    assert_eq!(
+        parse_seek_keyframe("N/A,K__\n10.5,K_\n"),
+        Some(Duration::from_millis(10_500))
+    );
+    assert_eq!(parse_seek_keyframe("12.000000,___\n"), None);
+    assert_eq!(parse_seek_keyframe(""), None);
+}
+
+#[test]
+fn detect_vfr() {
+    assert!(frame_rates_differ("30/1", "24000/1001"));
+    assert!(!frame_rates_differ("30000/1001", "30/1"));
+    assert!(!frame_rates_differ("0/0", "30/1"));
+
+    let cfr: Vec<_> = (0..100).map(|n| n as f64 / 30.0).collect();
+    assert!(!timestamps_vary(cfr.clone()));
+
+    // decode order with b-frames
+    let mut reordered = cfr.clone();
+    reordered.swap(3, 4);
+    assert!(!timestamps_vary(reordered));
+
+    // screen capture, frames only when something changes
+    let vfr: Vec<_> = (0..100)
+        .map(|n| match n % 4 {
+            0 => n as f64 / 30.0 + 0.5,
+            _ => n as f64 / 30.0,
+        })
+        .scan(0.0, |last, pts: f64| {
+            *last = pts.max(*last + 0.001);
+            Some(*last)
+        })
+        .collect();
+    assert!(timestamps_vary(vfr));
+}
+
+#[test]
+fn packet_sizes() {
+    assert_eq!(sum_packet_sizes("1200\n340,\n\n60\n"), 1600);
+    assert_eq!(sum_packet_sizes(""), 0);
+}
@@ -0,0 +1,367 @@
+//! Per-frame VMAF/XPSNR report logic
+use anyhow::Context;
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt::Write, path::Path, time::Duration};
+
+/// Number of worst time ranges reported.
+const WORST_RANGES: usize = 5;
+
+/// Per-frame scores, read from a libvmaf json log or xpsnr stats file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameReport {
+    /// Name of the main score metric, e.g. "vmaf".
+    pub score: &'static str,
+    /// Frame rate used for frame timestamps.
+    pub fps: f64,
+    pub frames: Vec<Frame>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Frame index starting from 0.
+    pub index: u64,
+    /// Main score & sub-features, e.g. "vmaf", "integer_adm2".
+    pub metrics: BTreeMap<String, f64>,
+}
+
+/// Aggregate score statistics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub mean: f64,
+    /// Harmonic mean, calculated like libvmaf as `n / Σ(1 / (x + 1)) - 1`.
+    pub harmonic_mean: f64,
+    pub min: f64,
+    /// 1st percentile.
+    pub p1: f64,
+    /// 5th percentile.
+    pub p5: f64,
+    pub median: f64,
+}
+
+/// Time range mean score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeRange {
+    pub start: Duration,
+    pub end: Duration,
+    pub mean: f64,
+}
+
+impl FrameReport {
+    /// Parse a libvmaf `log_fmt=json` log.
+    pub fn from_vmaf_log(json: &[u8], fps: f64) -> anyhow::Result<Self> {
+        #[derive(Deserialize)]
+        struct Log {
+            frames: Vec<LogFrame>,
+        }
+        #[derive(Deserialize)]
+        struct LogFrame {
+            #[serde(rename = "frameNum")]
+            frame_num: u64,
+            metrics: BTreeMap<String, f64>,
+        }
+
+        let log: Log = serde_json::from_slice(json).context("invalid vmaf log")?;
+        let frames = log
+            .frames
+            .into_iter()
+            .map(|f| Frame {
+                index: f.frame_num,
+                metrics: f.metrics,
+            })
+            .collect();
+        Ok(Self {
+            score: "vmaf",
+            fps,
+            frames,
+        })
+    }
+
+    /// Parse a xpsnr `stats_file`. The frame score is the minimum plane score.
+    pub fn from_xpsnr_stats(stats: &str, fps: f64) -> Self {
+        let frames = stats
+            .lines()
+            .filter_map(|line| {
+                // E.g. "n:    1  XPSNR y: 54.5266  XPSNR u: 56.3886  XPSNR v: 58.7794"
+                let (n, planes) = line.strip_prefix("n:")?.trim_start().split_once(' ')?;
+                let n: u64 = n.parse().ok()?;
+                let planes = crate::xpsnr::planes_from_line(planes);
+                let min = planes.iter().map(|(_, s)| *s).reduce(f32::min)?;
+                let mut metrics: BTreeMap<_, _> = planes
+                    .into_iter()
+                    .map(|(p, s)| (format!("xpsnr_{p}"), f64::from(s)))
+                    .collect();
+                metrics.insert("xpsnr".into(), f64::from(min));
+                Some(Frame {
+                    index: n.saturating_sub(1),
+                    metrics,
+                })
+            })
+            .collect();
+        Self {
+            score: "xpsnr",
+            fps,
+            frames,
+        }
+    }
+
+    fn frame_time(&self, index: u64) -> Duration {
+        Duration::from_secs_f64(index as f64 / self.fps.max(f64::EPSILON))
+    }
+
+    /// Main frame scores.
+    pub fn scores(&self) -> impl Iterator<Item = f64> + '_ {
+        self.frames
+            .iter()
+            .filter_map(|f| f.metrics.get(self.score).copied())
+    }
+
+    pub fn stats(&self) -> Option<Stats> {
+        let mut scores: Vec<_> = self.scores().filter(|s| !s.is_nan()).collect();
+        if scores.is_empty() {
+            return None;
+        }
+        scores.sort_by(f64::total_cmp);
+        let n = scores.len() as f64;
+        let percentile = |p: f64| scores[((p / 100.0) * (n - 1.0)).round() as usize];
+        Some(Stats {
+            mean: scores.iter().sum::<f64>() / n,
+            harmonic_mean: n / scores.iter().map(|s| 1.0 / (s + 1.0)).sum::<f64>() - 1.0,
+            min: scores[0],
+            p1: percentile(1.0),
+            p5: percentile(5.0),
+            median: percentile(50.0),
+        })
+    }
+
+    /// Returns the lowest mean score, non-overlapping, ~1s time ranges. Worst first.
+    pub fn worst_ranges(&self, max: usize) -> Vec<TimeRange> {
+        let window = (self.fps.round() as usize).max(1);
+        let mut ranges: Vec<_> = self
+            .frames
+            .chunks(window)
+            .filter_map(|frames| {
+                let scores: Vec<_> = frames
+                    .iter()
+                    .filter_map(|f| f.metrics.get(self.score))
+                    .collect();
+                if scores.is_empty() {
+                    return None;
+                }
+                Some(TimeRange {
+                    start: self.frame_time(frames[0].index),
+                    end: self.frame_time(frames[frames.len() - 1].index + 1),
+                    mean: scores.iter().copied().sum::<f64>() / scores.len() as f64,
+                })
+            })
+            .collect();
+        ranges.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+        ranges.truncate(max);
+        ranges
+    }
+
+    /// Metric names, the main score first.
+    fn metric_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = vec![self.score];
+        for frame in &self.frames {
+            for name in frame.metrics.keys() {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    pub fn to_csv(&self) -> String {
+        let names = self.metric_names();
+        let mut csv = format!("frame,time,{}\n", names.join(","));
+        for frame in &self.frames {
+            write!(
+                csv,
+                "{},{:.3}",
+                frame.index,
+                self.frame_time(frame.index).as_secs_f64()
+            )
+            .unwrap();
+            for name in &names {
+                csv.push(',');
+                if let Some(v) = frame.metrics.get(*name) {
+                    write!(csv, "{v}").unwrap();
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let frames: Vec<_> = self
+            .frames
+            .iter()
+            .map(|f| {
+                let mut json = serde_json::json!({
+                    "frame": f.index,
+                    "time": self.frame_time(f.index).as_secs_f64(),
+                });
+                for (name, v) in &f.metrics {
+                    json[name] = (*v).into();
+                }
+                json
+            })
+            .collect();
+        let stats = self.stats().map(|s| {
+            serde_json::json!({
+                "mean": s.mean,
+                "harmonic_mean": s.harmonic_mean,
+                "min": s.min,
+                "p1": s.p1,
+                "p5": s.p5,
+                "median": s.median,
+            })
+        });
+        let worst: Vec<_> = self
+            .worst_ranges(WORST_RANGES)
+            .into_iter()
+            .map(|r| {
+                serde_json::json!({
+                    "start": r.start.as_secs_f64(),
+                    "end": r.end.as_secs_f64(),
+                    "mean": r.mean,
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "score": self.score,
+            "stats": stats,
+            "worst": worst,
+            "frames": frames,
+        })
+    }
+
+    /// Write the report to `path`, as json if it has a `.json` extension, otherwise csv.
+    pub async fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let out = match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => self.to_json().to_string(),
+            _ => self.to_csv(),
+        };
+        tokio::fs::write(path, out)
+            .await
+            .with_context(|| format!("write {}", path.display()))
+    }
+
+    /// Print aggregate statistics & the worst time ranges to stderr.
+    pub fn print_summary(&self) {
+        let label = self.score.to_ascii_uppercase();
+        if let Some(Stats {
+            mean,
+            harmonic_mean,
+            min,
+            p1,
+            p5,
+            median,
+        }) = self.stats()
+        {
+            eprintln!(
+                "{label} mean {mean:.2}, harmonic mean {harmonic_mean:.2}, min {min:.2}, \
+                 1% {p1:.2}, 5% {p5:.2}, median {median:.2}"
+            );
+        }
+        let worst = self.worst_ranges(WORST_RANGES);
+        if !worst.is_empty() {
+            eprintln!("Worst:");
+            for TimeRange { start, end, mean } in worst {
+                eprintln!(
+                    "  {}-{} {label} {mean:.2}",
+                    format_time(start),
+                    format_time(end)
+                );
+            }
+        }
+    }
+}
+
+/// Format as `hh:mm:ss.mmm`.
+fn format_time(t: Duration) -> String {
+    let secs = t.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        t.subsec_millis()
+    )
+}
+
+/// Escape `path` for use as a filter option value in a filtergraph.
+pub fn filter_path(path: &Path) -> String {
+    filter_value(&path.to_string_lossy())
+}
+
+/// Returns `value` escaped & quoted for use as an ffmpeg filter option value.
+pub fn filter_value(value_str: &str) -> String {
+    let mut value = String::new();
+    for c in value_str.chars() {
+        if matches!(c, '\\' | '\'' | ':') {
+            value.push('\\');
+        }
+        value.push(c);
+    }
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[test]
+fn vmaf_log_report() {
+    let log = br#"{
+      "version": "3.0.0",
+      "frames": [
+        {"frameNum": 0, "metrics": {"integer_adm2": 0.99, "vmaf": 96.0}},
+        {"frameNum": 1, "metrics": {"integer_adm2": 0.98, "vmaf": 94.0}},
+        {"frameNum": 2, "metrics": {"integer_adm2": 0.90, "vmaf": 80.0}},
+        {"frameNum": 3, "metrics": {"integer_adm2": 0.99, "vmaf": 98.0}}
+      ],
+      "pooled_metrics": {}
+    }"#;
+    let report = FrameReport::from_vmaf_log(log, 2.0).unwrap();
+
+    let stats = report.stats().unwrap();
+    assert_eq!(stats.mean, 92.0);
+    assert_eq!(stats.min, 80.0);
+    assert_eq!(stats.median, 96.0);
+
+    let worst = report.worst_ranges(5);
+    assert_eq!(worst.len(), 2);
+    assert_eq!(worst[0].start, Duration::from_secs(1));
+    assert_eq!(worst[0].end, Duration::from_secs(2));
+    assert_eq!(worst[0].mean, 89.0);
+
+    assert_eq!(
+        report.to_csv(),
+        "frame,time,vmaf,integer_adm2\n\
+         0,0.000,96,0.99\n\
+         1,0.500,94,0.98\n\
+         2,1.000,80,0.9\n\
+         3,1.500,98,0.99\n"
+    );
+}
+
+#[test]
+fn xpsnr_stats_report() {
+    let stats = "n:    1  XPSNR y: 54.5266  XPSNR u: 56.3886  XPSNR v: 58.7794\n\
+                 n:    2  XPSNR y: 40.6035  XPSNR u: 39.3487  XPSNR v: 42.3634\n";
+    let report = FrameReport::from_xpsnr_stats(stats, 25.0);
+    assert_eq!(report.frames.len(), 2);
+    assert_eq!(report.frames[1].index, 1);
+    let metrics = &report.frames[1].metrics;
+    assert_eq!(metrics["xpsnr"], f64::from(39.3487_f32));
+    assert_eq!(metrics["xpsnr_y"], f64::from(40.6035_f32));
+    assert_eq!(metrics["xpsnr_v"], f64::from(42.3634_f32));
+}
+
+#[test]
+fn escape_filter_path() {
+    assert_eq!(filter_path(Path::new("/tmp/a.json")), "'/tmp/a.json'");
+    assert_eq!(
+        filter_path(Path::new(r"C:\tmp\a'b.json")),
+        r"'C\:\\tmp\\a\'\''b.json'"
+    );
+}
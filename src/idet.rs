@@ -0,0 +1,235 @@
+//! Interlace detection logic
+use crate::{
+    ffprobe::Ffprobe,
+    process::{CommandExt, ensure_success},
+    sample,
+};
+use anyhow::Context;
+use log::{debug, info};
+use std::{fmt, ops::AddAssign, path::Path, process::Stdio, time::Duration};
+use tokio::process::Command;
+
+/// Number of points across the input to run idet at.
+const POSITIONS: u64 = 6;
+/// Frames analysed at each point.
+const POSITION_FRAMES: u32 = 120;
+/// Duration used to spread the positions, roughly covers `POSITION_FRAMES`.
+const POSITION_DURATION: Duration = Duration::from_secs(5);
+
+/// Detected input scan type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scan {
+    Progressive,
+    Interlaced,
+    /// Progressive content with 3:2 pulldown applied.
+    Telecined,
+}
+
+impl Scan {
+    /// ffmpeg vfilter to produce progressive frames, if required.
+    ///
+    /// Deinterlacing keeps the frame rate, inverse telecine restores the original frame rate.
+    pub fn vfilter(self) -> Option<&'static str> {
+        match self {
+            Self::Progressive => None,
+            Self::Interlaced => Some("bwdif=mode=send_frame"),
+            Self::Telecined => Some("fieldmatch,decimate"),
+        }
+    }
+}
+
+impl fmt::Display for Scan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Progressive => "progressive".fmt(f),
+            Self::Interlaced => "interlaced".fmt(f),
+            Self::Telecined => "telecined".fmt(f),
+        }
+    }
+}
+
+/// Frame counts reported by ffmpeg idet.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Counts {
+    /// Multi frame detection top field first frames.
+    tff: u64,
+    /// Multi frame detection bottom field first frames.
+    bff: u64,
+    /// Multi frame detection progressive frames.
+    progressive: u64,
+    /// Frames with a repeated top or bottom field.
+    repeated: u64,
+    /// Frames with no repeated field.
+    not_repeated: u64,
+}
+
+impl AddAssign for Counts {
+    fn add_assign(&mut self, rhs: Self) {
+        self.tff += rhs.tff;
+        self.bff += rhs.bff;
+        self.progressive += rhs.progressive;
+        self.repeated += rhs.repeated;
+        self.not_repeated += rhs.not_repeated;
+    }
+}
+
+impl Counts {
+    /// Classify the counts. Falls back to the ffprobe `field_order` if idet is inconclusive.
+    fn scan(&self, field_order: Option<&str>) -> Scan {
+        let interlaced = self.tff + self.bff;
+        let determined = interlaced + self.progressive;
+        let fields = self.repeated + self.not_repeated;
+
+        // 3:2 pulldown repeats a field in 2 of every 5 frames
+        if fields > 0 && self.repeated as f64 / fields as f64 >= 0.15 {
+            return Scan::Telecined;
+        }
+        if determined > 0 {
+            return match interlaced as f64 / determined as f64 >= 0.5 {
+                true => Scan::Interlaced,
+                false => Scan::Progressive,
+            };
+        }
+        match field_order {
+            Some("tt" | "bb" | "tb" | "bt") => Scan::Interlaced,
+            _ => Scan::Progressive,
+        }
+    }
+}
+
+/// Detect interlaced or telecined content by running ffmpeg idet at several points
+/// across the input, reading image sequences at `fps`.
+pub async fn detect(input: &Path, fps: Option<f64>, probe: &Ffprobe) -> anyhow::Result<Scan> {
+    if probe.is_image {
+        return Ok(Scan::Progressive);
+    }
+    let duration = probe.duration.clone().unwrap_or_default();
+    let positions = match duration <= POSITION_DURATION {
+        true => 1,
+        false => POSITIONS,
+    };
+    info!(
+        "idet {} at {positions} positions, field_order {:?}",
+        input.file_name().and_then(|n| n.to_str()).unwrap_or(""),
+        probe.field_order.as_deref().unwrap_or("unknown"),
+    );
+
+    let mut counts = Counts::default();
+    for idx in 0..positions {
+        let start = match positions {
+            1 => Duration::ZERO,
+            _ => sample::start(idx, positions, POSITION_DURATION, duration),
+        };
+        counts += detect_at(input, fps, probe.video_stream, start).await?;
+    }
+    debug!("idet {counts:?}");
+
+    Ok(counts.scan(probe.field_order.as_deref()))
+}
+
+async fn detect_at(
+    input: &Path,
+    fps: Option<f64>,
+    video_stream: usize,
+    start: Duration,
+) -> anyhow::Result<Counts> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.kill_on_drop(true)
+        .stdin(Stdio::null())
+        .arg_input(input, fps, Some(start))?
+        .arg2("-map", format!("0:v:{video_stream}"))
+        .arg2("-frames:v", POSITION_FRAMES)
+        .arg2("-vf", "idet")
+        .arg("-an")
+        .arg("-sn")
+        .arg("-dn")
+        .arg2("-f", "null")
+        .arg("-");
+    debug!("cmd `{}`", cmd.to_cmd_str());
+
+    let out = cmd.output().await.context("ffmpeg idet")?;
+    ensure_success("ffmpeg idet", &out)?;
+
+    Ok(counts_from_stderr(&String::from_utf8_lossy(&out.stderr)))
+}
+
+// E.g.
+// "[Parsed_idet_0 @ 0x5f1a] Repeated Fields: Neither:   1199 Top:     0 Bottom:     0"
+// "[Parsed_idet_0 @ 0x5f1a] Single frame detection: TFF:     0 BFF:     0 Progressive:   935 Undetermined:   265"
+// "[Parsed_idet_0 @ 0x5f1a] Multi frame detection: TFF:     0 BFF:     0 Progressive:  1198 Undetermined:     2"
+fn counts_from_stderr(stderr: &str) -> Counts {
+    let mut counts = Counts::default();
+    for line in stderr.lines().filter(|l| l.contains("idet")) {
+        if let Some((_, tail)) = line.split_once("Multi frame detection:") {
+            counts.tff = value_after(tail, "TFF:").unwrap_or_default();
+            counts.bff = value_after(tail, "BFF:").unwrap_or_default();
+            counts.progressive = value_after(tail, "Progressive:").unwrap_or_default();
+        } else if let Some((_, tail)) = line.split_once("Repeated Fields:") {
+            counts.not_repeated = value_after(tail, "Neither:").unwrap_or_default();
+            counts.repeated = value_after(tail, "Top:").unwrap_or_default()
+                + value_after(tail, "Bottom:").unwrap_or_default();
+        }
+    }
+    counts
+}
+
+fn value_after(s: &str, label: &str) -> Option<u64> {
+    s.split_once(label)?
+        .1
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[test]
+fn parse_progressive() {
+    let counts = counts_from_stderr(
+        "[Parsed_idet_0 @ 0x5f1a2b3c4d00] Repeated Fields: Neither:   119 Top:     0 Bottom:     1\n\
+         [Parsed_idet_0 @ 0x5f1a2b3c4d00] Single frame detection: TFF:     0 BFF:     0 Progressive:    93 Undetermined:    27\n\
+         [Parsed_idet_0 @ 0x5f1a2b3c4d00] Multi frame detection: TFF:     1 BFF:     0 Progressive:   117 Undetermined:     2",
+    );
+    assert_eq!(
+        counts,
+        Counts {
+            tff: 1,
+            bff: 0,
+            progressive: 117,
+            repeated: 1,
+            not_repeated: 119,
+        }
+    );
+    assert_eq!(counts.scan(Some("progressive")), Scan::Progressive);
+}
+
+#[test]
+fn scan_interlaced() {
+    let counts = Counts {
+        tff: 110,
+        bff: 0,
+        progressive: 8,
+        repeated: 2,
+        not_repeated: 118,
+    };
+    assert_eq!(counts.scan(None), Scan::Interlaced);
+}
+
+#[test]
+fn scan_telecined() {
+    let counts = Counts {
+        tff: 46,
+        bff: 0,
+        progressive: 72,
+        repeated: 48,
+        not_repeated: 72,
+    };
+    assert_eq!(counts.scan(Some("progressive")), Scan::Telecined);
+}
+
+#[test]
+fn scan_undetermined_uses_field_order() {
+    let counts = Counts::default();
+    assert_eq!(counts.scan(Some("tt")), Scan::Interlaced);
+    assert_eq!(counts.scan(Some("progressive")), Scan::Progressive);
+    assert_eq!(counts.scan(None), Scan::Progressive);
+}
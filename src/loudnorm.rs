@@ -0,0 +1,128 @@
+//! EBU R128 loudness normalisation logic
+use crate::process::{CommandExt, ensure_success};
+use anyhow::Context;
+use log::{debug, info};
+use serde::Deserialize;
+use std::{path::Path, process::Stdio};
+use tokio::process::Command;
+
+/// EBU R128 integrated loudness, true peak & loudness range targets.
+const TARGET: &str = "I=-23:TP=-1:LRA=7";
+
+/// Values measured by a first pass of ffmpeg `loudnorm`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Measured {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+}
+
+impl Measured {
+    /// Second pass ffmpeg audio filter using the measured values.
+    ///
+    /// loudnorm upsamples to 192kHz so is followed by a resample to 48kHz.
+    pub fn afilter(&self) -> String {
+        let Self {
+            input_i,
+            input_tp,
+            input_lra,
+            input_thresh,
+            target_offset,
+        } = self;
+        format!(
+            "loudnorm={TARGET}:measured_I={input_i}:measured_TP={input_tp}:\
+             measured_LRA={input_lra}:measured_thresh={input_thresh}:\
+             offset={target_offset}:linear=true,aresample=48000"
+        )
+    }
+}
+
+/// Measure the loudness of input audio stream `0:a:{audio_idx}`, after the `pre_filter`
+/// if any, e.g. a downmix.
+///
+/// This decodes the entire stream.
+pub async fn measure(
+    input: &Path,
+    audio_idx: usize,
+    pre_filter: Option<&str>,
+) -> anyhow::Result<Measured> {
+    info!(
+        "loudnorm measure {} audio stream {audio_idx}",
+        input.file_name().and_then(|n| n.to_str()).unwrap_or(""),
+    );
+
+    let mut cmd = Command::new("ffmpeg");
+    cmd.kill_on_drop(true)
+        .arg2("-i", input)
+        .arg2("-map", format!("0:a:{audio_idx}"))
+        .arg2("-af", measure_afilter(pre_filter))
+        .arg("-vn")
+        .arg("-sn")
+        .arg("-dn")
+        .arg2("-f", "null")
+        .arg("-")
+        .stdin(Stdio::null());
+    debug!("cmd `{}`", cmd.to_cmd_str());
+
+    let out = cmd.output().await.context("ffmpeg loudnorm")?;
+    ensure_success("ffmpeg loudnorm", &out)?;
+
+    measured_from_stderr(&String::from_utf8_lossy(&out.stderr))
+}
+
+/// First pass ffmpeg audio filter, printing the measured values as json.
+fn measure_afilter(pre_filter: Option<&str>) -> String {
+    let loudnorm = format!("loudnorm={TARGET}:print_format=json");
+    match pre_filter {
+        Some(pre) => format!("{pre},{loudnorm}"),
+        None => loudnorm,
+    }
+}
+
+/// Parse the json printed at the end of a `print_format=json` loudnorm pass.
+fn measured_from_stderr(stderr: &str) -> anyhow::Result<Measured> {
+    let start = stderr.rfind('{').context("loudnorm: no json output")?;
+    let end = stderr[start..]
+        .find('}')
+        .context("loudnorm: invalid json output")?;
+    serde_json::from_str(&stderr[start..=start + end]).context("loudnorm: invalid json output")
+}
+
+#[test]
+fn parse_measured() {
+    let stderr = r#"size=N/A time=00:00:30.00 bitrate=N/A speed= 412x
+[Parsed_loudnorm_0 @ 0x61c2d8a3c5c0]
+{
+	"input_i" : "-27.61",
+	"input_tp" : "-4.47",
+	"input_lra" : "18.06",
+	"input_thresh" : "-39.20",
+	"output_i" : "-23.01",
+	"output_tp" : "-1.00",
+	"output_lra" : "7.00",
+	"output_thresh" : "-34.06",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.01"
+}
+"#;
+    let measured = measured_from_stderr(stderr).unwrap();
+    assert_eq!(
+        measured.afilter(),
+        "loudnorm=I=-23:TP=-1:LRA=7:measured_I=-27.61:measured_TP=-4.47:\
+         measured_LRA=18.06:measured_thresh=-39.20:offset=0.01:linear=true,aresample=48000"
+    );
+}
+
+#[test]
+fn measure_after_pre_filter() {
+    assert_eq!(
+        measure_afilter(None),
+        "loudnorm=I=-23:TP=-1:LRA=7:print_format=json"
+    );
+    assert_eq!(
+        measure_afilter(Some("aformat=channel_layouts=stereo")),
+        "aformat=channel_layouts=stereo,loudnorm=I=-23:TP=-1:LRA=7:print_format=json"
+    );
+}
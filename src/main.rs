@@ -1,11 +1,23 @@
 mod command;
+mod config;
 mod console_ext;
+mod cropdetect;
+mod encoder;
 mod ffmpeg;
 mod ffprobe;
 mod float;
+mod frame_report;
+mod idet;
 mod log;
+mod loudnorm;
+mod metric;
+mod native;
+mod noise;
 mod process;
+mod provenance;
 mod sample;
+mod script;
+mod sequence;
 mod temporary;
 mod vmaf;
 mod xpsnr;
@@ -26,6 +38,10 @@ enum Command {
     Encode(command::encode::Args),
     CrfSearch(command::crf_search::Args),
     AutoEncode(command::auto_encode::Args),
+    BatchImages(command::batch_images::Args),
+    Inspect(command::inspect::Args),
+    Encoders(command::encoders::Args),
+    Config(command::config::Args),
     PrintCompletions(command::print_completions::Args),
 }
 
@@ -42,7 +58,7 @@ async fn main() {
         .parse_default_env()
         .init();
 
-    let action = Command::parse();
+    let (action, profile) = config::parse::<Command>();
     let keep = action.keep_temp_files();
 
     let local = tokio::task::LocalSet::new();
@@ -53,6 +69,10 @@ async fn main() {
         Command::Encode(args) => command::encode(args).boxed_local(),
         Command::CrfSearch(args) => command::crf_search(args).boxed_local(),
         Command::AutoEncode(args) => command::auto_encode(args).boxed_local(),
+        Command::BatchImages(args) => command::batch_images(args).boxed_local(),
+        Command::Inspect(args) => command::inspect(args).boxed_local(),
+        Command::Encoders(args) => command::encoders(args).boxed_local(),
+        Command::Config(args) => std::future::ready(command::config(args, profile)).boxed_local(),
         Command::PrintCompletions(args) => return command::print_completions(args),
     });
 
@@ -85,6 +105,7 @@ impl Command {
             Self::SampleEncode(args) => args.sample.keep,
             Self::CrfSearch(args) => args.search.sample.keep,
             Self::AutoEncode(args) => args.search.sample.keep,
+            Self::BatchImages(args) => args.search.sample.keep,
             _ => false,
         }
     }
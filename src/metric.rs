@@ -0,0 +1,503 @@
+//! Score metric kinds & logic for metrics other than VMAF/XPSNR
+use crate::{
+    command::args::PixelFormat,
+    frame_report,
+    process::{Chunks, CommandExt, FfmpegOut, cmd_err, ensure_success, exit_ok_stderr},
+    temporary::{self, TempKind},
+    vmaf::VmafOut,
+    xpsnr::XpsnrOut,
+};
+use anyhow::{Context, anyhow, bail};
+use log::{debug, info};
+use std::{
+    fmt::{self, Display, Write},
+    path::{Path, PathBuf},
+    process::Stdio,
+    thread,
+    time::Duration,
+};
+use tokio::process::Command;
+use tokio_process_stream::{Item, ProcessChunkStream};
+use tokio_stream::{Stream, StreamExt};
+
+/// Frame rate of frames extracted for image metrics, SSIMULACRA2 & Butteraugli.
+const IMAGE_METRIC_FPS: f32 = 1.0;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    clap::ValueEnum,
+)]
+#[clap(rename_all = "lower")]
+pub enum ScoreKind {
+    Vmaf,
+    Xpsnr,
+    Ssim,
+    #[value(name = "ms-ssim")]
+    MsSsim,
+    Psnr,
+    Ssimulacra2,
+    Butteraugli,
+}
+
+impl ScoreKind {
+    /// Display label for fps in progress bar.
+    pub fn fps_label(&self) -> &'static str {
+        match self {
+            Self::Vmaf => "vmaf",
+            Self::Xpsnr => "xpsnr",
+            Self::Ssim => "ssim",
+            Self::MsSsim => "ms-ssim",
+            Self::Psnr => "psnr",
+            Self::Ssimulacra2 => "ssimulacra2",
+            Self::Butteraugli => "butteraugli",
+        }
+    }
+
+    /// General display name.
+    pub fn display_str(&self) -> &'static str {
+        match self {
+            Self::Vmaf => "VMAF",
+            Self::Xpsnr => "XPSNR",
+            Self::Ssim => "SSIM",
+            Self::MsSsim => "MS-SSIM",
+            Self::Psnr => "PSNR",
+            Self::Ssimulacra2 => "SSIMULACRA2",
+            Self::Butteraugli => "Butteraugli",
+        }
+    }
+
+    /// Json field name.
+    pub fn json_key(&self) -> &'static str {
+        match self {
+            Self::MsSsim => "ms_ssim",
+            kind => kind.fps_label(),
+        }
+    }
+
+    /// Decimal places used to display scores.
+    pub fn decimals(&self) -> usize {
+        match self {
+            Self::Ssim | Self::MsSsim => 4,
+            _ => 2,
+        }
+    }
+
+    /// Progress log target.
+    pub fn log_target(&self) -> &'static str {
+        match self {
+            Self::Vmaf => "ab_av1::vmaf",
+            Self::Xpsnr => "ab_av1::xpsnr",
+            _ => "ab_av1::metric",
+        }
+    }
+}
+
+impl Display for ScoreKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.display_str())
+    }
+}
+
+/// Calculate a SSIM, MS-SSIM, PSNR, SSIMULACRA2 or Butteraugli score.
+///
+/// SSIM & PSNR use ffmpeg filters, MS-SSIM the libvmaf `float_ms_ssim` feature.
+/// SSIMULACRA2 & Butteraugli extract frames with ffmpeg, scored by the libjxl
+/// `ssimulacra2` & `butteraugli_main` tools.
+///
+/// `streams` are the video stream indexes of the reference & distorted inputs.
+///
+/// `range` limits scoring to a start & duration of both inputs, using accurate decode seeking.
+/// Image sequence references are read at `ref_fps`, i.e. `--fps`.
+///
+/// Intermediate files, e.g. extracted frames, are written to the `temp_dir` process dir.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    kind: ScoreKind,
+    reference: &Path,
+    ref_fps: Option<f64>,
+    distorted: &Path,
+    streams: (usize, usize),
+    ref_vfilter: Option<&str>,
+    pix_fmt: Option<PixelFormat>,
+    ignore_film_grain: bool,
+    range: Option<(Duration, Duration)>,
+    temp_dir: Option<PathBuf>,
+) -> anyhow::Result<impl Stream<Item = MetricOut> + use<>> {
+    info!(
+        "{} {} vs reference {}",
+        kind.fps_label(),
+        distorted.file_name().and_then(|n| n.to_str()).unwrap_or(""),
+        reference.file_name().and_then(|n| n.to_str()).unwrap_or(""),
+    );
+
+    let mut cmd = Command::new("ffmpeg");
+    cmd.kill_on_drop(true)
+        .stdin(Stdio::null())
+        .arg2_opt("-t", range.map(|(_, duration)| duration.as_secs_f32()))
+        .arg_input(reference, ref_fps, range.map(|(start, _)| start))?
+        // decode without synthesized film grain
+        .arg2_if(ignore_film_grain, "-export_side_data", "film_grain")
+        .arg2_opt("-ss", range.map(|(start, _)| start.as_secs_f32()))
+        .arg2_opt("-t", range.map(|(_, duration)| duration.as_secs_f32()))
+        .arg2("-i", distorted);
+
+    // e.g. "{temp}/vid.av1.crf30.mkv"
+    let temp_file =
+        temporary::process_dir(temp_dir)?.join(distorted.file_name().context("no file name")?);
+    let (ref_stream, dis_stream) = streams;
+    let method = match kind {
+        ScoreKind::Vmaf | ScoreKind::Xpsnr => bail!("{kind} is not scored by metric::run"),
+        ScoreKind::Ssim | ScoreKind::Psnr => {
+            let lavfi = lavfi(kind.fps_label(), ref_vfilter, pix_fmt);
+            cmd.arg2(
+                "-filter_complex",
+                select_streams(&lavfi, ref_stream, dis_stream),
+            )
+            .arg2("-f", "null")
+            .arg("-");
+            Method::Filter
+        }
+        ScoreKind::MsSsim => {
+            let log = temp_file.with_extension("ms-ssim.json");
+            temporary::add(&log, TempKind::NotKeepable);
+            let libvmaf = format!(
+                "libvmaf=shortest=true:ts_sync_mode=nearest:n_threads={}:\
+                 feature=name=float_ms_ssim:log_fmt=json:log_path={}",
+                thread::available_parallelism().map_or(1, |p| p.get()),
+                frame_report::filter_path(&log),
+            );
+            // libvmaf takes the distorted input first
+            let lavfi = dis_ref_lavfi(&libvmaf, ref_vfilter, pix_fmt);
+            cmd.arg2(
+                "-filter_complex",
+                select_streams(&lavfi, ref_stream, dis_stream),
+            )
+            .arg2("-f", "null")
+            .arg("-");
+            Method::VmafLog(log)
+        }
+        ScoreKind::Ssimulacra2 | ScoreKind::Butteraugli => {
+            let dir = temp_file.with_extension(format!("{}-frames", kind.fps_label()));
+            std::fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+            temporary::add(&dir, TempKind::NotKeepable);
+            let ref_vf = ref_vfilter.map(|vf| format!("{vf},")).unwrap_or_default();
+            cmd.arg2(
+                "-filter_complex",
+                format!(
+                    "[0:v:{ref_stream}]{ref_vf}fps={IMAGE_METRIC_FPS}[ref];\
+                     [1:v:{dis_stream}]fps={IMAGE_METRIC_FPS}[dis]"
+                ),
+            )
+            .arg2("-map", "[ref]")
+            .arg(dir.join("ref-%05d.png"))
+            .arg2("-map", "[dis]")
+            .arg(dir.join("dis-%05d.png"));
+            Method::Frames(dir)
+        }
+    };
+    cmd.arg("-an").arg("-sn").arg("-dn");
+
+    let cmd_str = cmd.to_cmd_str();
+    debug!("cmd `{cmd_str}`");
+    let mut ffmpeg = crate::process::child::AddOnDropChunkStream::from(
+        ProcessChunkStream::try_from(cmd).context("ffmpeg metric")?,
+    );
+
+    Ok(async_stream::stream! {
+        let mut chunks = Chunks::default();
+        let mut score = None;
+        while let Some(next) = ffmpeg.next().await {
+            match next {
+                Item::Stderr(chunk) => {
+                    chunks.push(&chunk);
+                    if let Method::Filter = method
+                        && let Some(s) = chunks.rfind_line_map(|l| score_from_line(kind, l))
+                    {
+                        score = Some(s);
+                    } else if let Some(progress) = FfmpegOut::try_parse(chunks.last_line()) {
+                        yield MetricOut::Progress(progress);
+                    }
+                }
+                Item::Stdout(_) => {}
+                Item::Done(code) => {
+                    if let Err(err) = exit_ok_stderr("ffmpeg metric", code, &cmd_str, &chunks) {
+                        yield MetricOut::Err(err);
+                        return;
+                    }
+                }
+            }
+        }
+
+        let score = match method {
+            Method::Filter => score.ok_or_else(|| {
+                cmd_err(format!("could not parse ffmpeg {} score", kind.fps_label()), &cmd_str, &chunks)
+            }),
+            Method::VmafLog(log) => match tokio::fs::read(&log).await {
+                Ok(json) => ms_ssim_from_log(&json),
+                Err(err) => Err(anyhow!("{err}: {}", log.display())),
+            },
+            Method::Frames(dir) => {
+                let score = frames_score(kind, &dir).await;
+                _ = tokio::fs::remove_dir_all(&dir).await;
+                score
+            }
+        };
+        match score {
+            Ok(score) => yield MetricOut::Done(score),
+            Err(err) => yield MetricOut::Err(err),
+        }
+    })
+}
+
+/// How a metric score is produced.
+enum Method {
+    /// Parse the ffmpeg filter output.
+    Filter,
+    /// Read the libvmaf json log.
+    VmafLog(PathBuf),
+    /// Score extracted frames in this dir.
+    Frames(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum MetricOut {
+    Progress(FfmpegOut),
+    Done(f32),
+    Err(anyhow::Error),
+}
+
+impl From<VmafOut> for MetricOut {
+    fn from(out: VmafOut) -> Self {
+        match out {
+            VmafOut::Progress(p) => Self::Progress(p),
+            VmafOut::Done(score) => Self::Done(score),
+            VmafOut::Err(e) => Self::Err(e),
+        }
+    }
+}
+
+impl From<XpsnrOut> for MetricOut {
+    fn from(out: XpsnrOut) -> Self {
+        match out {
+            XpsnrOut::Progress(p) => Self::Progress(p),
+            XpsnrOut::Done(score) => Self::Done(score.min),
+            XpsnrOut::Err(e) => Self::Err(e),
+        }
+    }
+}
+
+/// Returns ffmpeg `filter_complex`/`lavfi` value using `filter` on the reference (`0:v`)
+/// & distorted (`1:v`), after any reference vfilter & pixel format conversion.
+pub fn lavfi(filter: &str, ref_vfilter: Option<&str>, pix_fmt: Option<PixelFormat>) -> String {
+    ordered_lavfi(filter, ref_vfilter, pix_fmt, false)
+}
+
+/// Returns ffmpeg `filter_complex`/`lavfi` value as [`lavfi`] but passing the distorted
+/// first, then the reference, to `filter`.
+fn dis_ref_lavfi(filter: &str, ref_vfilter: Option<&str>, pix_fmt: Option<PixelFormat>) -> String {
+    ordered_lavfi(filter, ref_vfilter, pix_fmt, true)
+}
+
+fn ordered_lavfi(
+    filter: &str,
+    ref_vfilter: Option<&str>,
+    pix_fmt: Option<PixelFormat>,
+    dis_first: bool,
+) -> String {
+    /// Add filter to `lavfi`, if necessary. If no filter added return `old_name`.
+    /// Otherwise return `new_name`.
+    fn add_filter(
+        lavfi: &mut String,
+        old_name: &'static str,
+        new_name: &'static str,
+        vfilter: Option<&str>,
+        pix_fmt: Option<PixelFormat>,
+    ) -> &'static str {
+        if vfilter.is_none() && pix_fmt.is_none() {
+            return old_name;
+        }
+
+        lavfi.push_str(old_name);
+        if let Some(pix_fmt) = pix_fmt {
+            _ = write!(lavfi, "format={pix_fmt}");
+        }
+        if let Some(vf) = vfilter {
+            if pix_fmt.is_some() {
+                lavfi.push(',');
+            }
+            lavfi.push_str(vf);
+        }
+        lavfi.push_str(new_name);
+        lavfi.push(';');
+        new_name
+    }
+
+    let mut lavfi = String::new();
+
+    let ref_stream = add_filter(&mut lavfi, "[0:v]", "[ref]", ref_vfilter, pix_fmt);
+    let dis_stream = add_filter(&mut lavfi, "[1:v]", "[dis]", None, pix_fmt);
+    let (first, second) = match dis_first {
+        true => (dis_stream, ref_stream),
+        false => (ref_stream, dis_stream),
+    };
+    lavfi.push_str(first);
+    lavfi.push_str(second);
+    lavfi.push_str(filter);
+    lavfi
+}
+
+/// Use video stream `first` of input 0 & `second` of input 1 in a score `lavfi`,
+/// instead of the first video streams.
+pub fn select_streams(lavfi: &str, first: usize, second: usize) -> String {
+    lavfi
+        .replace("[0:v]", &format!("[0:v:{first}]"))
+        .replace("[1:v]", &format!("[1:v:{second}]"))
+}
+
+/// Parse the ffmpeg ssim or psnr filter overall score.
+///
+/// E.g.
+/// * "[Parsed_ssim_0 @ 0x5f4c] SSIM Y:0.995 (23.1) U:0.997 (25.7) V:0.997 (25.9) All:0.996 (23.9)"
+/// * "[Parsed_psnr_0 @ 0x5f4c] PSNR y:41.52 u:44.13 v:45.30 average:42.43 min:38.21 max:48.10"
+fn score_from_line(kind: ScoreKind, line: &str) -> Option<f32> {
+    let (label, prefix) = match kind {
+        ScoreKind::Ssim => ("SSIM", "All:"),
+        ScoreKind::Psnr => ("PSNR", "average:"),
+        _ => return None,
+    };
+    if !line.contains(label) {
+        return None;
+    }
+    let idx = line.find(prefix)?;
+    let tail = &line[idx + prefix.len()..];
+    if tail.starts_with("inf") {
+        return Some(f32::INFINITY);
+    }
+    tail.split_whitespace().next()?.parse().ok()
+}
+
+/// Parse the pooled mean MS-SSIM from a libvmaf json log.
+fn ms_ssim_from_log(json: &[u8]) -> anyhow::Result<f32> {
+    let log: serde_json::Value = serde_json::from_slice(json).context("invalid vmaf log")?;
+    log["pooled_metrics"]["float_ms_ssim"]["mean"]
+        .as_f64()
+        .map(|s| s as f32)
+        .context("vmaf log missing float_ms_ssim")
+}
+
+/// Returns the mean score of extracted reference & distorted frame pairs in `dir`.
+async fn frames_score(kind: ScoreKind, dir: &Path) -> anyhow::Result<f32> {
+    let tool = match kind {
+        ScoreKind::Ssimulacra2 => "ssimulacra2",
+        _ => "butteraugli_main",
+    };
+
+    let mut total = 0.0;
+    let mut n = 0;
+    for frame in 1.. {
+        let reference = dir.join(format!("ref-{frame:05}.png"));
+        let distorted = dir.join(format!("dis-{frame:05}.png"));
+        if !reference.exists() || !distorted.exists() {
+            break;
+        }
+        let out = Command::new(tool)
+            .arg(&reference)
+            .arg(&distorted)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .with_context(|| format!("{tool} failed, is it installed?"))?;
+        ensure_success("image metric", &out)?;
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        total += tool_score(&stdout).with_context(|| format!("could not parse {tool} score"))?;
+        n += 1;
+    }
+    anyhow::ensure!(n > 0, "no frames extracted for {}", kind.fps_label());
+    Ok(total / n as f32)
+}
+
+/// Parse the first score output by `ssimulacra2` or `butteraugli_main`.
+///
+/// E.g. butteraugli_main "1.5293\n3-norm: 0.634150\n".
+fn tool_score(stdout: &str) -> Option<f32> {
+    stdout
+        .split_whitespace()
+        .find_map(|token| token.parse::<f32>().ok())
+}
+
+#[test]
+fn parse_ssim_line() {
+    assert_eq!(
+        score_from_line(
+            ScoreKind::Ssim,
+            "[Parsed_ssim_0 @ 0x5f4c] SSIM Y:0.995 (23.1) U:0.997 (25.7) V:0.997 (25.9) All:0.996 (23.9)"
+        ),
+        Some(0.996)
+    );
+    assert_eq!(
+        score_from_line(
+            ScoreKind::Psnr,
+            "[Parsed_psnr_0 @ 0x5f4c] PSNR y:41.52 u:44.13 v:45.30 average:42.43 min:38.21 max:48.10"
+        ),
+        Some(42.43)
+    );
+    assert_eq!(
+        score_from_line(
+            ScoreKind::Psnr,
+            "[Parsed_psnr_0 @ 0x5f4c] PSNR y:inf u:inf v:inf average:inf min:inf max:inf"
+        ),
+        Some(f32::INFINITY)
+    );
+}
+
+#[test]
+fn parse_tool_score() {
+    assert_eq!(tool_score("85.1234\n"), Some(85.1234));
+    assert_eq!(tool_score("1.5293\n3-norm: 0.634150\n"), Some(1.5293));
+}
+
+#[test]
+fn parse_ms_ssim_log() {
+    let log =
+        br#"{"frames": [], "pooled_metrics": {"float_ms_ssim": {"min": 0.98, "mean": 0.991}}}"#;
+    assert_eq!(ms_ssim_from_log(log).unwrap(), 0.991);
+}
+
+#[test]
+fn select_score_streams() {
+    assert_eq!(
+        select_streams("[0:v]format=yuv420p[dis];[1:v]format=yuv420p[ref];", 0, 1),
+        "[0:v:0]format=yuv420p[dis];[1:v:1]format=yuv420p[ref];"
+    );
+}
+
+#[test]
+fn lavfi_ssim() {
+    assert_eq!(lavfi("ssim", None, None), "[0:v][1:v]ssim");
+    assert_eq!(
+        lavfi("psnr", Some("scale=640:-1"), Some(PixelFormat::Yuv420p10le)),
+        "[0:v]format=yuv420p10le,scale=640:-1[ref];\
+         [1:v]format=yuv420p10le[dis];\
+         [ref][dis]psnr"
+    );
+}
+
+#[test]
+fn lavfi_ms_ssim_distorted_first() {
+    assert_eq!(dis_ref_lavfi("libvmaf", None, None), "[1:v][0:v]libvmaf");
+    assert_eq!(
+        dis_ref_lavfi("libvmaf", Some("scale=640:-1"), Some(PixelFormat::Yuv420p)),
+        "[0:v]format=yuv420p,scale=640:-1[ref];\
+         [1:v]format=yuv420p[dis];\
+         [dis][ref]libvmaf"
+    );
+}
@@ -0,0 +1,364 @@
+//! Native encoder backend, piping ffmpeg decoded y4m into a standalone encoder binary,
+//! e.g. SvtAv1EncApp, then muxing the encoded ivf with ffmpeg.
+use crate::{
+    ffmpeg::{EncodeStream, FfmpegEncodeArgs},
+    float::TerseF32,
+    process::{CommandExt, FfmpegOut, exit_ok},
+    temporary::{self, TempKind},
+};
+use anyhow::Context;
+use futures_util::StreamExt;
+use log::debug;
+use std::{
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::process::Command;
+
+/// Standalone encoder app supported by the native backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NativeEncoder {
+    SvtAv1,
+    Aom,
+    Rav1e,
+}
+
+impl NativeEncoder {
+    /// Default binary name.
+    pub fn bin(self) -> &'static str {
+        match self {
+            Self::SvtAv1 => "SvtAv1EncApp",
+            Self::Aom => "aomenc",
+            Self::Rav1e => "rav1e",
+        }
+    }
+
+    /// Args to read y4m from stdin & set crf & preset.
+    fn rate_args(self, crf: f32, preset: Option<&str>, high_bit_depth: bool) -> Vec<String> {
+        let crf = TerseF32(crf);
+        let mut args: Vec<String> = match self {
+            Self::SvtAv1 => vec!["-i".into(), "stdin".into(), "--crf".into(), crf.to_string()],
+            // stdin input only supports single pass
+            Self::Aom => vec![
+                "-".into(),
+                "--passes=1".into(),
+                "--end-usage=q".into(),
+                format!("--cq-level={crf}"),
+            ],
+            Self::Rav1e => vec!["-".into(), "--quantizer".into(), crf.to_string()],
+        };
+        if let Some(preset) = preset {
+            match self {
+                Self::SvtAv1 => args.extend(["--preset".into(), preset.into()]),
+                Self::Aom => args.push(format!("--cpu-used={preset}")),
+                Self::Rav1e => args.extend(["--speed".into(), preset.into()]),
+            }
+        }
+        if self == Self::Aom && high_bit_depth {
+            args.push("--bit-depth=10".into());
+        }
+        args
+    }
+
+    /// Args to set the keyframe interval.
+    pub fn keyint_args(self, keyint: i32) -> Vec<String> {
+        match self {
+            Self::SvtAv1 => vec!["--keyint".into(), keyint.to_string()],
+            Self::Aom => vec![format!("--kf-max-dist={keyint}")],
+            Self::Rav1e => vec!["--keyint".into(), keyint.to_string()],
+        }
+    }
+
+    fn output_args(self, ivf: &Path) -> Vec<String> {
+        let ivf = ivf.to_string_lossy().into_owned();
+        match self {
+            Self::SvtAv1 => vec!["-b".into(), ivf],
+            Self::Aom => vec!["--ivf".into(), "-o".into(), ivf],
+            Self::Rav1e => vec!["-o".into(), ivf],
+        }
+    }
+
+    /// Parse encoder progress output, e.g. svt-av1 `Encoding frame  412 1279.52 kbps 20.56 fps`.
+    ///
+    /// `time` is not set as the encoders only report frames.
+    fn parse_progress(self, line: &str) -> Option<FfmpegOut> {
+        let (frame, fps) = match self {
+            Self::SvtAv1 => {
+                let mut words = line
+                    .trim()
+                    .strip_prefix("Encoding frame")?
+                    .split_whitespace();
+                let frame = words.next()?.parse().ok()?;
+                let fps = words.skip_while(|w| *w != "kbps").nth(1)?.parse().ok()?;
+                (frame, fps)
+            }
+            // Pass 1/1 frame   50/49     25030B    4004b/f  120125b/s   10240 us (4.88 fps)
+            Self::Aom => {
+                let line = line.trim();
+                let (_, frames) = line.split_once(" frame ")?;
+                let frame = frames.trim_start().split('/').next()?.parse().ok()?;
+                let (_, fps) = line.rsplit_once('(')?;
+                let fps = fps.strip_suffix("fps)")?.trim().parse().ok()?;
+                (frame, fps)
+            }
+            // encoded 30 frames, 12.345 fps, 1234.56 Kb/s, est. size: 1.23 MB, est. time: 4 s
+            Self::Rav1e => {
+                let mut parts = line.trim().strip_prefix("encoded ")?.split(", ");
+                let frame = parts.next()?.strip_suffix(" frames")?.parse().ok()?;
+                let fps = parts.next()?.strip_suffix(" fps")?.parse().ok()?;
+                (frame, fps)
+            }
+        };
+        Some(FfmpegOut::Progress {
+            frame,
+            fps,
+            time: Duration::ZERO,
+        })
+    }
+
+    fn version_arg(self) -> &'static str {
+        match self {
+            // aomenc has no --version, but lists the library version in --help
+            Self::Aom => "--help",
+            Self::SvtAv1 | Self::Rav1e => "--version",
+        }
+    }
+
+    /// Returns the version line from the output of [`Self::version_arg`].
+    fn parse_version(self, out: &str) -> Option<&str> {
+        let mut lines = out.lines().map(str::trim).filter(|l| !l.is_empty());
+        match self {
+            Self::Aom => lines.find(|l| l.contains("AOMedia Project AV1 Encoder")),
+            Self::SvtAv1 | Self::Rav1e => lines.next(),
+        }
+    }
+}
+
+/// Native backend encoder binary & args.
+#[derive(Debug, Clone)]
+pub struct Native {
+    pub encoder: NativeEncoder,
+    pub bin: Arc<str>,
+    /// Additional encoder args, excluding input, output, crf & preset.
+    pub args: Vec<Arc<String>>,
+    /// Frame rate of the decoded video, used to convert encoded frames into progress time.
+    pub fps: f64,
+    /// Encoder binary version, e.g. "SVT-AV1 v3.0.2 (release)". Empty if unknown.
+    pub version: Arc<str>,
+}
+
+impl Native {
+    pub fn sample_encode_hash(&self, state: &mut impl Hasher) {
+        // hashing the version means new encoder releases will avoid old cache data
+        self.version.hash(state);
+        self.encoder.hash(state);
+        self.args.hash(state);
+    }
+
+    /// Encoder args excluding input & output, e.g. for metadata.
+    pub fn encode_args(&self, crf: f32, preset: Option<&str>, high_bit_depth: bool) -> Vec<String> {
+        let mut args = self.encoder.rate_args(crf, preset, high_bit_depth);
+        args.extend(self.args.iter().map(|a| a.to_string()));
+        args
+    }
+}
+
+/// Returns the `bin` version, e.g. "SVT-AV1 v3.0.2 (release)". Empty if unknown.
+pub async fn read_version(encoder: NativeEncoder, bin: &str) -> String {
+    let out = Command::new(bin)
+        .arg(encoder.version_arg())
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .with_context(|| format!("{bin} {}", encoder.version_arg()));
+    let version = out.and_then(|out| {
+        let out = [out.stdout, out.stderr].concat();
+        let out = String::from_utf8_lossy(&out);
+        encoder
+            .parse_version(&out)
+            .map(Into::into)
+            .context("no version found")
+    });
+    version
+        .inspect_err(|e| debug!("{bin} version: {e}"))
+        .unwrap_or_default()
+}
+
+/// Encode the video to ivf `dest`, decoding to y4m with ffmpeg piped into the native encoder.
+///
+/// Progress `time` is derived from the encoded frames.
+pub fn encode_video(
+    args: &FfmpegEncodeArgs,
+    native: &Native,
+    map_video: bool,
+    dest: &Path,
+) -> anyhow::Result<EncodeStream> {
+    let mut decode = Command::new("ffmpeg");
+    decode
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .arg("-hide_banner")
+        .arg2("-loglevel", "error")
+        .arg("-nostats")
+        .args(args.input_args.iter().map(|a| &**a))
+        .arg_input(args.input, args.input_fps, None)?
+        .arg2_if(map_video, "-map", format!("0:v:{}", args.video_stream))
+        // Avoid dropping or duplicating frames as this may negatively affect input/output analysis
+        .arg2("-fps_mode", "passthrough")
+        .arg2_opt("-pix_fmt", args.pix_fmt.map(|v| v.as_str()))
+        .arg2_opt("-vf", args.vfilter)
+        // allow high bit depth y4m
+        .arg2("-strict", "-1")
+        .arg2("-f", "yuv4mpegpipe")
+        .arg("-")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    debug!("cmd `{}`", decode.to_cmd_str());
+    let mut decoder = decode.spawn().context("ffmpeg y4m decode")?;
+    let y4m: Stdio = decoder
+        .stdout
+        .take()
+        .context("ffmpeg y4m decode stdout")?
+        .try_into()?;
+
+    let high_bit_depth = args.pix_fmt.is_some_and(|p| p.as_str().contains("10"));
+    let mut cmd = Command::new(&*native.bin);
+    cmd.kill_on_drop(true)
+        .args(native.encode_args(args.crf, args.preset.as_deref(), high_bit_depth))
+        .args(native.encoder.output_args(dest))
+        .stdin(y4m)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let cmd_str = cmd.to_cmd_str();
+    debug!("cmd `{cmd_str}`");
+    let enc = cmd
+        .spawn()
+        .with_context(|| format!("native encode {}", native.bin))?;
+
+    let encoder = native.encoder;
+    let fps = native.fps;
+    let mut enc = FfmpegOut::stream_with(enc, "native encode", cmd_str, move |line| {
+        encoder.parse_progress(line)
+    });
+    Ok(async_stream::try_stream! {
+        while let Some(out) = enc.next().await {
+            if let FfmpegOut::Progress { frame, fps: enc_fps, .. } = out? {
+                yield FfmpegOut::Progress {
+                    frame,
+                    fps: enc_fps,
+                    time: Duration::from_secs_f64(frame as f64 / fps),
+                };
+            }
+        }
+        exit_ok("ffmpeg y4m decode", decoder.wait().await)?;
+    }
+    .boxed_local())
+}
+
+/// Encode a sample to `dest`, encoding to an intermediate ivf then remuxing.
+pub fn encode_sample(
+    args: &FfmpegEncodeArgs,
+    native: &Native,
+    dest: PathBuf,
+) -> anyhow::Result<EncodeStream> {
+    let ivf = dest.with_extension("ivf");
+    temporary::add(&ivf, TempKind::NotKeepable);
+    let mut video = encode_video(args, native, false, &ivf)?;
+
+    Ok(async_stream::try_stream! {
+        while let Some(out) = video.next().await {
+            yield out?;
+        }
+
+        let mut cmd = Command::new("ffmpeg");
+        cmd.kill_on_drop(true)
+            .arg("-y")
+            .arg2("-i", &ivf)
+            .arg2("-c", "copy")
+            .arg(&dest)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        let cmd_str = cmd.to_cmd_str();
+        debug!("cmd `{cmd_str}`");
+        let mux = cmd.spawn().context("ffmpeg native mux")?;
+        let mut mux = FfmpegOut::stream(mux, "ffmpeg native mux", cmd_str);
+        while let Some(out) = mux.next().await {
+            out?;
+        }
+        _ = tokio::fs::remove_file(&ivf).await;
+    }
+    .boxed_local())
+}
+
+/// Encode the video to `ivf` then run the `mux` command, which reads it.
+///
+/// Mux progress is not yielded, as the video encode progress already covered the duration.
+pub fn encode_then_mux(video: EncodeStream, mut mux: Command, ivf: PathBuf) -> EncodeStream {
+    let mut video = video;
+    async_stream::try_stream! {
+        while let Some(out) = video.next().await {
+            yield out?;
+        }
+
+        let cmd_str = mux.to_cmd_str();
+        debug!("cmd `{cmd_str}`");
+        let mux = mux.spawn().context("ffmpeg encode")?;
+        let mut mux = FfmpegOut::stream(mux, "ffmpeg encode", cmd_str);
+        while let Some(out) = mux.next().await {
+            if let out @ FfmpegOut::StreamSizes { .. } = out? {
+                yield out;
+            }
+        }
+        _ = tokio::fs::remove_file(&ivf).await;
+    }
+    .boxed_local()
+}
+
+#[test]
+fn parse_native_progress() {
+    let progress = |encoder: NativeEncoder, line| match encoder.parse_progress(line) {
+        Some(FfmpegOut::Progress { frame, fps, .. }) => Some((frame, fps)),
+        _ => None,
+    };
+    assert_eq!(
+        progress(
+            NativeEncoder::SvtAv1,
+            "\rEncoding frame  412 1279.52 kbps 20.56 fps"
+        ),
+        Some((412, 20.56))
+    );
+    assert_eq!(
+        progress(
+            NativeEncoder::Aom,
+            "Pass 1/1 frame   50/49     25030B    4004b/f  120125b/s   10240 us (4.88 fps)"
+        ),
+        Some((50, 4.88))
+    );
+    assert_eq!(
+        progress(
+            NativeEncoder::Rav1e,
+            "encoded 30 frames, 12.345 fps, 1234.56 Kb/s, est. size: 1.23 MB, est. time: 4 s"
+        ),
+        Some((30, 12.345))
+    );
+    assert_eq!(progress(NativeEncoder::SvtAv1, "Svt[info]: -----"), None);
+}
+
+#[test]
+fn parse_native_version() {
+    assert_eq!(
+        NativeEncoder::SvtAv1.parse_version("SVT-AV1 v3.0.2 (release)\n"),
+        Some("SVT-AV1 v3.0.2 (release)")
+    );
+    let aom_help = "Usage: aomenc <options> -o dst_filename src_filename\n\n\
+        Included encoders:\n\n    \
+        av1    - AOMedia Project AV1 Encoder 3.8.0 (default)\n";
+    assert_eq!(
+        NativeEncoder::Aom.parse_version(aom_help),
+        Some("av1    - AOMedia Project AV1 Encoder 3.8.0 (default)")
+    );
+}
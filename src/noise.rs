@@ -0,0 +1,148 @@
+//! Source noise estimation logic
+use crate::{
+    ffprobe::Ffprobe,
+    process::{CommandExt, ensure_success},
+    sample,
+};
+use anyhow::Context;
+use log::{debug, info};
+use std::{path::Path, process::Stdio, time::Duration};
+use tokio::process::Command;
+
+/// Number of points across the input to estimate noise at.
+const POSITIONS: u64 = 6;
+/// Frames analysed at each point.
+const POSITION_FRAMES: u32 = 24;
+/// Duration used to spread the positions, roughly covers `POSITION_FRAMES`.
+const POSITION_DURATION: Duration = Duration::from_secs(1);
+
+/// Denoiser used to estimate noise & to produce a grain free reference for scoring.
+pub const DENOISE_VFILTER: &str = "hqdn3d";
+
+/// Max svt-av1 film-grain level chosen by [`film_grain_level`].
+const MAX_FILM_GRAIN: u8 = 24;
+
+/// Estimate source noise as the mean luma PSNR of the input vs a denoised copy
+/// of itself at several points across the input.
+///
+/// Lower values mean noisier sources. `vfilter` is applied before measuring.
+/// Image sequences are read at `fps`.
+pub async fn estimate(
+    input: &Path,
+    fps: Option<f64>,
+    probe: &Ffprobe,
+    vfilter: Option<&str>,
+) -> anyhow::Result<f32> {
+    let duration = probe.duration.clone().unwrap_or_default();
+    let positions = match probe.is_image || duration <= POSITION_DURATION {
+        true => 1,
+        false => POSITIONS,
+    };
+    info!(
+        "estimating noise {} at {positions} positions",
+        input.file_name().and_then(|n| n.to_str()).unwrap_or(""),
+    );
+
+    let mut psnrs = Vec::with_capacity(positions as _);
+    for idx in 0..positions {
+        let start = match positions {
+            1 => Duration::ZERO,
+            _ => sample::start(idx, positions, POSITION_DURATION, duration),
+        };
+        if let Some(psnr) = estimate_at(input, fps, probe.video_stream, start, vfilter).await? {
+            psnrs.push(psnr);
+        }
+    }
+    anyhow::ensure!(!psnrs.is_empty(), "noise estimation: no psnr found");
+
+    Ok(psnrs.iter().sum::<f32>() / psnrs.len() as f32)
+}
+
+async fn estimate_at(
+    input: &Path,
+    fps: Option<f64>,
+    video_stream: usize,
+    start: Duration,
+    vfilter: Option<&str>,
+) -> anyhow::Result<Option<f32>> {
+    let vf = vfilter.map(|vf| format!("{vf},")).unwrap_or_default();
+    let mut cmd = Command::new("ffmpeg");
+    cmd.kill_on_drop(true)
+        .stdin(Stdio::null())
+        .arg_input(input, fps, Some(start))?
+        .arg2(
+            "-filter_complex",
+            format!(
+                "[0:v:{video_stream}]{vf}split[src][raw];[raw]{DENOISE_VFILTER}[dn];[src][dn]psnr"
+            ),
+        )
+        .arg2("-frames:v", POSITION_FRAMES)
+        .arg("-an")
+        .arg("-sn")
+        .arg("-dn")
+        .arg2("-f", "null")
+        .arg("-");
+    debug!("cmd `{}`", cmd.to_cmd_str());
+
+    let out = cmd.output().await.context("ffmpeg noise estimate")?;
+    ensure_success("ffmpeg noise estimate", &out)?;
+
+    Ok(String::from_utf8_lossy(&out.stderr)
+        .lines()
+        .rev()
+        .find_map(psnr_y_from_line))
+}
+
+// E.g. "[Parsed_psnr_3 @ 0x6c1f] PSNR y:41.39 u:46.775036 v:47.058470 average:42.614232 min:40.125410 max:43.990002"
+fn psnr_y_from_line(line: &str) -> Option<f32> {
+    if !line.contains("PSNR y:") {
+        return None;
+    }
+    let tail = line.split_once("PSNR y:")?.1;
+    match tail.split_whitespace().next()? {
+        "inf" => Some(f32::INFINITY),
+        y => y.parse().ok(),
+    }
+}
+
+/// Choose a svt-av1 film-grain level for a source with the given [`estimate`].
+///
+/// Clean sources (luma psnr vs denoised >= 46) get no grain. Noisier sources scale
+/// linearly up to [`MAX_FILM_GRAIN`] at psnr 32 & below. Levels under 4 have little
+/// visible effect so are rounded down to 0.
+pub fn film_grain_level(noise_psnr: f32) -> u8 {
+    const CLEAN: f32 = 46.0;
+    const NOISY: f32 = 32.0;
+
+    let noisiness = ((CLEAN - noise_psnr) / (CLEAN - NOISY)).clamp(0.0, 1.0);
+    match (noisiness * MAX_FILM_GRAIN as f32).round() as u8 {
+        level if level < 4 => 0,
+        level => level,
+    }
+}
+
+#[test]
+fn parse_psnr_line() {
+    let psnr = psnr_y_from_line(
+        "[Parsed_psnr_3 @ 0x6c1f2a3b4c00] PSNR y:41.39 u:46.775036 v:47.058470 average:42.614232 min:40.125410 max:43.990002",
+    );
+    assert_eq!(psnr, Some(41.39));
+}
+
+#[test]
+fn parse_psnr_line_inf() {
+    let psnr = psnr_y_from_line(
+        "[Parsed_psnr_3 @ 0x6c1f2a3b4c00] PSNR y:inf u:inf v:inf average:inf min:inf max:inf",
+    );
+    assert_eq!(psnr, Some(f32::INFINITY));
+}
+
+#[test]
+fn film_grain_levels() {
+    assert_eq!(film_grain_level(f32::INFINITY), 0);
+    assert_eq!(film_grain_level(50.0), 0);
+    assert_eq!(film_grain_level(44.0), 0);
+    assert_eq!(film_grain_level(39.0), 12);
+    assert_eq!(film_grain_level(32.0), MAX_FILM_GRAIN);
+    assert_eq!(film_grain_level(20.0), MAX_FILM_GRAIN);
+}
@@ -1,11 +1,13 @@
 pub mod child;
 
+use crate::{script, sequence};
 use anyhow::{anyhow, ensure};
 use std::{
     borrow::Cow,
     ffi::OsStr,
     fmt::Display,
     io,
+    path::Path,
     pin::Pin,
     process::{ExitStatus, Output},
     sync::Arc,
@@ -108,11 +110,22 @@ impl FfmpegOut {
     }
 
     pub fn stream(child: Child, name: &'static str, cmd_str: String) -> FfmpegOutStream {
+        Self::stream_with(child, name, cmd_str, Self::try_parse)
+    }
+
+    /// Like [`Self::stream`] using a custom output line parser, e.g. for native encoders.
+    pub fn stream_with(
+        child: Child,
+        name: &'static str,
+        cmd_str: String,
+        parse: impl Fn(&str) -> Option<Self> + Send + 'static,
+    ) -> FfmpegOutStream {
         FfmpegOutStream {
             chunk_stream: ProcessChunkStream::from(child),
             chunks: <_>::default(),
             name,
             cmd_str,
+            parse: Box::new(parse),
         }
     }
 }
@@ -233,15 +246,7 @@ pin_project_lite::pin_project! {
         name: &'static str,
         cmd_str: String,
         chunks: Chunks,
-    }
-}
-
-impl FfmpegOutStream {
-    pub async fn wait(&mut self) -> io::Result<ExitStatus> {
-        match self.chunk_stream.child_mut() {
-            Some(c) => c.wait().await,
-            None => Ok(<_>::default()),
-        }
+        parse: Box<dyn Fn(&str) -> Option<FfmpegOut> + Send>,
     }
 }
 
@@ -254,7 +259,7 @@ impl Stream for FfmpegOutStream {
                 Some(item) => match item {
                     Item::Stderr(chunk) => {
                         self.chunks.push(&chunk);
-                        if let Some(out) = FfmpegOut::try_parse(self.chunks.last_line()) {
+                        if let Some(out) = (self.parse)(self.chunks.last_line()) {
                             return Poll::Ready(Some(Ok(out)));
                         }
                     }
@@ -336,6 +341,21 @@ pub trait CommandExt {
     /// Adds an argument if `condition` otherwise noop.
     fn arg_if(&mut self, condition: bool, a: impl ArgString) -> &mut Self;
 
+    /// Adds ffmpeg input args `-ss start -i input`.
+    ///
+    /// VapourSynth & AviSynth script inputs are instead rendered from `start` as y4m
+    /// piped into stdin, so only one script input is supported per command.
+    /// Stdin should be set before calling this.
+    ///
+    /// Image sequences are read with the image2 demuxer at `fps`, i.e. `--fps`,
+    /// seeking by frame number.
+    fn arg_input(
+        &mut self,
+        input: &Path,
+        fps: Option<f64>,
+        start: Option<Duration>,
+    ) -> anyhow::Result<&mut Self>;
+
     /// Convert to readable shell-like string.
     fn to_cmd_str(&self) -> String;
 }
@@ -365,6 +385,29 @@ impl CommandExt for tokio::process::Command {
         }
     }
 
+    fn arg_input(
+        &mut self,
+        input: &Path,
+        fps: Option<f64>,
+        start: Option<Duration>,
+    ) -> anyhow::Result<&mut Self> {
+        if sequence::is_sequence(input) {
+            return Ok(self.args(sequence::input_args(input, fps, start)?));
+        }
+        if !script::is_script(input) {
+            return Ok(self
+                .arg2_opt("-ss", start.map(|s| s.as_secs_f32()))
+                .arg2("-i", input));
+        }
+        let args: Vec<_> = self.as_std().get_args().collect();
+        ensure!(
+            !args.windows(2).any(|w| w[0] == "-i" && w[1] == "-"),
+            "only one script input is supported"
+        );
+        let y4m = script::spawn_y4m(input, start)?;
+        Ok(self.arg2("-f", "yuv4mpegpipe").arg2("-i", "-").stdin(y4m))
+    }
+
     fn to_cmd_str(&self) -> String {
         let cmd = self.as_std();
         cmd.get_args().map(|a| a.to_string_lossy()).fold(
@@ -0,0 +1,102 @@
+//! Encode provenance stored in output `AB_AV1_SETTINGS` metadata.
+use serde::{Deserialize, Serialize};
+
+/// Output metadata tag containing the json [`Provenance`].
+pub const METADATA_TAG: &str = "AB_AV1_SETTINGS";
+
+/// Encode settings & how they were chosen, enough to reproduce the encode.
+///
+/// Missing fields are defaulted when reading.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Provenance {
+    /// ab-av1 version.
+    pub version: String,
+    pub vcodec: String,
+    pub crf: f32,
+    pub preset: Option<String>,
+    pub pix_fmt: Option<String>,
+    pub vfilter: Option<String>,
+    pub input_args: Vec<String>,
+    pub output_args: Vec<String>,
+    /// Encoder library version, if known. E.g. "3.0.2" for libsvtav1.
+    pub encoder_version: Option<String>,
+    /// ab-av1 command reproducing the encode.
+    pub command: Option<String>,
+    /// crf-search result, if the crf was chosen by auto-encode.
+    pub search: Option<SearchResult>,
+}
+
+/// crf-search target & the result for the chosen crf.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult {
+    /// Each min score & the chosen crf score, the main min score first.
+    pub scores: Vec<SearchScore>,
+    pub max_encoded_percent: f32,
+    /// Predicted video stream size.
+    pub predicted_size: u64,
+    /// Predicted video stream size as a percentage of the input.
+    pub predicted_percent: f64,
+    /// auto-encode `--no-good-crf` fallbacks used, e.g. "relax min VMAF 94.5".
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+}
+
+/// A crf-search min score & the score of the chosen crf.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchScore {
+    /// Score label, e.g. "VMAF" or "VMAF p5".
+    pub kind: String,
+    pub min: f32,
+    /// Sample score of the chosen crf.
+    pub score: f32,
+}
+
+impl Provenance {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("provenance json")
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+#[test]
+fn provenance_json() {
+    let provenance = Provenance {
+        version: "0.11.7".into(),
+        vcodec: "libsvtav1".into(),
+        crf: 32.0,
+        preset: Some("6".into()),
+        encoder_version: Some("3.0.2".into()),
+        command: Some("ab-av1 encode -i vid.mkv --crf 32 --preset 6".into()),
+        search: Some(SearchResult {
+            scores: vec![
+                SearchScore {
+                    kind: "VMAF".into(),
+                    min: 95.0,
+                    score: 95.2,
+                },
+                SearchScore {
+                    kind: "XPSNR".into(),
+                    min: 42.0,
+                    score: 42.8,
+                },
+            ],
+            max_encoded_percent: 80.0,
+            predicted_size: 123_456_789,
+            predicted_percent: 31.5,
+            fallbacks: vec!["relax min VMAF 94.5".into()],
+        }),
+        ..<_>::default()
+    };
+    let read = Provenance::from_json(&provenance.to_json()).unwrap();
+    assert_eq!(read, provenance);
+
+    // missing fields use defaults
+    let partial = Provenance::from_json(r#"{"vcodec":"libsvtav1","crf":30.0}"#).unwrap();
+    assert_eq!(partial.crf, 30.0);
+    assert_eq!(partial.command, None);
+    assert_eq!(partial.search, None);
+}
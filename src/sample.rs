@@ -1,24 +1,92 @@
 //! ffmpeg logic
 use crate::{
     process::{CommandExt, ensure_success},
+    script, sequence,
     temporary::{self, TempKind},
 };
 use anyhow::Context;
 use std::{
+    fmt,
     path::{Path, PathBuf},
     process::Stdio,
     time::Duration,
 };
 use tokio::process::Command;
 
-/// Create a sample from `sample_start` + `frames`.
+/// Returns the start of sample `sample_idx` of `samples`, each `sample_duration` long,
+/// evenly spread across an input of `duration`.
+pub fn start(
+    sample_idx: u64,
+    samples: u64,
+    sample_duration: Duration,
+    duration: Duration,
+) -> Duration {
+    let sample_n = sample_idx + 1;
+    (duration.saturating_sub(sample_duration * samples as _) / (samples as u32 + 1)) * sample_n as _
+        + sample_duration * sample_idx as _
+}
+
+/// Returns the start of sample `sample_idx` of `samples` placed between the samples of
+/// [`start`], in the middle of the gap before each. So these positions are distinct from
+/// those used by a search with the same number of samples.
+pub fn between_start(
+    sample_idx: u64,
+    samples: u64,
+    sample_duration: Duration,
+    duration: Duration,
+) -> Duration {
+    let gap = duration.saturating_sub(sample_duration * samples as _) / (samples as u32 + 1);
+    (gap + sample_duration) * sample_idx as _ + gap.saturating_sub(sample_duration) / 2
+}
+
+/// Length of a sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleLen {
+    /// Cut by frame count, accurate for constant frame rate inputs.
+    Frames(u32),
+    /// Cut by time, for variable frame rate inputs where a frame count
+    /// derived from the average fps would misrepresent the duration.
+    Time(Duration),
+}
+
+impl SampleLen {
+    /// Returns the ffmpeg output arg limiting the sample to this length.
+    fn ffmpeg_arg(self) -> (&'static str, String) {
+        match self {
+            Self::Frames(frames) => ("-frames:v", frames.to_string()),
+            Self::Time(duration) => ("-t", duration.as_secs_f32().to_string()),
+        }
+    }
+
+    /// Returns the number of frames at `fps`.
+    fn frames(self, fps: f64) -> u32 {
+        match self {
+            Self::Frames(frames) => frames,
+            Self::Time(duration) => ((duration.as_secs_f64() * fps).round() as u32).max(1),
+        }
+    }
+}
+
+impl fmt::Display for SampleLen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Frames(frames) => write!(f, "{frames}f"),
+            Self::Time(duration) => write!(f, "{}s", duration.as_secs_f32()),
+        }
+    }
+}
+
+/// Create a sample of input video stream `0:v:{video_stream}` from `sample_start` + `len`.
 ///
-/// Fast as this uses `-c:v copy`.
+/// Fast as this uses `-c:v copy`. Script inputs are instead trimmed inside a new script
+/// & image sequences are sampled by frame range at `fps` into a new sequence directory.
 pub async fn copy(
     input: &Path,
+    fps: Option<f64>,
+    video_stream: usize,
     sample_start: Duration,
     floor_to_sec: bool,
-    frames: u32,
+    len: SampleLen,
     temp_dir: Option<PathBuf>,
 ) -> anyhow::Result<PathBuf> {
     let mut sample_start_s = sample_start.as_secs_f32();
@@ -27,11 +95,25 @@ pub async fn copy(
     }
 
     let mut dest = temporary::process_dir(temp_dir)?;
+
+    if script::is_script(input) {
+        let fps = script::info(input)?.fps;
+        let start_frame = (f64::from(sample_start_s) * fps).round() as u64;
+        let sample = script::trim(input, start_frame, len.frames(fps), &dest)?;
+        temporary::add(&sample, TempKind::Keepable);
+        return Ok(sample);
+    }
+    if sequence::is_sequence(input) {
+        let fps = sequence::fps(fps)?;
+        let start_frame = (f64::from(sample_start_s) * fps).round() as u64;
+        return sequence::trim(input, start_frame, len.frames(fps), &dest);
+    }
+
     // Always using mkv for the samples works better than, e.g. using mp4 for mp4s
     // see https://github.com/alexheretic/ab-av1/issues/82#issuecomment-1337306325
     dest.push(
         input
-            .with_extension(format!("sample{sample_start_s}+{frames}f.mkv"))
+            .with_extension(format!("sample{sample_start_s}+{len}.mkv"))
             .file_name()
             .unwrap(),
     );
@@ -40,13 +122,15 @@ pub async fn copy(
     }
     temporary::add(&dest, TempKind::Keepable);
 
-    // Note: `-ss` before `-i` & `-frames:v` instead of `-t`
+    // Note: `-ss` before `-i` & `-frames:v` instead of `-t`, except for vfr
     // See https://github.com/alexheretic/ab-av1/issues/36#issuecomment-1146634936
+    let (len_arg, len_val) = len.ffmpeg_arg();
     let mut out = Command::new("ffmpeg")
         .arg("-y")
         .arg2("-ss", sample_start_s)
         .arg2("-i", input)
-        .arg2("-frames:v", frames)
+        .arg2("-map", format!("0:v:{video_stream}"))
+        .arg2(len_arg, &len_val)
         .arg2("-c:v", "copy")
         .arg("-an")
         .arg("-sn")
@@ -66,7 +150,8 @@ pub async fn copy(
             .arg2("-fflags", "+genpts")
             .arg2("-ss", sample_start_s)
             .arg2("-i", input)
-            .arg2("-frames:v", frames)
+            .arg2("-map", format!("0:v:{video_stream}"))
+            .arg2(len_arg, &len_val)
             .arg2("-c:v", "copy")
             .arg("-an")
             .arg("-sn")
@@ -80,3 +165,93 @@ pub async fn copy(
     ensure_success("ffmpeg copy", &out)?;
     Ok(dest)
 }
+
+/// Create a sample of input video stream `0:v:{video_stream}` from `sample_start` + `len`
+/// by losslessly re-encoding with ffv1.
+///
+/// Slower than [`copy`], but cuts exactly at `sample_start` rather than the keyframe before,
+/// so works for inputs with sparse keyframes.
+pub async fn lossless(
+    input: &Path,
+    video_stream: usize,
+    sample_start: Duration,
+    floor_to_sec: bool,
+    len: SampleLen,
+    temp_dir: Option<PathBuf>,
+) -> anyhow::Result<PathBuf> {
+    let mut sample_start_s = sample_start.as_secs_f32();
+    if floor_to_sec {
+        sample_start_s = sample_start_s.floor();
+    }
+
+    let mut dest = temporary::process_dir(temp_dir)?;
+    dest.push(
+        input
+            .with_extension(format!("sample{sample_start_s}+{len}.lossless.mkv"))
+            .file_name()
+            .unwrap(),
+    );
+    if dest.exists() {
+        return Ok(dest);
+    }
+    temporary::add(&dest, TempKind::Keepable);
+
+    // decoding `-ss` before `-i` seeks accurately, discarding frames before the start
+    let (len_arg, len_val) = len.ffmpeg_arg();
+    let out = Command::new("ffmpeg")
+        .arg("-y")
+        .arg2("-ss", sample_start_s)
+        .arg2("-i", input)
+        .arg2("-map", format!("0:v:{video_stream}"))
+        .arg2(len_arg, &len_val)
+        .arg2("-c:v", "ffv1")
+        .arg2("-level", "3")
+        .arg("-an")
+        .arg("-sn")
+        .arg("-dn")
+        .arg(&dest)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("ffmpeg lossless sample")?;
+
+    ensure_success("ffmpeg lossless sample", &out)?;
+    Ok(dest)
+}
+
+#[test]
+fn between_start_distinct() {
+    let sample_duration = Duration::from_secs(20);
+    let duration = Duration::from_secs(3600);
+    for idx in 0..3 {
+        let search = start(idx, 3, sample_duration, duration);
+        let between = between_start(idx, 3, sample_duration, duration);
+        assert!(
+            between + sample_duration <= search,
+            "{between:?} {search:?}"
+        );
+        if idx > 0 {
+            assert!(between >= start(idx - 1, 3, sample_duration, duration) + sample_duration);
+        }
+    }
+    assert_eq!(
+        between_start(0, 3, sample_duration, duration),
+        Duration::from_millis(432_500)
+    );
+}
+
+#[test]
+fn sample_len() {
+    assert_eq!(SampleLen::Frames(48).to_string(), "48f");
+    assert_eq!(SampleLen::Time(Duration::from_secs(2)).to_string(), "2s");
+    assert_eq!(
+        SampleLen::Time(Duration::from_millis(1500)).ffmpeg_arg(),
+        ("-t", "1.5".into())
+    );
+    assert_eq!(
+        SampleLen::Frames(48).ffmpeg_arg(),
+        ("-frames:v", "48".into())
+    );
+    assert_eq!(SampleLen::Time(Duration::from_secs(2)).frames(29.97), 60);
+    assert_eq!(SampleLen::Frames(48).frames(29.97), 48);
+}
@@ -0,0 +1,374 @@
+//! VapourSynth `.vpy` & AviSynth `.avs` script input support.
+//!
+//! Scripts are rendered by `vspipe`/`avs2yuv` as y4m piped into ffmpeg.
+use crate::ffprobe::{Ffprobe, Stream, StreamKind};
+use anyhow::{Context, ensure};
+use log::debug;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptKind {
+    VapourSynth,
+    AviSynth,
+}
+
+impl ScriptKind {
+    pub fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "vpy" => Some(Self::VapourSynth),
+            "avs" => Some(Self::AviSynth),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `path` is a VapourSynth or AviSynth script.
+pub fn is_script(path: &Path) -> bool {
+    ScriptKind::of(path).is_some()
+}
+
+/// Script output video info.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Info {
+    pub width: u32,
+    pub height: u32,
+    pub frames: u64,
+    pub fps: f64,
+    pub pix_fmt: Option<String>,
+    /// Raw frame size in bytes.
+    pub frame_bytes: u64,
+}
+
+impl Info {
+    /// Raw size of all rendered frames.
+    pub fn size(&self) -> u64 {
+        self.frames * self.frame_bytes
+    }
+
+    fn to_probe(&self) -> Ffprobe {
+        Ffprobe {
+            duration: Ok(Duration::from_secs_f64(self.frames as f64 / self.fps)),
+            has_audio: false,
+            max_audio_channels: None,
+            fps: Ok(self.fps),
+            resolution: Some((self.width, self.height)),
+            is_image: false,
+            animated: false,
+            vfr: false,
+            pix_fmt: self.pix_fmt.clone(),
+            field_order: None,
+            streams: vec![Stream {
+                kind: StreamKind::Video,
+                codec: Some("rawvideo".into()),
+                ..<_>::default()
+            }],
+            video_stream: 0,
+            chapters: 0,
+        }
+    }
+
+    /// Parse `vspipe --info` output.
+    fn parse_vspipe(out: &str) -> anyhow::Result<Self> {
+        let value = |label: &str| {
+            out.lines()
+                .find_map(|l| l.strip_prefix(label)?.strip_prefix(':'))
+                .map(str::trim)
+                .with_context(|| format!("no {label}"))
+        };
+        let fps = value("FPS")?.split_whitespace().next().unwrap_or_default();
+        let fps = parse_fraction(fps).context("variable frame rate is not supported")?;
+        let bits: u32 = value("Bits").and_then(|b| Ok(b.parse()?)).unwrap_or(8);
+        let sub_w: u32 = value("SubSampling W")
+            .and_then(|s| Ok(s.parse()?))
+            .unwrap_or(0);
+        let sub_h: u32 = value("SubSampling H")
+            .and_then(|s| Ok(s.parse()?))
+            .unwrap_or(0);
+        let width = value("Width")?.parse()?;
+        let height = value("Height")?.parse()?;
+        let chroma = match value("Color Family") {
+            Ok("Gray") => 0,
+            _ => 2 * (width as u64 >> sub_w) * (height as u64 >> sub_h),
+        };
+        Ok(Self {
+            width,
+            height,
+            frames: value("Frames")?.parse()?,
+            fps,
+            pix_fmt: value("Format Name").ok().and_then(vs_pix_fmt),
+            frame_bytes: (width as u64 * height as u64 + chroma) * bits.div_ceil(8) as u64,
+        })
+    }
+
+    /// Parse `avs2yuv` output, e.g. `in.avs: 1920x1080, 24000/1001 fps, 34046 frames`.
+    fn parse_avs2yuv(out: &str) -> anyhow::Result<Self> {
+        let line = out
+            .lines()
+            .find(|l| l.contains(" fps") && l.contains(" frames"))
+            .context("no avs2yuv info")?;
+        let parts: Vec<_> = line
+            .rsplit_once(": ")
+            .map_or(line, |(_, info)| info)
+            .split(", ")
+            .map(str::trim)
+            .collect();
+        let (width, height) = parts
+            .iter()
+            .find_map(|p| {
+                let (w, h) = p.split_once('x')?;
+                Some((w.parse().ok()?, h.parse().ok()?))
+            })
+            .context("no resolution")?;
+        let fps = parts
+            .iter()
+            .find_map(|p| parse_fraction(p.strip_suffix(" fps")?))
+            .context("no fps")?;
+        let frames = parts
+            .iter()
+            .find_map(|p| p.strip_suffix(" frames")?.parse().ok())
+            .context("no frames")?;
+        Ok(Self {
+            width,
+            height,
+            frames,
+            fps,
+            pix_fmt: None,
+            // assume 8-bit 4:2:0
+            frame_bytes: width as u64 * height as u64 * 3 / 2,
+        })
+    }
+}
+
+/// Parse "24000/1001" or "24".
+fn parse_fraction(s: &str) -> Option<f64> {
+    let fps = match s.split_once('/') {
+        Some((n, d)) => n.parse::<f64>().ok()? / d.parse::<f64>().ok()?,
+        None => s.parse().ok()?,
+    };
+    (fps.is_normal() && fps > 0.0).then_some(fps)
+}
+
+/// Convert a VapourSynth format name to the ffmpeg pixel format, e.g. YUV420P10 -> yuv420p10le.
+fn vs_pix_fmt(name: &str) -> Option<String> {
+    let name = name.to_ascii_lowercase();
+    Some(match name.strip_suffix('8') {
+        Some(pf) if pf.ends_with('p') || pf == "gray" => pf.into(),
+        Some(_) => return None,
+        None if (name.starts_with("yuv") || name.starts_with("gray"))
+            && name.ends_with(|c: char| c.is_ascii_digit()) =>
+        {
+            format!("{name}le")
+        }
+        None => return None,
+    })
+}
+
+/// Returns script output info, cached per script.
+pub fn info(script: &Path) -> anyhow::Result<Info> {
+    static INFO: LazyLock<Mutex<HashMap<PathBuf, Info>>> = LazyLock::new(<_>::default);
+
+    if let Some(info) = INFO.lock().unwrap().get(script) {
+        return Ok(info.clone());
+    }
+    let info = read_info(script)?;
+    INFO.lock().unwrap().insert(script.into(), info.clone());
+    Ok(info)
+}
+
+fn read_info(script: &Path) -> anyhow::Result<Info> {
+    let kind = ScriptKind::of(script).context("not a script")?;
+    let mut cmd = std::process::Command::new(match kind {
+        ScriptKind::VapourSynth => "vspipe",
+        ScriptKind::AviSynth => "avs2yuv",
+    });
+    match kind {
+        ScriptKind::VapourSynth => cmd.arg("--info").arg(script).arg("-"),
+        ScriptKind::AviSynth => cmd.args(["-frames", "1"]).arg(script).args(["-o", "-"]),
+    };
+    let out = cmd
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("{:?} info", cmd.get_program()))?;
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    match kind {
+        ScriptKind::VapourSynth => {
+            ensure!(
+                out.status.success(),
+                "vspipe exit code {:?}\n---stderr---\n{}\n------------",
+                out.status.code(),
+                stderr.trim()
+            );
+            Info::parse_vspipe(&stdout)
+        }
+        ScriptKind::AviSynth => Info::parse_avs2yuv(&stderr)
+            .with_context(|| format!("---stderr---\n{}\n------------", stderr.trim())),
+    }
+}
+
+/// Convert to a probe, ffprobe being replaced by script introspection.
+pub fn probe(script: &Path) -> anyhow::Result<Ffprobe> {
+    Ok(info(script)?.to_probe())
+}
+
+/// File size, or for scripts the raw size of the rendered video as scripts don't
+/// contain compressed video. Image sequences are the total size of all frames.
+pub async fn input_size(input: &Path) -> anyhow::Result<u64> {
+    if is_script(input) {
+        return Ok(info(input)?.size());
+    }
+    if crate::sequence::is_sequence(input) {
+        return crate::sequence::find(input)?.size();
+    }
+    Ok(tokio::fs::metadata(input).await?.len())
+}
+
+/// Spawn a script renderer, from `start` if set, returning its y4m output.
+///
+/// The renderer exits on its own once the reader closes the pipe.
+pub fn spawn_y4m(script: &Path, start: Option<Duration>) -> anyhow::Result<Stdio> {
+    let kind = ScriptKind::of(script).context("not a script")?;
+    let start_frame = match start {
+        Some(start) if !start.is_zero() => {
+            Some((start.as_secs_f64() * info(script)?.fps).round() as u64)
+        }
+        _ => None,
+    };
+
+    let mut cmd = match kind {
+        ScriptKind::VapourSynth => {
+            let mut cmd = Command::new("vspipe");
+            cmd.args(["-c", "y4m"]);
+            if let Some(frame) = start_frame {
+                cmd.args(["--start", &frame.to_string()]);
+            }
+            cmd.arg(script).arg("-");
+            cmd
+        }
+        ScriptKind::AviSynth => {
+            let mut cmd = Command::new("avs2yuv");
+            if let Some(frame) = start_frame {
+                cmd.args(["-seek", &frame.to_string()]);
+            }
+            cmd.arg(script).args(["-o", "-"]);
+            cmd
+        }
+    };
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    debug!("cmd `{}`", crate::process::CommandExt::to_cmd_str(&cmd));
+
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("{:?}", cmd.as_std().get_program()))?;
+    Ok(child.stdout.take().context("script stdout")?.try_into()?)
+}
+
+/// Write a script of `frames` of `script` from `start_frame`, trimmed inside the script.
+///
+/// The name includes a hash of the script so changes to it produce a new sample.
+pub fn trim(
+    script: &Path,
+    start_frame: u64,
+    frames: u32,
+    temp_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let kind = ScriptKind::of(script).context("not a script")?;
+    let content =
+        std::fs::read(script).with_context(|| format!("failed to read {}", script.display()))?;
+    let hash = blake3::hash(&content).to_hex();
+    let ext = script.extension().and_then(|e| e.to_str()).unwrap_or("vpy");
+    let name = script
+        .with_extension(format!(
+            "sample{start_frame}+{frames}f.{}.{ext}",
+            &hash[..8]
+        ))
+        .file_name()
+        .context("no script file name")?
+        .to_owned();
+    let dest = temp_dir.join(name);
+    if !dest.exists() {
+        let script = std::path::absolute(script)?;
+        std::fs::write(&dest, trim_script(kind, &script, start_frame, frames))?;
+    }
+    Ok(dest)
+}
+
+fn trim_script(kind: ScriptKind, script: &Path, start_frame: u64, frames: u32) -> String {
+    let end = start_frame + u64::from(frames);
+    match kind {
+        ScriptKind::VapourSynth => format!(
+            "import runpy\n\
+             import vapoursynth as vs\n\
+             runpy.run_path({:?}, run_name=\"__vapoursynth__\")\n\
+             clip = vs.get_output(0)\n\
+             clip = getattr(clip, \"clip\", clip)\n\
+             vs.clear_outputs()\n\
+             clip[{start_frame}:{end}].set_output(0)\n",
+            script.to_string_lossy(),
+        ),
+        ScriptKind::AviSynth => format!(
+            "Import(\"\"\"{}\"\"\")\nTrim({start_frame}, -{frames})\n",
+            script.display()
+        ),
+    }
+}
+
+#[test]
+fn parse_vspipe_info() {
+    let out = "Width: 1920\n\
+        Height: 1080\n\
+        Frames: 240\n\
+        FPS: 24000/1001 (23.976 fps)\n\
+        Format Name: YUV420P10\n\
+        Color Family: YUV\n\
+        Alpha: No\n\
+        Sample Type: Integer\n\
+        Bits: 10\n\
+        SubSampling W: 1\n\
+        SubSampling H: 1\n";
+    let info = Info::parse_vspipe(out).unwrap();
+    assert_eq!(info.width, 1920);
+    assert_eq!(info.height, 1080);
+    assert_eq!(info.frames, 240);
+    assert!((info.fps - 23.976).abs() < 0.001, "{}", info.fps);
+    assert_eq!(info.pix_fmt.as_deref(), Some("yuv420p10le"));
+    assert_eq!(info.frame_bytes, 1920 * 1080 * 3);
+
+    let vfr = out.replace("FPS: 24000/1001 (23.976 fps)", "FPS: 0/0 (0.000 fps)");
+    assert!(Info::parse_vspipe(&vfr).is_err());
+}
+
+#[test]
+fn parse_avs2yuv_info() {
+    let info = Info::parse_avs2yuv("in.avs: 1280x720, 24000/1001 fps, 500 frames\n").unwrap();
+    assert_eq!((info.width, info.height, info.frames), (1280, 720, 500));
+    assert!((info.fps - 23.976).abs() < 0.001, "{}", info.fps);
+}
+
+#[test]
+fn vapoursynth_pix_fmt() {
+    assert_eq!(vs_pix_fmt("YUV420P8").as_deref(), Some("yuv420p"));
+    assert_eq!(vs_pix_fmt("YUV444P12").as_deref(), Some("yuv444p12le"));
+    assert_eq!(vs_pix_fmt("Gray8").as_deref(), Some("gray"));
+    assert_eq!(vs_pix_fmt("RGB24"), None);
+}
+
+#[test]
+fn trim_scripts() {
+    assert_eq!(
+        trim_script(ScriptKind::AviSynth, Path::new("/v/a.avs"), 480, 120),
+        "Import(\"\"\"/v/a.avs\"\"\")\nTrim(480, -120)\n"
+    );
+    let vpy = trim_script(ScriptKind::VapourSynth, Path::new("/v/a.vpy"), 480, 120);
+    assert!(vpy.contains("runpy.run_path(\"/v/a.vpy\""), "{vpy}");
+    assert!(vpy.ends_with("clip[480:600].set_output(0)\n"), "{vpy}");
+}
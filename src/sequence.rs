@@ -0,0 +1,400 @@
+//! Image sequence input support, e.g. `frame_%05d.png` or a directory of EXR/TIFF frames.
+//!
+//! Sequences are read by the ffmpeg image2 demuxer at the frame rate set with `--fps`.
+use crate::{
+    ffprobe::{self, Ffprobe, StreamKind},
+    temporary::{self, TempKind},
+};
+use anyhow::{Context, ensure};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+/// Image file extensions considered when a directory is used as an input.
+const FRAME_EXTENSIONS: &[&str] = &[
+    "bmp", "dpx", "exr", "jpeg", "jpg", "jxl", "png", "tga", "tif", "tiff", "webp",
+];
+
+/// Frame rate of image sequence inputs, i.e. `--fps`, which is required.
+pub fn fps(fps: Option<f64>) -> anyhow::Result<f64> {
+    fps.context("image sequence inputs require --fps")
+}
+
+/// Whether `path` is an image sequence, i.e. a directory or a `%d` numbered file pattern.
+pub fn is_sequence(path: &Path) -> bool {
+    path.is_dir()
+        || path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| split_pattern(n).is_some())
+}
+
+/// Numbered image frames, the first contiguous run matching a pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequence {
+    dir: PathBuf,
+    prefix: String,
+    /// Zero padded number width, e.g. 5 for `%05d`.
+    width: Option<usize>,
+    suffix: String,
+    pub start_number: u64,
+    pub frames: u64,
+}
+
+impl Sequence {
+    /// The ffmpeg image2 input pattern, e.g. `dir/frame_%05d.png`.
+    pub fn pattern(&self) -> PathBuf {
+        let number = match self.width {
+            Some(w) => format!("%0{w}d"),
+            None => "%d".into(),
+        };
+        self.dir
+            .join(format!("{}{number}{}", self.prefix, self.suffix))
+    }
+
+    /// Path of the frame at `index`, counting from the start of the sequence.
+    pub fn frame(&self, index: u64) -> PathBuf {
+        let n = self.start_number + index;
+        let width = self.width.unwrap_or_default();
+        self.dir
+            .join(format!("{}{n:0width$}{}", self.prefix, self.suffix))
+    }
+
+    /// Total size of all frame files.
+    pub fn size(&self) -> anyhow::Result<u64> {
+        (0..self.frames).try_fold(0, |size, idx| {
+            let frame = self.frame(idx);
+            let meta = std::fs::metadata(&frame)
+                .with_context(|| format!("failed to read {}", frame.display()))?;
+            Ok(size + meta.len())
+        })
+    }
+}
+
+/// Returns the sequence of a directory or file pattern input, cached per input.
+pub fn find(input: &Path) -> anyhow::Result<Sequence> {
+    static SEQUENCES: LazyLock<Mutex<HashMap<PathBuf, Sequence>>> = LazyLock::new(<_>::default);
+
+    if let Some(seq) = SEQUENCES.lock().unwrap().get(input) {
+        return Ok(seq.clone());
+    }
+    let seq = read_sequence(input)?;
+    SEQUENCES.lock().unwrap().insert(input.into(), seq.clone());
+    Ok(seq)
+}
+
+fn read_sequence(input: &Path) -> anyhow::Result<Sequence> {
+    let (dir, pattern) = match input.is_dir() {
+        true => (input, None),
+        false => {
+            let name = input.file_name().and_then(|n| n.to_str());
+            let dir = input.parent().filter(|p| !p.as_os_str().is_empty());
+            (dir.unwrap_or(Path::new(".")), name)
+        }
+    };
+    let names: Vec<_> = std::fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .collect();
+
+    let pattern = match pattern {
+        Some(p) => p.to_owned(),
+        None => dir_pattern(&names)
+            .with_context(|| format!("no numbered image frames in {}", dir.display()))?,
+    };
+    let (prefix, width, suffix) = split_pattern(&pattern).context("invalid frame pattern")?;
+    let numbers = names
+        .iter()
+        .filter_map(|n| frame_number(n, prefix, width, suffix))
+        .collect();
+    let (start_number, frames) =
+        contiguous(numbers).with_context(|| format!("no frames matching {}", input.display()))?;
+
+    Ok(Sequence {
+        dir: dir.into(),
+        prefix: prefix.into(),
+        width,
+        suffix: suffix.into(),
+        start_number,
+        frames,
+    })
+}
+
+/// Split a `%d` or `%0Nd` file pattern into prefix, number width & suffix.
+fn split_pattern(name: &str) -> Option<(&str, Option<usize>, &str)> {
+    let (prefix, rest) = name.split_once('%')?;
+    let (spec, suffix) = rest.split_once('d')?;
+    let width = match spec {
+        "" => None,
+        _ => Some(spec.strip_prefix('0')?.parse().ok()?),
+    };
+    Some((prefix, width, suffix))
+}
+
+/// Returns the frame number of file `name` if it matches the pattern.
+fn frame_number(name: &str, prefix: &str, width: Option<usize>, suffix: &str) -> Option<u64> {
+    let digits = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    let padded_ok = match width {
+        Some(w) => digits.len() == w || (digits.len() > w && !digits.starts_with('0')),
+        None => digits.len() == 1 || !digits.starts_with('0'),
+    };
+    if !padded_ok || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Infer a frame pattern from the image file names of a directory, using the last number
+/// in each name & choosing the pattern matching the most files.
+fn dir_pattern(names: &[String]) -> Option<String> {
+    let mut groups = BTreeMap::<_, Vec<&str>>::new();
+    for name in names {
+        let Some((_, ext)) = name.rsplit_once('.') else {
+            continue;
+        };
+        if !FRAME_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()) {
+            continue;
+        }
+        let stem_len = name.len() - ext.len() - 1;
+        let Some(end) = name[..stem_len].rfind(|c: char| c.is_ascii_digit()) else {
+            continue;
+        };
+        let start = name[..end]
+            .rfind(|c: char| !c.is_ascii_digit())
+            .map_or(0, |i| i + 1);
+        groups
+            .entry((&name[..start], &name[end + 1..]))
+            .or_default()
+            .push(&name[start..=end]);
+    }
+
+    let ((prefix, suffix), digits) = groups
+        .into_iter()
+        .max_by(|(ka, a), (kb, b)| a.len().cmp(&b.len()).then(kb.cmp(ka)))?;
+    let len = digits[0].len();
+    Some(match digits.iter().all(|d| d.len() == len) {
+        true => format!("{prefix}%0{len}d{suffix}"),
+        false => format!("{prefix}%d{suffix}"),
+    })
+}
+
+/// Returns the start & length of the first contiguous run of frame numbers,
+/// as the image2 demuxer stops at the first missing frame.
+fn contiguous(mut numbers: Vec<u64>) -> Option<(u64, u64)> {
+    numbers.sort_unstable();
+    numbers.dedup();
+    let start = *numbers.first()?;
+    let frames = numbers
+        .iter()
+        .zip(start..)
+        .take_while(|(n, expected)| *n == expected)
+        .count();
+    Some((start, frames as _))
+}
+
+/// A file-like path to base output names on, e.g. `frames/f_%05d.png` -> `frames/f.png`
+/// or `shots/a` -> `shots/a.frames`, so the extension may be replaced.
+pub fn output_base(input: &Path) -> PathBuf {
+    let name = input
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    match split_pattern(name) {
+        Some((prefix, _, suffix)) if !input.is_dir() => {
+            let prefix = prefix.trim_end_matches(['_', '-', '.', ' ']);
+            let prefix = match prefix {
+                "" => input
+                    .parent()
+                    .and_then(|p| p.file_name())
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("frames"),
+                prefix => prefix,
+            };
+            input.with_file_name(format!("{prefix}{suffix}"))
+        }
+        _ => input.with_file_name(format!("{name}.frames")),
+    }
+}
+
+/// Probe a sequence using the first frame, with duration from the frame count & `--fps`.
+pub fn probe(input: &Path, fps: Option<f64>) -> anyhow::Result<Ffprobe> {
+    let seq = find(input)?;
+    let fps = self::fps(fps)?;
+    let first = ffprobe::probe(&seq.frame(0), None);
+    ensure!(
+        first.resolution.is_some(),
+        "failed to probe {}",
+        seq.frame(0).display()
+    );
+    Ok(Ffprobe {
+        duration: Ok(Duration::from_secs_f64(seq.frames as f64 / fps)),
+        has_audio: false,
+        max_audio_channels: None,
+        fps: Ok(fps),
+        is_image: false,
+        animated: false,
+        vfr: false,
+        streams: first
+            .streams
+            .into_iter()
+            .filter(|s| s.kind == StreamKind::Video)
+            .take(1)
+            .collect(),
+        video_stream: 0,
+        chapters: 0,
+        ..first
+    })
+}
+
+/// Image2 demuxer input args at `--fps` reading from `start`, converted to a frame offset.
+pub fn input_args(
+    input: &Path,
+    fps: Option<f64>,
+    start: Option<Duration>,
+) -> anyhow::Result<[String; 8]> {
+    let seq = find(input)?;
+    let fps = self::fps(fps)?;
+    let offset = start.map_or(0, |s| (s.as_secs_f64() * fps).round() as u64);
+    Ok([
+        "-f".into(),
+        "image2".into(),
+        "-framerate".into(),
+        fps.to_string(),
+        "-start_number".into(),
+        (seq.start_number + offset).to_string(),
+        "-i".into(),
+        seq.pattern().to_string_lossy().into(),
+    ])
+}
+
+/// Create a sequence directory of `frames` of `input` from `start_frame`, hard linking
+/// (or if not possible copying) the frame files.
+pub fn trim(
+    input: &Path,
+    start_frame: u64,
+    frames: u32,
+    temp_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let seq = find(input)?;
+    ensure!(start_frame < seq.frames, "sample start beyond sequence end");
+    let frames = u64::from(frames).min(seq.frames - start_frame);
+
+    let name = match seq.prefix.as_str() {
+        "" => seq
+            .dir
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("frames"),
+        prefix => prefix,
+    };
+    let dest = temp_dir.join(format!("{name}.sample{start_frame}+{frames}f.frames"));
+    temporary::add(&dest, TempKind::Keepable);
+    std::fs::create_dir_all(&dest)?;
+
+    for idx in 0..frames {
+        let frame = seq.frame(start_frame + idx);
+        let link = dest.join(format!("{idx:06}{}", seq.suffix));
+        temporary::add(&link, TempKind::Keepable);
+        if link.exists() {
+            continue;
+        }
+        if std::fs::hard_link(&frame, &link).is_err() {
+            std::fs::copy(&frame, &link)
+                .with_context(|| format!("failed to copy {}", frame.display()))?;
+        }
+    }
+    Ok(dest)
+}
+
+#[test]
+fn frame_patterns() {
+    assert_eq!(
+        split_pattern("frame_%05d.png"),
+        Some(("frame_", Some(5), ".png"))
+    );
+    assert_eq!(split_pattern("%d.exr"), Some(("", None, ".exr")));
+    assert_eq!(split_pattern("frame_%5d.png"), None);
+    assert_eq!(split_pattern("frame.png"), None);
+
+    assert_eq!(
+        frame_number("frame_00042.png", "frame_", Some(5), ".png"),
+        Some(42)
+    );
+    assert_eq!(
+        frame_number("frame_123456.png", "frame_", Some(5), ".png"),
+        Some(123456)
+    );
+    assert_eq!(
+        frame_number("frame_0042.png", "frame_", Some(5), ".png"),
+        None
+    );
+    assert_eq!(
+        frame_number("frame_00042.jpg", "frame_", Some(5), ".png"),
+        None
+    );
+    assert_eq!(frame_number("7.exr", "", None, ".exr"), Some(7));
+    assert_eq!(frame_number("07.exr", "", None, ".exr"), None);
+}
+
+#[test]
+fn infer_dir_pattern() {
+    let names: Vec<String> = [
+        "shot2_0001.exr",
+        "shot2_0002.exr",
+        "shot2_0003.exr",
+        "notes.txt",
+    ]
+    .map(Into::into)
+    .into();
+    assert_eq!(dir_pattern(&names).as_deref(), Some("shot2_%04d.exr"));
+
+    let names: Vec<String> = ["9.tif", "10.tif", "11.tif", "thumb.png"]
+        .map(Into::into)
+        .into();
+    assert_eq!(dir_pattern(&names).as_deref(), Some("%d.tif"));
+
+    assert_eq!(dir_pattern(&["readme.md".into()]), None);
+}
+
+#[test]
+fn sequence_output_base() {
+    assert_eq!(
+        output_base(Path::new("/v/frames/f_%05d.png")),
+        Path::new("/v/frames/f.png")
+    );
+    assert_eq!(
+        output_base(Path::new("/v/frames/%04d.exr")),
+        Path::new("/v/frames/frames.exr")
+    );
+}
+
+#[test]
+fn contiguous_frames() {
+    assert_eq!(contiguous(vec![3, 1, 2, 5]), Some((1, 3)));
+    assert_eq!(contiguous(vec![10]), Some((10, 1)));
+    assert_eq!(contiguous(vec![]), None);
+}
+
+#[test]
+fn sequence_paths() {
+    let seq = Sequence {
+        dir: "/v".into(),
+        prefix: "f_".into(),
+        width: Some(4),
+        suffix: ".png".into(),
+        start_number: 1,
+        frames: 10,
+    };
+    assert_eq!(seq.pattern(), Path::new("/v/f_%04d.png"));
+    assert_eq!(seq.frame(0), Path::new("/v/f_0001.png"));
+    assert_eq!(seq.frame(9), Path::new("/v/f_0010.png"));
+}
+
+#[test]
+fn fps_required() {
+    assert_eq!(fps(Some(24.0)).unwrap(), 24.0);
+    assert!(fps(None).is_err());
+}
@@ -1,13 +1,18 @@
 //! temp file logic
 use anyhow::Context;
 use std::{
+    cmp::Reverse,
     collections::HashMap,
     env, iter,
     path::{Path, PathBuf},
-    sync::{LazyLock, Mutex},
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
 static TEMPS: LazyLock<Mutex<HashMap<PathBuf, TempKind>>> = LazyLock::new(<_>::default);
+static DEFER_EARLY_CLEAN: AtomicBool = AtomicBool::new(false);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TempKind {
@@ -37,12 +42,27 @@ pub async fn clean(keep_keepables: bool) {
     }
 }
 
+/// Delete non-keepable temporary files during a run, e.g. after each sample,
+/// unless deferred with [`defer_early_clean`].
+pub async fn clean_early() {
+    if !DEFER_EARLY_CLEAN.load(Ordering::Relaxed) {
+        clean_non_keepables().await;
+    }
+}
+
+/// Defer [`clean_early`] to the final clean. Used when running concurrently, as one
+/// run could otherwise delete the in progress files of another.
+pub fn defer_early_clean() {
+    DEFER_EARLY_CLEAN.store(true, Ordering::Relaxed);
+}
+
 /// Delete all added temporary files.
 pub async fn clean_all() {
     let mut files: Vec<_> = std::mem::take(&mut *TEMPS.lock().unwrap())
         .into_keys()
         .collect();
-    files.sort_by_key(|f| f.is_dir()); // rm dir at the end
+    // rm dirs at the end, deepest first
+    files.sort_by_key(|f| (f.is_dir(), Reverse(f.components().count())));
 
     for file in files {
         match file.is_dir() {
@@ -60,7 +80,8 @@ async fn clean_non_keepables() {
         .filter(|(_, k)| **k == TempKind::NotKeepable)
         .map(|(f, _)| f.clone())
         .collect();
-    matching.sort_by_key(|f| f.is_dir()); // rm dir at the end
+    // rm dirs at the end, deepest first
+    matching.sort_by_key(|f| (f.is_dir(), Reverse(f.components().count())));
 
     for file in matching {
         match file.is_dir() {
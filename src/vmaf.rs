@@ -2,17 +2,23 @@
 use crate::process::{Chunks, CommandExt, FfmpegOut, cmd_err, exit_ok_stderr};
 use anyhow::Context;
 use log::{debug, info};
-use std::{path::Path, process::Stdio};
+use std::{path::Path, process::Stdio, time::Duration};
 use tokio::process::Command;
 use tokio_process_stream::{Item, ProcessChunkStream};
 use tokio_stream::{Stream, StreamExt};
 
 /// Calculate VMAF score using ffmpeg.
+///
+/// `range` limits scoring to a start & duration of both inputs, using accurate decode seeking.
+/// Image sequence references are read at `ref_fps`, i.e. `--fps`.
 pub fn run(
     reference: &Path,
+    ref_fps: Option<f64>,
     distorted: &Path,
     filter_complex: &str,
     fps: Option<f32>,
+    ignore_film_grain: bool,
+    range: Option<(Duration, Duration)>,
 ) -> anyhow::Result<impl Stream<Item = VmafOut> + use<>> {
     info!(
         "vmaf {} vs reference {}",
@@ -22,10 +28,16 @@ pub fn run(
 
     let mut cmd = Command::new("ffmpeg");
     cmd.kill_on_drop(true)
+        .stdin(Stdio::null())
         .arg2_opt("-r", fps)
+        // decode without synthesized film grain
+        .arg2_if(ignore_film_grain, "-export_side_data", "film_grain")
+        .arg2_opt("-ss", range.map(|(start, _)| start.as_secs_f32()))
+        .arg2_opt("-t", range.map(|(_, duration)| duration.as_secs_f32()))
         .arg2("-i", distorted)
         .arg2_opt("-r", fps)
-        .arg2("-i", reference)
+        .arg2_opt("-t", range.map(|(_, duration)| duration.as_secs_f32()))
+        .arg_input(reference, ref_fps, range.map(|(start, _)| start))?
         .arg2("-filter_complex", filter_complex)
         // Workaround unused streams causing ffmpeg memory leaks
         // See https://github.com/alexheretic/ab-av1/issues/189
@@ -33,8 +45,7 @@ pub fn run(
         .arg("-sn")
         .arg("-dn")
         .arg2("-f", "null")
-        .arg("-")
-        .stdin(Stdio::null());
+        .arg("-");
 
     let cmd_str = cmd.to_cmd_str();
     debug!("cmd `{cmd_str}`");
@@ -2,18 +2,24 @@
 use crate::process::{Chunks, CommandExt, FfmpegOut, cmd_err, exit_ok_stderr};
 use anyhow::Context;
 use log::{debug, info};
-use std::{path::Path, process::Stdio};
+use std::{path::Path, process::Stdio, time::Duration};
 use tokio::process::Command;
 use tokio_process_stream::{Item, ProcessChunkStream};
 use tokio_stream::{Stream, StreamExt};
 
 /// Calculate XPSNR score using ffmpeg.
+///
+/// `range` limits scoring to a start & duration of both inputs, using accurate decode seeking.
+/// Image sequence references are read at `ref_fps`, i.e. `--fps`.
 // TODO: fix progress update to account for fps
 pub fn run(
     reference: &Path,
+    ref_fps: Option<f64>,
     distorted: &Path,
     filter_complex: &str,
     fps: Option<f32>,
+    ignore_film_grain: bool,
+    range: Option<(Duration, Duration)>,
 ) -> anyhow::Result<impl Stream<Item = XpsnrOut> + use<>> {
     info!(
         "xpsnr {} vs reference {}",
@@ -23,14 +29,19 @@ pub fn run(
 
     let mut cmd = Command::new("ffmpeg");
     cmd.kill_on_drop(true)
+        .stdin(Stdio::null())
         .arg2_opt("-r", fps)
-        .arg2("-i", reference)
+        .arg2_opt("-t", range.map(|(_, duration)| duration.as_secs_f32()))
+        .arg_input(reference, ref_fps, range.map(|(start, _)| start))?
         .arg2_opt("-r", fps)
+        // decode without synthesized film grain
+        .arg2_if(ignore_film_grain, "-export_side_data", "film_grain")
+        .arg2_opt("-ss", range.map(|(start, _)| start.as_secs_f32()))
+        .arg2_opt("-t", range.map(|(_, duration)| duration.as_secs_f32()))
         .arg2("-i", distorted)
         .arg2("-filter_complex", filter_complex)
         .arg2("-f", "null")
-        .arg("-")
-        .stdin(Stdio::null());
+        .arg("-");
 
     let cmd_str = cmd.to_cmd_str();
     debug!("cmd `{cmd_str}`");
@@ -72,15 +83,30 @@ pub fn run(
 #[derive(Debug)]
 pub enum XpsnrOut {
     Progress(FfmpegOut),
-    Done(f32),
+    Done(XpsnrScore),
     Err(anyhow::Error),
 }
 
+/// XPSNR average scores.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XpsnrScore {
+    /// Minimum plane score, used as the overall score.
+    pub min: f32,
+    /// Per plane scores, e.g. `[('y', 33.6547), ('u', 41.8741), ('v', 42.2571)]`.
+    pub planes: Vec<(char, f32)>,
+}
+
 impl XpsnrOut {
     fn try_from_chunk(chunk: &[u8], chunks: &mut Chunks) -> Option<Self> {
         chunks.push(chunk);
 
-        if let Some(score) = chunks.rfind_line_map(score_from_line) {
+        if let Some(score) = chunks.rfind_line_map(|line| {
+            let min = score_from_line(line)?;
+            Some(XpsnrScore {
+                min,
+                planes: planes_from_line(line),
+            })
+        }) {
             return Some(Self::Done(score));
         }
         if let Some(progress) = FfmpegOut::try_parse(chunks.last_line()) {
@@ -112,16 +138,35 @@ fn score_from_line(line: &str) -> Option<f32> {
     tail[..=end_idx].parse().ok()
 }
 
+/// Parse plane scores, e.g. "y: 33.6547  u: 41.8741  v: 42.2571" -> y, u & v scores.
+pub fn planes_from_line(line: &str) -> Vec<(char, f32)> {
+    let mut tokens = line.split_whitespace().peekable();
+    let mut planes = vec![];
+    while let Some(token) = tokens.next() {
+        let mut chars = token.chars();
+        if let (Some(plane), Some(':'), None) = (chars.next(), chars.next(), chars.next())
+            && plane.is_ascii_alphabetic()
+            && let Some(score) = tokens.next_if(|t| t.parse::<f32>().is_ok())
+        {
+            planes.push((plane, score.parse().unwrap()));
+        }
+    }
+    planes
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn parse_rgb_line() {
-        let score = score_from_line(
-            "XPSNR average, 1 frames  r: 40.6130  g: 41.0275  b: 40.6961  (minimum: 40.6130)",
+        const LINE: &str =
+            "XPSNR average, 1 frames  r: 40.6130  g: 41.0275  b: 40.6961  (minimum: 40.6130)";
+        assert_eq!(score_from_line(LINE), Some(40.6130));
+        assert_eq!(
+            planes_from_line(LINE),
+            [('r', 40.6130), ('g', 41.0275), ('b', 40.6961)]
         );
-        assert_eq!(score, Some(40.6130));
     }
 
     #[test]
@@ -229,7 +274,7 @@ XPSNR average, 1344 frames  y: 40.7139
             if let Some(xpsnr) = XpsnrOut::try_from_chunk(chunk, &mut chunks) {
                 println!("{xpsnr:?}");
                 if let XpsnrOut::Done(score) = xpsnr {
-                    xpsnr_score = Some(score);
+                    xpsnr_score = Some(score.min);
                 }
             }
 
@@ -298,7 +343,7 @@ XPSNR average, 1344 frames  y: 40.7139
             if let Some(xpsnr) = XpsnrOut::try_from_chunk(chunk, &mut chunks) {
                 println!("{xpsnr:?}");
                 if let XpsnrOut::Done(score) = xpsnr {
-                    xpsnr_score = Some(score);
+                    xpsnr_score = Some(score.min);
                 }
             }
 